@@ -6,38 +6,108 @@
 mod router;
 mod routing;
 mod server;
+mod session;
 mod tcp;
+mod time;
 
 // but it's much easier to use if they're not in different modules
 pub use self::router::*;
 pub use self::routing::*;
 pub use self::server::*;
+pub use self::session::*;
 pub use self::tcp::*;
+pub use self::time::*;
 
 use csv::ReaderBuilder;
-use rustasim::spsc::Producer;
-use rustasim::{start, Advancer, Event, EventType};
-use serde::Deserialize;
-use std::collections::HashMap;
+use rustasim::net::{Cluster, Launch};
+use rustasim::spsc;
+use rustasim::spsc::{Consumer, Producer};
+pub use rustasim::Scheduler;
+use rustasim::{
+    checkpoint, restore, start_partitioned, start_sequential, start_watched, start_windowed,
+    start_with, ActorPanic, Advancer, CheckpointActor, Event, EventType, Partition, Watchdog,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::time::Instant;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Size for the internal event queue
 const Q_SIZE: usize = 1 << 14;
 
-/// Convenience alias for time type
-pub type Time = u64;
+/// Size of the segments of segmented event queues
+const SEGMENT_LEN: usize = 1 << 8;
 
 /// Convenience alias for the simulation result
 pub type ActorResult = u64;
 
+/// What the devices returned once done, by id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Results {
+    /// What each server returned
+    pub servers: BTreeMap<usize, ActorResult>,
+
+    /// What each switch returned
+    pub routers: BTreeMap<usize, ActorResult>,
+}
+
+impl Results {
+    /// Sum of what all the devices returned
+    pub fn total(&self) -> ActorResult {
+        self.servers.values().chain(self.routers.values()).sum()
+    }
+
+    /// Number of devices
+    pub fn n_devices(&self) -> usize {
+        self.servers.len() + self.routers.len()
+    }
+}
+
+/// Ids of the devices in the order they are handed to the engine, servers first
+#[derive(Debug, Clone, Default)]
+struct DeviceIds {
+    ids: Vec<usize>,
+    n_servers: usize,
+}
+
+impl DeviceIds {
+    fn new(servers: &[Server], routers: &[Router]) -> DeviceIds {
+        let ids = servers.iter().map(|s| s.id);
+        DeviceIds {
+            ids: ids.chain(routers.iter().map(|r| r.id)).collect(),
+            n_servers: servers.len(),
+        }
+    }
+
+    /// Puts together the results of the devices, given along with their index
+    fn results(&self, results: impl IntoIterator<Item = (usize, ActorResult)>) -> Results {
+        let mut by_id = Results::default();
+        for (ix, result) in results {
+            let devices = if ix < self.n_servers {
+                &mut by_id.servers
+            } else {
+                &mut by_id.routers
+            };
+            devices.insert(self.ids[ix], result);
+        }
+
+        by_id
+    }
+}
+
 /// Shorthand for the event types
 pub type ModelEvent = Event<Time, NetworkEvent>;
 
 /// Simulation parameters
+///
+/// The default is a single rack for a second, with the same parameters as the command line.
 #[derive(Debug)]
 pub struct SimConfig {
-    /// Simulation end, in ns
+    /// Simulation end
     pub time_limit: Time,
 
     /// Datacenter topology type
@@ -50,9 +120,123 @@ pub struct SimConfig {
     pub bandwidth_gbps: u64,
 
     /// Server<>ToR<>* latency
-    pub latency_ns: Time,
+    pub latency: Time,
     // ToR<>* latency
     //pub tor_out_latency_ns: Time,
+    /// Seed for all the random decisions of the model
+    ///
+    /// Each actor derives its own random stream from it, two runs with the same seed produce the
+    /// same output regardless of the number of cores.
+    pub seed: u64,
+
+    /// What runs the devices
+    pub executor: Executor,
+
+    /// How the workers pick the next actor to advance
+    pub scheduler: Scheduler,
+
+    /// Keeps linked devices on the same worker, rather than dealing them out in turn
+    pub partition: bool,
+
+    /// Pins each worker to its own core
+    pub pin: bool,
+
+    /// How the devices store the events they receive
+    pub queue_kind: QueueKind,
+
+    /// Saves the simulation to the file once it reaches the time
+    pub checkpoint: Option<(Time, String)>,
+
+    /// Resumes the simulation saved to this file rather than starting over
+    ///
+    /// The rest of the configuration must be the same as when it was saved.
+    pub resume: Option<String>,
+
+    /// Traces what the engine does to this file, only with the `trace` feature
+    pub trace: Option<String>,
+
+    /// Dumps what the devices wait on to stderr whenever the run makes no progress for this long
+    pub watchdog: Option<Duration>,
+
+    /// Runs only this process's share of the simulation, along with the other processes
+    pub launch: Option<Launch>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            time_limit: Time::from_ns(1_000_000_000),
+            topology: Topology::FullyConnected(1),
+            flow_file: String::new(),
+            bandwidth_gbps: 10,
+            latency: Time::from_ns(500),
+            seed: 0,
+            executor: Executor::default(),
+            scheduler: Scheduler::default(),
+            partition: false,
+            pin: false,
+            queue_kind: QueueKind::default(),
+            checkpoint: None,
+            resume: None,
+            trace: None,
+            watchdog: None,
+            launch: None,
+        }
+    }
+}
+
+/// What runs the devices
+///
+/// By default, workers sending null-messages on one less than the number of cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Executor {
+    /// The sequential reference executor, on the calling thread
+    Sequential,
+
+    /// `n` workers sending null-messages, at least one
+    Parallel(usize),
+
+    /// `n` workers synchronized in windows of simulated time, at least one
+    Window(usize),
+}
+
+impl Executor {
+    /// Number of threads it runs the devices on
+    pub fn n_workers(self) -> usize {
+        match self {
+            Executor::Sequential => 1,
+            Executor::Parallel(n) | Executor::Window(n) => std::cmp::max(1, n),
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::Parallel(num_cpus::get() - 1)
+    }
+}
+
+/// How the events a device receives on a link are stored
+///
+/// The queues from the world are always segmented, it has all the flows to push before the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueKind {
+    /// In a queue of fixed capacity, allocated up front
+    #[default]
+    Bounded,
+
+    /// In an unbounded queue, which only takes as much memory as it holds events
+    Segmented,
+}
+
+impl QueueKind {
+    /// Creates a queue of this kind
+    fn queue(self) -> (Producer<ModelEvent>, Consumer<ModelEvent>) {
+        match self {
+            QueueKind::Bounded => spsc::new(Q_SIZE),
+            QueueKind::Segmented => spsc::segmented(SEGMENT_LEN),
+        }
+    }
 }
 
 /// Topology types
@@ -69,6 +253,7 @@ pub enum Topology {
 }
 
 /// Datacenter network model events
+#[derive(Serialize, Deserialize)]
 pub enum NetworkEvent {
     /// Flow start
     Flow(FlowDesc),
@@ -76,8 +261,8 @@ pub enum NetworkEvent {
     /// Packet arrival
     Packet(Packet),
 
-    /// Server timeout, server needs to check itself for timeouts
-    Timeout,
+    /// Retransmission timeout of a packet, by flow id and sequence number
+    Timeout(usize, usize),
 }
 
 impl std::fmt::Debug for NetworkEvent {
@@ -91,7 +276,7 @@ impl std::fmt::Debug for NetworkEvent {
                     "Packet"
                 }
             }
-            NetworkEvent::Timeout => "Timeout",
+            NetworkEvent::Timeout(..) => "Timeout",
         })
     }
 }
@@ -103,7 +288,7 @@ pub fn tx_rx_time(
     latency: Time,
     bandwidth_gbps: u64,
 ) -> (Time, Time) {
-    let tx_time = cur_time + 8 * packet_size_bytes / bandwidth_gbps;
+    let tx_time = cur_time + Time::transmission(packet_size_bytes, bandwidth_gbps);
     (tx_time, tx_time + latency)
 }
 
@@ -126,7 +311,7 @@ pub trait Connectable {
 }
 
 /// Takes care of properly building the simulation object, running it, and reporting to the user
-pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>> {
+pub fn run_config(config: SimConfig) -> Result<(), Box<dyn Error>> {
     eprintln!("Setup...");
 
     eprintln!("  Creating network... ");
@@ -134,7 +319,7 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
         Topology::CLOS(u, d) => build_clos(u, d),
         Topology::FullyConnected(k) => build_fc(k, k - 1),
     };
-    let n_links: u64 = (&net).iter().map(|(_, v)| v.len() as u64).sum();
+    let n_links: u64 = net.values().map(|v| v.len() as u64).sum();
     eprintln!(
         "    {} devices, {} hosts, {} links",
         net.len(),
@@ -142,7 +327,29 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
         n_links
     );
 
-    let mut world = World::new_from_network(net, &config, n_hosts);
+    let mut cluster = match &config.launch {
+        Some(launch) => {
+            if !matches!(config.executor, Executor::Parallel(_))
+                || config.checkpoint.is_some()
+                || config.resume.is_some()
+            {
+                return Err(
+                    "Processes can't run sequentially or in windows, nor be saved or resumed"
+                        .into(),
+                );
+            }
+            eprintln!(
+                "  Connecting to {} other processes...",
+                launch.addrs.len() - 1
+            );
+            Some(launch.connect()?)
+        }
+        None => None,
+    };
+    let mut world = match &mut cluster {
+        Some(cluster) => World::new_on(net, &config, n_hosts, cluster),
+        None => World::new_from_network(net, &config, n_hosts),
+    };
 
     // Flows
     let mut flows = Vec::new();
@@ -159,7 +366,7 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
         let src = line[0].parse::<usize>()? + 1;
         let dst = line[1].parse::<usize>()? + 1;
         let size_byte = line[2].parse::<u64>()?;
-        let time = line[3].parse::<u64>()?;
+        let time = Time::from_ns(line[3].parse::<u64>()?);
 
         if time > config.time_limit {
             break;
@@ -185,17 +392,56 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
     */
     world.add_flows(flows);
 
-    eprintln!("Running on {} cores...", n_cpus);
+    let windowed = matches!(config.executor, Executor::Window(_));
+    if windowed && (config.checkpoint.is_some() || config.resume.is_some()) {
+        return Err("Windows can't be saved or resumed".into());
+    }
+    if config.watchdog.is_some() && !matches!(config.executor, Executor::Parallel(_)) {
+        return Err(
+            "Only the workers can be watched, not windows or the sequential executor".into(),
+        );
+    }
+
+    match config.executor {
+        Executor::Sequential => eprintln!("Running sequentially..."),
+        Executor::Parallel(_) => eprintln!(
+            "Running on {} cores ({:?} scheduler)...",
+            config.executor.n_workers(),
+            config.scheduler
+        ),
+        Executor::Window(_) => eprintln!(
+            "Running on {} cores in windows...",
+            config.executor.n_workers()
+        ),
+    }
+    #[cfg(feature = "trace")]
+    if let Some(path) = &config.trace {
+        rustasim::trace::start(Path::new(path))?;
+    }
+
     let start = Instant::now();
-    let counts = world.start(n_cpus, config.time_limit);
+    let results = if let Some(cluster) = cluster {
+        world.start_on(cluster, config.time_limit)?
+    } else if let Some(path) = &config.resume {
+        world.resume(config.time_limit, Path::new(path))?
+    } else if let Some((time, path)) = &config.checkpoint {
+        world.start_checkpointed(config.time_limit, *time, Path::new(path))?
+    } else {
+        world.start(config.time_limit)?
+    };
     let duration = start.elapsed();
     eprintln!("  ok");
 
-    let n_actors = counts.len();
-    let n_cpus = std::cmp::min(n_cpus, n_actors);
+    #[cfg(feature = "trace")]
+    if config.trace.is_some() {
+        rustasim::trace::finish()?;
+    }
+
+    let n_actors = results.n_devices();
+    let n_cpus = std::cmp::min(config.executor.n_workers(), n_actors);
 
     // stats...
-    let sum_count = counts.iter().sum::<ActorResult>();
+    let sum_count = results.total();
     let ns_per_count: f64 = if sum_count > 0 {
         1000. * duration.as_nanos() as f64 / sum_count as f64
     } else {
@@ -204,8 +450,8 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
 
     // time_limit/1e9 is in seconds which is how much we simulated
     // divide by the time it took us -> simulation bandwidth
-    let gbps =
-        (n_links * config.bandwidth_gbps * config.time_limit) as f64 / 1e9 / duration.as_secs_f64();
+    let gbps = (n_links * config.bandwidth_gbps) as f64 * config.time_limit.as_secs_f64()
+        / duration.as_secs_f64();
 
     eprintln!(
         "= {} in {:.3}s. {} actors ({} hosts) for {:.3}s on {} cores",
@@ -213,18 +459,18 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
         duration.as_secs_f32(),
         n_actors,
         n_hosts,
-        config.time_limit as f64 / 1e9,
+        config.time_limit.as_secs_f64(),
         n_cpus,
     );
     eprintln!(
         "  {:.3}M count/sec, {:.3}M /actors, {:.3}M /cpu",
-        (1e6 / ns_per_count as f64),
+        (1e6 / ns_per_count),
         (1e6 / (ns_per_count * n_actors as f64)),
         (1e6 / (ns_per_count * n_cpus as f64)),
     );
     eprintln!(
         "  {:.1} ns/count, {:.1} ns/actor, {:.1} ns/cpu",
-        ns_per_count / 1000. as f64,
+        ns_per_count / 1000.,
         ns_per_count * n_actors as f64 / 1000.,
         ns_per_count * n_cpus as f64 / 1000.
     );
@@ -239,6 +485,68 @@ pub fn run_config(config: SimConfig, n_cpus: usize) -> Result<(), Box<dyn Error>
     Ok(())
 }
 
+/// Runs the simulation over `n` processes of this very program, each given `args`
+///
+/// They talk over Unix sockets in a temporary directory. The flows each of them completed are
+/// merged back into the same output as a single process would give.
+pub fn run_processes(n: usize, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let dir = std::env::temp_dir().join(format!("rustasim-dcsim-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let exe = std::env::current_exe()?;
+    let children = Launch::spawn(n, &dir, |_| {
+        let mut command = Command::new(&exe);
+        command.args(args).stdout(Stdio::piped());
+        command
+    })?;
+
+    // all at once, a process stuck writing its output would hold the others back
+    let outputs: Vec<_> = children
+        .into_iter()
+        .map(|child| thread::spawn(move || child.wait_with_output()))
+        .collect();
+    let mut records = Vec::new();
+    let mut failed = Vec::new();
+    for (rank, output) in outputs.into_iter().enumerate() {
+        let output = output.join().unwrap()?;
+        if !output.status.success() {
+            failed.push(rank);
+            continue;
+        }
+        for line in String::from_utf8(output.stdout)?.lines().skip(1) {
+            records.push(line.parse::<FlowRecord>()?);
+        }
+    }
+    std::fs::remove_dir_all(dir)?;
+    if !failed.is_empty() {
+        return Err(format!("Processes {:?} failed", failed).into());
+    }
+
+    records.sort();
+    println!("{}", FlowRecord::CSV_HEADER);
+    for record in records {
+        println!("{}", record);
+    }
+
+    Ok(())
+}
+
+/// Hands the links of device `id`, which runs elsewhere, to the `cluster` if they lead to us
+fn bridge(
+    id: usize,
+    links: Vec<(usize, Producer<ModelEvent>, Consumer<ModelEvent>)>,
+    cluster: &mut Cluster<Time, NetworkEvent>,
+    rank: usize,
+    owner: impl Fn(usize) -> usize,
+) {
+    for (neighbour, tx, rx) in links {
+        if owner(neighbour) == rank {
+            // what our device sends it, and what it sends our device
+            cluster.send_to(owner(id), (neighbour, id), rx);
+            cluster.receive_from(owner(id), (id, neighbour), tx);
+        }
+    }
+}
+
 /// Main simulation object.
 ///
 /// This is where the core of the simualtion setup should happen. Notably it has the important
@@ -254,28 +562,110 @@ pub struct World {
 
     /// Communication channels from us (the world) to the actors
     chans: HashMap<usize, Producer<ModelEvent>>,
+
+    /// Flows to start, pushed to the servers when the simulation starts
+    flows: Vec<(Time, FlowDesc)>,
+
+    /// Completed flows, reported by the servers
+    records: Receiver<FlowRecord>,
+
+    /// Ids of the devices once handed over
+    ids: DeviceIds,
+
+    executor: Executor,
+    scheduler: Scheduler,
+    partition: bool,
+    pin: bool,
+    watchdog: Option<Duration>,
+
+    /// Links between devices, by actor index
+    links: Vec<(usize, usize)>,
 }
 
 impl World {
     /// Builds a world based on the network
     pub fn new_from_network(network: Network, config: &SimConfig, n_hosts: usize) -> World {
+        World::new_with(network, config, n_hosts, None)
+    }
+
+    /// Builds this process's share of a world spread over the processes of `cluster`
+    ///
+    /// Every process must build it from the same network. Devices are split between them so that
+    /// as few links as possible cross over, the links that do are handed to the `cluster`, which
+    /// must then be given to [`start_on`](#method.start_on).
+    pub fn new_on(
+        network: Network,
+        config: &SimConfig,
+        n_hosts: usize,
+        cluster: &mut Cluster<Time, NetworkEvent>,
+    ) -> World {
+        World::new_with(network, config, n_hosts, Some(cluster))
+    }
+
+    /// Builds the devices of this process, all of them without a `cluster`
+    fn new_with(
+        network: Network,
+        config: &SimConfig,
+        n_hosts: usize,
+        mut cluster: Option<&mut Cluster<Time, NetworkEvent>>,
+    ) -> World {
         let mut server_builders: Vec<ServerBuilder> = Vec::new();
         let mut router_builders: Vec<RouterBuilder> = Vec::new();
 
+        // actors are handed over by id, starting at 1
+        let mut links: Vec<(usize, usize)> = network
+            .iter()
+            .flat_map(|(&id, neighbours)| neighbours.iter().map(move |&n| (id, n)))
+            .filter(|&(id, n)| id < n)
+            .map(|(id, n)| (id - 1, n - 1))
+            .collect();
+        links.sort_unstable();
+
+        // whose device is whose, the same in every process
+        let (rank, owners) = match &cluster {
+            Some(cluster) => {
+                let partition =
+                    Partition::from_links(network.len(), cluster.n_ranks(), links.iter().copied());
+                let owners = (0..network.len()).map(|ix| partition.group(ix)).collect();
+                (cluster.rank(), owners)
+            }
+            None => (0, vec![0; network.len()]),
+        };
+        let owner = |id: usize| owners[id - 1];
+
+        // our devices are handed over in order, the links between them are all we run
+        let mut local_ix = vec![None; network.len()];
+        for (local, ix) in (0..network.len())
+            .filter(|&ix| owners[ix] == rank)
+            .enumerate()
+        {
+            local_ix[ix] = Some(local);
+        }
+        let links = links
+            .into_iter()
+            .filter_map(|(a, b)| Some((local_ix[a]?, local_ix[b]?)))
+            .collect();
+
+        let (records_tx, records) = channel();
+
         // Host builders, they don't connect to anything else
         for id in 1..n_hosts + 1 {
             server_builders.push(
                 ServerBuilder::new(id)
-                    .latency_ns(config.latency_ns)
-                    .bandwidth_gbps(config.bandwidth_gbps),
+                    .queue_kind(config.queue_kind)
+                    .latency(config.latency)
+                    .bandwidth_gbps(config.bandwidth_gbps)
+                    .flow_records(records_tx.clone()),
             );
         }
 
         // Router builders, we can connect those we know about
         for id in n_hosts + 1..network.len() + 1 {
             let mut rb = RouterBuilder::new(id)
-                .latency_ns(config.latency_ns)
-                .bandwidth_gbps(config.bandwidth_gbps);
+                .queue_kind(config.queue_kind)
+                .latency(config.latency)
+                .bandwidth_gbps(config.bandwidth_gbps)
+                .seed(config.seed);
             for &n in &network[&id] {
                 // skip those who are not connected yet...
                 if n >= id {
@@ -298,6 +688,7 @@ impl World {
         eprintln!("  Routing...");
         router_builders
             .iter_mut()
+            .filter(|r| owner(r.id) == rank)
             .map(|r| {
                 let routes = route_all(&network, r.id);
                 r.install_routes(routes);
@@ -309,6 +700,16 @@ impl World {
         eprintln!("  Building {} servers...", server_builders.len());
         let mut servers = vec![];
         for mut b in server_builders {
+            if owner(b.id) != rank {
+                bridge(
+                    b.id,
+                    b.into_links(),
+                    cluster.as_deref_mut().unwrap(),
+                    rank,
+                    owner,
+                );
+                continue;
+            }
             chans.insert(b.id, b.connect_world());
             servers.push(b.build());
         }
@@ -316,6 +717,16 @@ impl World {
         eprintln!("  Building {} routers...", router_builders.len());
         let mut routers = vec![];
         for mut rb in router_builders {
+            if owner(rb.id) != rank {
+                bridge(
+                    rb.id,
+                    rb.into_links(),
+                    cluster.as_deref_mut().unwrap(),
+                    rank,
+                    owner,
+                );
+                continue;
+            }
             chans.insert(rb.id, rb.connect_world());
             routers.push(rb.build());
         }
@@ -324,49 +735,245 @@ impl World {
             servers,
             routers,
             chans,
+            flows: Vec::new(),
+            records,
+            ids: DeviceIds::default(),
+
+            executor: config.executor,
+            scheduler: config.scheduler,
+            partition: config.partition,
+            pin: config.pin,
+            watchdog: config.watchdog,
+            links,
         }
     }
 
     /// Adds specified flows to the current network
-    pub fn add_flows(&mut self, flows: Vec<(u64, FlowDesc)>) {
+    ///
+    /// Flows from servers of other processes are left to them.
+    pub fn add_flows(&mut self, flows: Vec<(Time, FlowDesc)>) {
         eprintln!("  Init {} flows...", flows.len());
-        for (time, f) in flows {
-            self.chans[&f.0]
-                .push(Event {
-                    src: 0,
-                    time,
-                    event_type: EventType::ModelEvent(NetworkEvent::Flow(f)),
-                })
-                .unwrap();
+        self.flows.extend(flows);
+    }
+
+    /// Runs this `World`'s simulation bit by bit up to time `done`, see [`Session`](struct.Session.html)
+    ///
+    /// The devices run on as many workers as the config's executor has, scheduled as it asked,
+    /// always with null-messages.
+    pub fn session(mut self, done: Time) -> Session {
+        let ids = DeviceIds::new(&self.servers, &self.routers);
+        let mut devices = Vec::new();
+        for s in self.servers.drain(..) {
+            devices.push(Box::new(Device::Server(s)));
+        }
+        for r in self.routers.drain(..) {
+            devices.push(Box::new(Device::Router(r)));
         }
+
+        let n_workers = self.executor.n_workers();
+        let session = rustasim::Session::new(self.scheduler, n_workers, devices, Some(done));
+        Session::new(session, ids, self.chans, self.flows, self.records, done)
     }
 
     /// Runs this `World`'s simulation up to time `done`.
     ///
-    /// This will spawn a thread per actor and wait for all of them to end.
-    pub fn start(mut self, num_cpus: usize, done: u64) -> Vec<u64> {
-        // csv header, cheating but that's okay here...
-        println!("src,dst,start,end,size_byte,fct_ns");
+    /// This will spawn a thread per actor and wait for all of them to end. The completed flows
+    /// are then written to stdout as CSV, in a stable order.
+    ///
+    /// It runs on the executor the config asked for. If it also asked for a watchdog, the workers
+    /// run under it.
+    ///
+    /// What each device returned comes back by id. If a device panics, the run stops and the
+    /// panic is returned instead.
+    pub fn start(mut self, done: Time) -> Result<Results, ActorPanic> {
+        let actors = self.actors(done);
+        self.run(actors, done, Vec::new())
+    }
+
+    /// Runs this process's share of the simulation up to time `done`, along with the others
+    ///
+    /// The world must have been built [`on`](#method.new_on) the `cluster`. Only the flows
+    /// completed by our servers are written out. It runs on workers sending null-messages, neither
+    /// the sequential executor nor windows can wait on other processes.
+    pub fn start_on(
+        mut self,
+        cluster: Cluster<Time, NetworkEvent>,
+        done: Time,
+    ) -> Result<Results, Box<dyn Error>> {
+        if !matches!(self.executor, Executor::Parallel(_)) {
+            return Err("Only workers sending null-messages can run along with processes".into());
+        }
+        let actors = self.actors(done);
+        let bridges = cluster.start()?;
+        let results = self.run(actors, done, Vec::new())?;
+        bridges.finish()?;
+        Ok(results)
+    }
+
+    /// Runs this `World`'s simulation up to time `done`, saving it to `path` at time `time`
+    ///
+    /// The simulation carries on once saved, and can be resumed from `path` with
+    /// [`resume`](#method.resume) if it doesn't make it to the end. It runs on workers up to the
+    /// checkpoint, the sequential executor can't be paused.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` isn't before `done`, the servers done by then would have reported their
+    /// flows already.
+    pub fn start_checkpointed(
+        mut self,
+        done: Time,
+        time: Time,
+        path: &Path,
+    ) -> Result<Results, Box<dyn Error>> {
+        assert!(
+            time < done,
+            "The checkpoint must be before the end of the simulation"
+        );
+        let n_workers = self.executor.n_workers();
+        let actors = self.actors(done);
+        let (actors, results) =
+            checkpoint(self.scheduler, n_workers, actors, time, Some(done), path)?;
+        eprintln!("  saved to {}", path.display());
+
+        Ok(self.run(actors, done, results)?)
+    }
 
-        // Tell everyone when the end is
+    /// Resumes the simulation saved to `path`, and runs it up to time `done`
+    ///
+    /// This `World` must be built the same way as the one that was saved, the flows it was given
+    /// are replaced by those saved.
+    pub fn resume(mut self, done: Time, path: &Path) -> Result<Results, Box<dyn Error>> {
+        let actors = self.actors(done);
+        let (actors, results) = restore(actors, path)?;
+        eprintln!("  resumed from {}", path.display());
+
+        Ok(self.run(actors, done, results)?)
+    }
+
+    /// Tells everyone about the flows and that nothing else comes until `done`, and hands the
+    /// actors over, servers first
+    fn actors(&mut self, done: Time) -> Vec<CheckpointActor<Time, u64>> {
+        push_flows(&self.chans, std::mem::take(&mut self.flows));
         for (_, c) in self.chans.iter_mut() {
             c.push(Event {
                 time: done,
                 src: 0,
-                event_type: EventType::Close,
+                event_type: EventType::Null,
             })
-            .unwrap();
+            .expect("World queues are unbounded");
         }
 
-        // Workers
-        let mut actors: Vec<Box<dyn Advancer<u64, u64> + Send>> = Vec::new();
-        for s in self.servers {
+        self.ids = DeviceIds::new(&self.servers, &self.routers);
+        let mut actors: Vec<CheckpointActor<Time, u64>> = Vec::new();
+        for s in self.servers.drain(..) {
             actors.push(Box::new(s));
         }
-        for r in self.routers {
+        for r in self.routers.drain(..) {
             actors.push(Box::new(r));
         }
 
-        start(num_cpus, actors)
+        actors
+    }
+
+    /// Runs the actors up to `end`, and writes the completed flows to stdout
+    ///
+    /// The actors are those still running, in order, the others are `done` with their result.
+    fn run(
+        self,
+        actors: Vec<CheckpointActor<Time, u64>>,
+        end: Time,
+        mut done: Vec<(usize, ActorResult)>,
+    ) -> Result<Results, ActorPanic> {
+        let actors: Vec<Box<dyn Advancer<Time, u64> + Send>> = actors
+            .into_iter()
+            .map(|actor| -> Box<dyn Advancer<Time, u64> + Send> { actor })
+            .collect();
+
+        let windowed = matches!(self.executor, Executor::Window(_));
+        let num_cpus = self.executor.n_workers();
+        let counts = if self.executor == Executor::Sequential {
+            start_sequential(actors, Some(end))
+        } else {
+            let partitioned = self.partition || self.pin || windowed || self.watchdog.is_some();
+            let (counts, report) = if partitioned {
+                let partition = if self.partition {
+                    Partition::from_links(actors.len(), num_cpus, self.links.iter().copied())
+                } else {
+                    Partition::round_robin(actors.len(), num_cpus)
+                }
+                .pinned(self.pin);
+                eprintln!(
+                    "  {} of {} links between workers",
+                    partition.cut(self.links.iter().copied()),
+                    self.links.len()
+                );
+
+                if windowed {
+                    start_windowed(&partition, actors, Some(end))
+                } else if let Some(timeout) = self.watchdog {
+                    start_watched(
+                        &Watchdog::new(timeout),
+                        self.scheduler,
+                        &partition,
+                        actors,
+                        Some(end),
+                    )
+                } else {
+                    start_partitioned(self.scheduler, &partition, actors, Some(end))
+                }
+            } else {
+                start_with(self.scheduler, num_cpus, actors, Some(end))
+            }?;
+            let total = report.total();
+            eprintln!(
+                "  {} events, {} nulls sent, {} nulls received, {} stalls, {:.1}% idle",
+                total.events,
+                total.nulls_sent,
+                total.nulls_received,
+                total.stalls,
+                100. * report.idle_fraction(),
+            );
+            counts
+        };
+
+        let running: Vec<usize> = (0..self.ids.ids.len())
+            .filter(|ix| done.iter().all(|&(d, _)| d != *ix))
+            .collect();
+        done.extend(running.into_iter().zip(counts));
+
+        write_records(&self.records);
+        Ok(self.ids.results(done))
+    }
+}
+
+/// Pushes the flows to their servers, in order of time
+///
+/// Flows from servers of other processes are left to them.
+fn push_flows(chans: &HashMap<usize, Producer<ModelEvent>>, mut flows: Vec<(Time, FlowDesc)>) {
+    flows.sort_by_key(|&(time, _)| time);
+    for (time, f) in flows {
+        let chan = match chans.get(&f.0) {
+            Some(chan) => chan,
+            None => continue,
+        };
+        chan.push(Event {
+            src: 0,
+            time,
+            event_type: EventType::ModelEvent(NetworkEvent::Flow(f)),
+        })
+        .expect("World queues are unbounded");
+    }
+}
+
+/// Writes the flows the servers completed to stdout as CSV, in a stable order
+fn write_records(records: &Receiver<FlowRecord>) {
+    // csv output, cheating but that's okay here...
+    let mut records: Vec<FlowRecord> = records.try_iter().collect();
+    records.sort();
+
+    println!("{}", FlowRecord::CSV_HEADER);
+    for record in records {
+        println!("{}", record);
     }
 }
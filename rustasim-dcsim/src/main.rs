@@ -1,12 +1,16 @@
 use docopt::Docopt;
-use rustasim_dcsim::{run_config, SimConfig, Topology};
+use rustasim::net::Launch;
+use rustasim_dcsim::{
+    run_config, run_processes, Executor, QueueKind, Scheduler, SimConfig, Time, Topology,
+};
 use serde::Deserialize;
+use std::time::Duration;
 
 const USAGE: &str = "
 Rustasim datacenter simulator.
 
 Usage:
-    rustasim-dcsim [--limit TIME] (--flows FILE | --load LOAD) ((--clos-up UP --clos-down DOWN) | --fc <N_RACKS>) [--latency LATENCY --bandwidth BANDWIDTH]
+    rustasim-dcsim [--limit TIME] (--flows FILE | --load LOAD) ((--clos-up UP --clos-down DOWN) | --fc <N_RACKS>) [--latency LATENCY --bandwidth BANDWIDTH] [--seed SEED] [--queues KIND] [--cores CORES [--scheduler SCHEDULER] [--partition] [--pin] [--window] | --sequential] [--processes N] [--checkpoint FILE --checkpoint-at TIME | --resume FILE] [--trace FILE] [--watchdog SECS]
     rustasim-dcsim --help
 
 Options:
@@ -15,6 +19,22 @@ Options:
     -l, --limit TIME         Simulation end in seconds [default: 1.0].
     --latency LATENCY        Inter-device latency, in ns [default: 500].
     --bandwidth BANDWIDTH    Inter-device bandwidth, in gigabits/sec (note: bits, NOT bytes) [default: 10].
+    --seed SEED              Seed for the random decisions, same seed gives the same output [default: 0].
+    --queues KIND            Event queues: bounded, or segmented to only take memory for what they hold [default: bounded].
+    --cores CORES            Number of worker threads, defaults to one less than the number of cores.
+    --scheduler SCHEDULER    How workers pick actors: fifo, or priority for lowest timestamp first [default: fifo].
+    --partition              Keep linked devices on the same worker.
+    --pin                    Pin each worker to its own core.
+    --window                 Synchronize the workers in windows of simulated time rather than with null-messages.
+    --sequential             Run on a single thread with the sequential reference executor.
+    --processes N            Split the simulation over N processes on this machine, each with its own cores.
+
+    --checkpoint FILE        Save the simulation to FILE once it reaches --checkpoint-at, then carry on.
+    --checkpoint-at TIME     When to save the simulation, in seconds.
+    --resume FILE            Resume the simulation saved to FILE, the other options must be the same.
+
+    --trace FILE             Write what the engine does to FILE as CSV, needs the trace feature.
+    --watchdog SECS          Dump what the devices wait on whenever the run makes no progress for SECS seconds.
 
     -f, --flows FILE         Flow file, following the htsim format.
     --load LOAD              (unused) Load between 0 and 1, hosts will average LOAD*BANDWIDTH network demand
@@ -33,6 +53,24 @@ struct Args {
     flag_limit: f64,
     flag_latency: u64,
     flag_bandwidth: u64,
+    flag_seed: u64,
+    flag_queues: String,
+    flag_cores: Option<usize>,
+    flag_scheduler: String,
+    flag_partition: bool,
+    flag_pin: bool,
+    flag_window: bool,
+    flag_sequential: bool,
+    flag_processes: Option<usize>,
+
+    // checkpoints
+    flag_checkpoint: Option<String>,
+    flag_checkpoint_at: Option<f64>,
+    flag_resume: Option<String>,
+
+    // debugging
+    flag_trace: Option<String>,
+    flag_watchdog: Option<f64>,
 
     // flows
     flag_flows: Option<String>,
@@ -57,10 +95,8 @@ fn main() {
 
     let topology = if let Some(n_racks) = args.flag_fc {
         Topology::FullyConnected(n_racks)
-    } else if args.flag_clos_up.is_some() && args.flag_clos_down.is_some() {
-        let u = args.flag_clos_up.unwrap();
-        let d = args.flag_clos_down.unwrap();
-        if (u + d) % 2 != 0 {
+    } else if let (Some(u), Some(d)) = (args.flag_clos_up, args.flag_clos_down) {
+        if !(u + d).is_multiple_of(2) {
             eprintln!("For a CLOS topology to be valid, k (u+d) must be even! You gave CLOS({}, {}), k: {}\nexit", u, d, u+d);
             std::process::exit(1);
         }
@@ -74,8 +110,92 @@ fn main() {
         unimplemented!();
     }
 
-    #[allow(clippy::zero_prefixed_literal)]
-    let time_limit: u64 = (args.flag_limit * 1e9) as u64;
+    let scheduler = match args.flag_scheduler.as_str() {
+        "fifo" => Scheduler::Fifo,
+        "priority" => Scheduler::Priority,
+        other => {
+            eprint!("FAIL: Unknown scheduler {}...\n\n{}", other, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let queue_kind = match args.flag_queues.as_str() {
+        "bounded" => QueueKind::Bounded,
+        "segmented" => QueueKind::Segmented,
+        other => {
+            eprint!("FAIL: Unknown queue kind {}...\n\n{}", other, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let seconds = |secs: f64| {
+        Time::try_from_secs_f64(secs).unwrap_or_else(|| {
+            eprint!("FAIL: {}s is out of range...\n\n{}", secs, USAGE);
+            std::process::exit(1);
+        })
+    };
+    let time_limit = seconds(args.flag_limit);
+    let checkpoint_at = args.flag_checkpoint_at.map(seconds);
+    if checkpoint_at.is_some_and(|time| time >= time_limit) {
+        eprint!(
+            "FAIL: The checkpoint must be before the limit...\n\n{}",
+            USAGE
+        );
+        std::process::exit(1);
+    }
+
+    if args.flag_trace.is_some() && !cfg!(feature = "trace") {
+        eprint!(
+            "FAIL: Tracing needs the trace feature, rebuild with --features trace...\n\n{}",
+            USAGE
+        );
+        std::process::exit(1);
+    }
+
+    let watchdog = args.flag_watchdog.map(|secs| {
+        Duration::try_from_secs_f64(secs).unwrap_or_else(|_| {
+            eprint!("FAIL: Can't watch for {}s...\n\n{}", secs, USAGE);
+            std::process::exit(1);
+        })
+    });
+
+    // we're one of the processes, or about to start them
+    let launch = Launch::from_env().unwrap_or_else(|e| {
+        eprint!("FAIL: {}...\n\n{}", e, USAGE);
+        std::process::exit(1);
+    });
+    if let Some(n_processes) = args.flag_processes {
+        if args.flag_sequential
+            || args.flag_window
+            || args.flag_checkpoint.is_some()
+            || args.flag_resume.is_some()
+            || args.flag_trace.is_some()
+        {
+            eprint!(
+                "FAIL: Processes can't run sequentially or in windows, be saved, resumed or traced...\n\n{}",
+                USAGE
+            );
+            std::process::exit(1);
+        }
+
+        if launch.is_none() {
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            if let Err(e) = run_processes(n_processes, &args) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    let n_cores = args.flag_cores.unwrap_or_else(|| num_cpus::get() - 1);
+    let executor = if args.flag_sequential {
+        Executor::Sequential
+    } else if args.flag_window {
+        Executor::Window(n_cores)
+    } else {
+        Executor::Parallel(n_cores)
+    };
 
     let config = SimConfig {
         time_limit,
@@ -84,11 +204,24 @@ fn main() {
 
         bandwidth_gbps: args.flag_bandwidth,
 
-        latency_ns: args.flag_latency,
+        latency: Time::from_ns(args.flag_latency),
+
+        seed: args.flag_seed,
+
+        executor,
+        scheduler,
+        partition: args.flag_partition,
+        pin: args.flag_pin,
+        queue_kind,
+
+        checkpoint: checkpoint_at.zip(args.flag_checkpoint),
+        resume: args.flag_resume,
+        trace: args.flag_trace,
+        watchdog,
+        launch,
     };
 
-    let n_cpus = num_cpus::get() - 1;
-    if let Err(e) = run_config(config, n_cpus) {
+    if let Err(e) = run_config(config) {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
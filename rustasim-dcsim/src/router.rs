@@ -1,12 +1,14 @@
 //! Router module, takes care of ToRs and backbone switches
 
-use crate::{Connectable, ModelEvent, NetworkEvent, Time, Q_SIZE};
+use crate::{Connectable, ModelEvent, NetworkEvent, QueueKind, Time};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rustasim::spsc;
 use rustasim::spsc::*;
-use rustasim::{ActorState, Advancer, Event, EventType, Merger};
+use rustasim::{
+    actor_rng, load_state, save_state, ActorRng, ActorState, Advancer, Checkpoint, CheckpointError,
+    Delivered, Driver, Event, EventType, Merger, Pausable,
+};
 use std::collections::HashMap;
+use std::io::{Read, Write};
 
 /// Top of rack switch builder
 ///
@@ -18,9 +20,14 @@ pub struct RouterBuilder {
     pub id: usize,
 
     // fundamental properties
-    latency_ns: u64,
+    latency: Time,
     bandwidth_gbps: u64,
 
+    // ECMP
+    seed: u64,
+
+    queue_kind: QueueKind,
+
     // internal mappings
     id_to_ix: HashMap<usize, usize>,
     ix_to_id: Vec<usize>,
@@ -40,7 +47,7 @@ impl Connectable for &mut RouterBuilder {
     }
 
     fn connect(&mut self, mut other: impl Connectable) {
-        let (prod, cons) = spsc::new(Q_SIZE);
+        let (prod, cons) = self.queue_kind.queue();
 
         self.id_to_ix.insert(other.id(), self.next_ix);
         self.ix_to_id.push(other.id());
@@ -67,7 +74,7 @@ impl Connectable for &mut RouterBuilder {
         //self.out_times.push(0);
         // self.route.insert(other.id, self.next_ix); // route to neighbour is neighbour
 
-        let (prod, cons) = spsc::new(Q_SIZE);
+        let (prod, cons) = self.queue_kind.queue();
         self.in_queues.push(cons);
 
         self.next_ix += 1;
@@ -81,9 +88,13 @@ impl RouterBuilder {
     pub fn new(id: usize) -> RouterBuilder {
         RouterBuilder {
             id,
-            latency_ns: 500,
+            latency: Time::from_ns(500),
             bandwidth_gbps: 1,
 
+            seed: 0,
+
+            queue_kind: QueueKind::default(),
+
             id_to_ix: HashMap::new(),
             ix_to_id: Vec::new(),
             next_ix: 0,
@@ -100,21 +111,44 @@ impl RouterBuilder {
     pub fn connect_world(&mut self) -> Producer<ModelEvent> {
         self.id_to_ix.insert(0, self.next_ix);
 
-        let (prod, cons) = spsc::new(Q_SIZE);
+        // the world might push any number of flows up front, it can't wait for room
+        let (prod, cons) = QueueKind::Segmented.queue();
         self.in_queues.push(cons);
         self.ix_to_id.push(0);
 
         prod
     }
 
+    /// Queues to and from each neighbour, for a router that runs in another process
+    ///
+    /// The world isn't connected to such a router.
+    pub(crate) fn into_links(self) -> Vec<(usize, Producer<ModelEvent>, Consumer<ModelEvent>)> {
+        self.ix_to_id
+            .into_iter()
+            .zip(self.out_queues)
+            .zip(self.in_queues)
+            .map(|((id, tx), rx)| (id, tx, rx))
+            .collect()
+    }
+
     /// Define router's outgoing bandwidth
-    pub fn bandwidth_gbps(mut self, bandwidth_gbps: Time) -> RouterBuilder {
+    pub fn bandwidth_gbps(mut self, bandwidth_gbps: u64) -> RouterBuilder {
         self.bandwidth_gbps = bandwidth_gbps;
         self
     }
     /// Define router's outgoing latency
-    pub fn latency_ns(mut self, latency: Time) -> RouterBuilder {
-        self.latency_ns = latency;
+    pub fn latency(mut self, latency: Time) -> RouterBuilder {
+        self.latency = latency;
+        self
+    }
+    /// Define how the router stores the events it receives, on the links connected from now on
+    pub fn queue_kind(mut self, queue_kind: QueueKind) -> RouterBuilder {
+        self.queue_kind = queue_kind;
+        self
+    }
+    /// Define the simulation seed, used to pick between equal-cost paths
+    pub fn seed(mut self, seed: u64) -> RouterBuilder {
+        self.seed = seed;
         self
     }
 
@@ -140,7 +174,7 @@ impl RouterBuilder {
             }
 
             let next_hop_ids = &routes[&dst_id];
-            let next_hop_ixs = next_hop_ids.iter().map(|x| self.id_to_ix[&x]).collect();
+            let next_hop_ixs = next_hop_ids.iter().map(|x| self.id_to_ix[x]).collect();
 
             self.route.push(next_hop_ixs);
         }
@@ -154,35 +188,26 @@ impl RouterBuilder {
             v.push(*id);
         }
 
-        let merger = Merger::new(self.in_queues, self.id, v);
-
+        // the merger sends the null-messages, nothing we send arrives sooner than the latency
+        let mut merger = Merger::new(self.in_queues, self.id, v);
         let mut out_times = vec![];
-        for dst_ix in 0..self.out_queues.len() {
-            self.out_queues[dst_ix]
-                .push(Event {
-                    event_type: EventType::Null,
-                    //real_time: start.elapsed().as_nanos(),
-                    //real_time: 0,
-                    src: self.id,
-                    time: self.latency_ns,
-                })
-                .unwrap();
-
-            out_times.push(0);
+        for q in self.out_queues {
+            merger = merger.output(q, self.latency);
+            out_times.push(Time::ZERO);
         }
 
         Router {
             id: self.id,
 
-            latency_ns: self.latency_ns,
+            latency: self.latency,
             bandwidth_gbps: self.bandwidth_gbps,
 
             merger,
+            rng: actor_rng(self.seed, self.id),
 
-            ix_to_id: self.ix_to_id,
+            _ix_to_id: self.ix_to_id,
 
             // event management
-            out_queues: self.out_queues,
             out_times,
 
             // Route should eventually be turned into a vec
@@ -205,16 +230,16 @@ pub struct Router {
     pub id: usize,
 
     // fundamental properties
-    latency_ns: u64,
+    latency: Time,
     bandwidth_gbps: u64,
 
-    ix_to_id: Vec<usize>,
+    _ix_to_id: Vec<usize>,
 
-    merger: Merger<u64, NetworkEvent>,
+    merger: Merger<Time, NetworkEvent>,
+    rng: ActorRng,
 
     // event management
-    out_queues: Vec<Producer<ModelEvent>>,
-    out_times: Vec<u64>,
+    out_times: Vec<Time>,
 
     // Route should eventually be turned into a vec
     route: Vec<Vec<usize>>,
@@ -232,67 +257,44 @@ impl Router {
         println!("Router {} done", self.id);
         self.count
     }
+
+    /// How long a packet sent to each neighbour at `now` would wait before going out, by
+    /// neighbour id
+    pub fn queue_delays(&self, now: Time) -> Vec<(usize, Time)> {
+        self._ix_to_id
+            .iter()
+            .zip(&self.out_times)
+            .map(|(&id, &out_time)| (id, std::cmp::max(out_time, now) - now))
+            .collect()
+    }
 }
 
-impl Advancer<u64, u64> for Router {
+impl Advancer<Time, u64> for Router {
     /// Starts the rack, consumes the object
     ///
     /// The return value is a counter of some sort. It is mostly used for fast stats on the run.
     /// This will almost certainly change to a function with no return value in the near future.
     //pub fn start(&mut self, log: slog::Logger, start: Instant) -> u64 {
-    fn advance(&mut self) -> ActorState<u64, u64> {
+    fn advance(&mut self) -> ActorState<Time, u64> {
         //println!("Router {} advancing", self.id);
         //let log = log.new(o!("Router" => self.id));
         //info!(log, "start...");
 
         // main loop :)
         //for event in self.merger {
-        let mut rng = thread_rng();
         while let Some(event) = self.merger.next() {
             /*println!(
                 "Router {} @{}: <{} {:?}",
-                self.id, event.time, self.ix_to_id[event.src], event.event_type
+                self.id, event.time, self._ix_to_id[event.src], event.event_type
             );*/
             //self.count += 1;
             match event.event_type {
-                EventType::Close => {
-                    // ensure everyone ignores us from now until close
-                    for dst_ix in 0..self.out_queues.len() {
-                        self.out_queues[dst_ix]
-                            .push(Event {
-                                event_type: EventType::Close,
-                                //real_time: start.elapsed().as_nanos(),
-                                src: self.id,
-                                time: event.time + self.latency_ns,
-                            }) // add latency to avoid violating in-order invariant
-                            .unwrap();
-                    }
+                // The simulation ends at the engine's end time, nobody sends these
+                EventType::Close => unreachable!(),
 
-                    break;
-                }
-
-                // We're waiting on a neighbour...
-                EventType::Stalled => {
-                    // We need the time from these friendos
-                    for (dst_ix, out_time) in self.out_times.iter_mut().enumerate() {
-                        // equal because they might just need a jog, blocking happens in the
-                        // iterator, so no infinite loop risk
-                        if *out_time < event.time {
-                            self.out_queues[dst_ix]
-                                .push(Event {
-                                    event_type: EventType::Null,
-                                    src: self.id,
-                                    time: event.time + self.latency_ns,
-                                })
-                                .unwrap();
-                            //self.count += 1;
-
-                            *out_time = event.time;
-                        }
-                    }
-
-                    return ActorState::Continue(event.time);
-                }
+                // We're waiting on a neighbour, the merger already told the others how far they
+                // can go
+                EventType::Stalled => return ActorState::Continue(event.time),
 
                 // This is a message from neighbour we were waiting on, it has served its purpose
                 EventType::Null => {} //unreachable!(),
@@ -303,15 +305,15 @@ impl Advancer<u64, u64> for Router {
                     match model_event {
                         // this is only for servers, not routers
                         NetworkEvent::Flow(_) => unreachable!(),
-                        NetworkEvent::Timeout => unreachable!(),
+                        NetworkEvent::Timeout(..) => unreachable!(),
 
                         NetworkEvent::Packet(packet) => {
                             // Next step
                             let next_hop_ix: usize =
-                                *self.route[packet.dst].choose(&mut rng).unwrap();
+                                *self.route[packet.dst].choose(&mut self.rng).unwrap();
 
                             // drop packet if our outgoing queue is full
-                            if event.time + 1000 * 8 * 1500 * self.bandwidth_gbps
+                            if event.time + Time::from_ns(1000 * 8 * 1500 * self.bandwidth_gbps)
                                 < self.out_times[next_hop_ix]
                             {
                                 //println!("@{} Router {} drop {:?}", event.time, self.id, packet);
@@ -320,24 +322,22 @@ impl Advancer<u64, u64> for Router {
 
                             // when
                             let cur_time = std::cmp::max(event.time, self.out_times[next_hop_ix]);
-                            let tx_end = cur_time + 8 * packet.size_byte / self.bandwidth_gbps;
-                            let rx_end = tx_end + self.latency_ns;
+                            let tx_end = cur_time
+                                + Time::transmission(packet.size_byte, self.bandwidth_gbps);
+                            let rx_end = tx_end + self.latency;
 
                             //println!("\x1b[0;3{}m@{} Router {} sent {:?} to {}@{}",
                             //self.id+1, event.time, self.id, packet, next_hop, rx_end);
                             // go
-                            if let Err(e) = self.out_queues[next_hop_ix].push(Event {
-                                event_type: EventType::ModelEvent(NetworkEvent::Packet(packet)),
-                                //real_time: start.elapsed().as_nanos(),
-                                src: self.id,
-                                time: rx_end,
-                            }) {
-                                println!(
-                                    "@{} Router #{} push error to #{}: {:?}",
-                                    event.time, self.id, self.ix_to_id[next_hop_ix], e
-                                );
-                                break;
-                            }
+                            self.merger.send(
+                                next_hop_ix,
+                                Event {
+                                    event_type: EventType::ModelEvent(NetworkEvent::Packet(packet)),
+                                    //real_time: start.elapsed().as_nanos(),
+                                    src: self.id,
+                                    time: rx_end,
+                                },
+                            );
 
                             // update our estimate of time
                             self.out_times[next_hop_ix] = tx_end;
@@ -347,7 +347,38 @@ impl Advancer<u64, u64> for Router {
             } // end match
         } // end for loop
 
+        // the closes might still be waiting for room
         //println!("Router #{} done. {} count", self.id, self.count);
-        ActorState::Done(self.count)
+        match self.merger.finish() {
+            None => ActorState::Done(self.count),
+            Some(time) => ActorState::Continue(time),
+        }
     } // end start() function
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<Time>> {
+        self.merger.last_delivered()
+    }
 } // end NIC methods
+
+impl Checkpoint<Time, u64> for Router {
+    fn merger(&mut self) -> &mut dyn Pausable<Time> {
+        &mut self.merger
+    }
+
+    fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+        save_state(out, &(self.rng.get_word_pos(), &self.out_times, self.count))
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+        let (word_pos, out_times, count) = load_state(input)?;
+        self.rng.set_word_pos(word_pos);
+        self.out_times = out_times;
+        self.count = count;
+
+        Ok(())
+    }
+}
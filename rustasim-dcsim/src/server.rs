@@ -2,17 +2,19 @@
 
 use crate::tcp;
 use crate::tcp::Flow;
-use crate::tcp::Timeout;
-use crate::tcp::MIN_RTO;
-use crate::{tx_rx_time, Connectable, ModelEvent, NetworkEvent, Time, Q_SIZE};
-use rustasim::spsc;
+use crate::tcp::FlowRecord;
+use crate::{tx_rx_time, Connectable, ModelEvent, NetworkEvent, QueueKind, Time};
 use rustasim::spsc::*;
-use rustasim::{ActorState, Advancer, Event, EventType, Merger};
-use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use rustasim::{
+    load_state, save_state, ActorState, Advancer, Checkpoint, CheckpointError, Delivered, Driver,
+    Event, EventType, Merger, Pausable, TimerId,
+};
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::mpsc::Sender;
 
-type MinHeap<T> = BinaryHeap<Reverse<T>>;
+/// Output of the `Merger` going to the ToR
+const TOR: usize = 0;
 
 /// A ServerBuilder is used to create a Server
 ///
@@ -24,7 +26,11 @@ pub struct ServerBuilder {
     pub id: usize,
 
     bandwidth_gbps: u64,
-    latency_ns: Time,
+    latency: Time,
+
+    records: Option<Sender<FlowRecord>>,
+
+    queue_kind: QueueKind,
 
     id_to_ix: HashMap<usize, usize>,
     ix_to_id: Vec<usize>,
@@ -40,7 +46,7 @@ impl Connectable for &mut ServerBuilder {
     }
 
     fn connect(&mut self, mut other: impl Connectable) {
-        let (prod, cons) = spsc::new(Q_SIZE);
+        let (prod, cons) = self.queue_kind.queue();
 
         self.id_to_ix.insert(other.id(), self.next_ix);
         self.ix_to_id.push(other.id());
@@ -62,7 +68,7 @@ impl Connectable for &mut ServerBuilder {
 
         self.out_queues.push(tx_queue);
 
-        let (prod, cons) = spsc::new(Q_SIZE);
+        let (prod, cons) = self.queue_kind.queue();
         self.in_queues.push(cons);
 
         self.next_ix += 1;
@@ -74,47 +80,43 @@ impl Connectable for &mut ServerBuilder {
 impl ServerBuilder {
     /// Starts the process for building a server
     pub fn new(id: usize) -> ServerBuilder {
-        let mut id_to_ix = HashMap::new();
-        let mut ix_to_id = Vec::new();
-
-        let mut in_queues = Vec::new();
-        let mut out_queues = Vec::new();
-
-        let mut out_times = Vec::new();
-
-        // self queue
-        let (self_prod, self_cons) = spsc::new(Q_SIZE);
-
-        id_to_ix.insert(id, 0);
-        ix_to_id.insert(0, id);
-        in_queues.push(self_cons);
-        out_queues.push(self_prod);
-
-        out_times.push(0);
-
         ServerBuilder {
             id,
 
             bandwidth_gbps: 10,
-            latency_ns: 500,
+            latency: Time::from_ns(500),
 
-            id_to_ix,
-            ix_to_id,
-            next_ix: 1,
+            records: None,
 
-            in_queues,
-            out_queues,
+            queue_kind: QueueKind::default(),
+
+            id_to_ix: HashMap::new(),
+            ix_to_id: Vec::new(),
+            next_ix: 0,
+
+            in_queues: Vec::new(),
+            out_queues: Vec::new(),
         }
     }
 
     /// Define server's outgoing bandwidth
-    pub fn bandwidth_gbps(mut self, bandwidth_gbps: Time) -> ServerBuilder {
+    pub fn bandwidth_gbps(mut self, bandwidth_gbps: u64) -> ServerBuilder {
         self.bandwidth_gbps = bandwidth_gbps;
         self
     }
     /// Define server's outgoing latency
-    pub fn latency_ns(mut self, latency: Time) -> ServerBuilder {
-        self.latency_ns = latency;
+    pub fn latency(mut self, latency: Time) -> ServerBuilder {
+        self.latency = latency;
+        self
+    }
+    /// Define how the server stores the events it receives, on the links connected from now on
+    pub fn queue_kind(mut self, queue_kind: QueueKind) -> ServerBuilder {
+        self.queue_kind = queue_kind;
+        self
+    }
+    /// Define where the server reports its completed flows when the simulation ends
+    pub fn flow_records(mut self, records: Sender<FlowRecord>) -> ServerBuilder {
+        self.records = Some(records);
         self
     }
 
@@ -122,7 +124,8 @@ impl ServerBuilder {
     pub fn connect_world(&mut self) -> Producer<ModelEvent> {
         // world queue
         // TODO create a WORLD_ID thing
-        let (world_prod, world_cons) = spsc::new(Q_SIZE);
+        // the world might push any number of flows up front, it can't wait for room
+        let (world_prod, world_cons) = QueueKind::Segmented.queue();
 
         self.id_to_ix.insert(0, self.next_ix);
         self.ix_to_id.push(0);
@@ -131,6 +134,18 @@ impl ServerBuilder {
         world_prod
     }
 
+    /// Queues to and from the ToR, for a server that runs in another process
+    ///
+    /// The world isn't connected to such a server.
+    pub(crate) fn into_links(self) -> Vec<(usize, Producer<ModelEvent>, Consumer<ModelEvent>)> {
+        self.ix_to_id
+            .into_iter()
+            .zip(self.out_queues)
+            .zip(self.in_queues)
+            .map(|((id, tx), rx)| (id, tx, rx))
+            .collect()
+    }
+
     /// Returns the Server with the specified parameters
     pub fn build(self) -> Server {
         let mut v = Vec::new();
@@ -138,69 +153,51 @@ impl ServerBuilder {
             v.push(*id);
         }
 
-        let merger = Merger::new(self.in_queues, self.id, v);
-
-        // Send null events to the ToR
-        self.out_queues[1]
-            .push(Event {
-                event_type: EventType::Null,
-                src: self.id,
-                time: self.latency_ns,
-            })
-            .unwrap();
-
-        // null event to ourselves...
-        self.out_queues[0]
-            .push(Event {
-                event_type: EventType::ModelEvent(NetworkEvent::Timeout),
-                src: self.id,
-                time: MIN_RTO,
-            })
-            .unwrap();
+        // the merger sends null events to the ToR
+        let tor_q = self.out_queues.into_iter().next().unwrap();
+        let merger = Merger::new(self.in_queues, self.id, v).output(tor_q, self.latency);
 
         Server {
             id: self.id,
 
             bandwidth_gbps: self.bandwidth_gbps,
-            latency_ns: self.latency_ns,
-
-            out_queues: self.out_queues,
+            latency: self.latency,
 
             merger,
 
             _ix_to_id: self.ix_to_id,
 
-            tor_time: 0,
-            timeouts: MinHeap::new(),
+            tor_time: Time::ZERO,
+            timeouts: HashMap::new(),
             count: 0,
 
             flows: Vec::new(),
+            records: self.records,
         }
     }
 }
 
 /// Server-in-a-rack actor
 ///
-/// The server has 3 neighbours: the top-of-rack switch, the outside world, and itself (for
-/// timeouts). Timeouts are particularly tricky in that they might not be monotonically
-/// scheduled... TBD
+/// The server has 2 neighbours: the top-of-rack switch and the outside world. Each packet in
+/// flight has a timeout, scheduled on the `Merger` and cancelled when the packet is acked.
 #[derive(Debug)]
 pub struct Server {
     /// Unique ID for the server
     pub id: usize,
 
     bandwidth_gbps: u64,
-    latency_ns: Time,
+    latency: Time,
 
     merger: Merger<Time, NetworkEvent>,
-    out_queues: Vec<Producer<ModelEvent>>,
 
     _ix_to_id: Vec<usize>,
 
     tor_time: Time,
-    timeouts: MinHeap<Timeout>,
+    timeouts: HashMap<(usize, usize), TimerId<Time>>,
 
     flows: Vec<tcp::Flow>,
+    records: Option<Sender<FlowRecord>>,
 
     count: u64,
 }
@@ -217,6 +214,11 @@ impl Server {
         println!(" Server {} done", self.id);
         self.count
     }
+
+    /// Number of flows started here that aren't done yet
+    pub fn flows_in_progress(&self) -> usize {
+        self.flows.iter().filter(|f| f.record().is_none()).count()
+    }
 }
 
 impl Advancer<Time, u64> for Server {
@@ -224,8 +226,6 @@ impl Advancer<Time, u64> for Server {
         //info!(log, "start...");
         //println!(" Server {} advance", self.id);
 
-        let tor_q = &self.out_queues[1];
-
         // TODO figure out this whole loop thing?
         //for event in self.merger {
         while let Some(event) = self.merger.next() {
@@ -237,59 +237,12 @@ impl Advancer<Time, u64> for Server {
             );
             */
             match event.event_type {
-                EventType::Close => {
-                    // ensure everyone ignores us from now until close
-                    for out_q in self.out_queues.iter() {
-                        out_q
-                            .push(Event {
-                                event_type: EventType::Close,
-                                //real_time: start.elapsed().as_nanos(),
-                                src: self.id,
-                                time: event.time + self.latency_ns,
-                            }) // add latency to avoid violating in-order invariant
-                            .unwrap();
-                    }
-
-                    break;
-                }
+                // The simulation ends at the engine's end time, nobody sends these
+                EventType::Close => unreachable!(),
 
-                EventType::Stalled => {
-                    // TODO how on earth do we tell ourselves to move forward??
-                    // min timeout of 100us
-                    /*
-                    if self_time <= event.time {
-                        //let cur_time = std::cmp::max(event.time, out_time);
-                        self.out_queues[0]
-                            .push(Event {
-                                event_type: EventType::Null,
-                                src: self.id,
-                                time: event.time + 10_000,
-                            })
-                            .unwrap();
-                        //self.count += 1;
-
-                        self.out_times[0] = event.time;
-                    }
-                    */
-
-                    // ToR
-                    if self.tor_time < event.time {
-                        tor_q
-                            .push(Event {
-                                event_type: EventType::Null,
-                                src: self.id,
-                                time: event.time + self.latency_ns,
-                            })
-                            .unwrap();
-                        //self.count += 1;
-
-                        self.tor_time = event.time;
-                    }
-
-                    // We're stalled, return so that we can be rescheduled later
-                    //println!(" Server {} stall", self.id);
-                    return ActorState::Continue(event.time);
-                }
+                // We're stalled, the merger already updated the ToR, return so that we can be
+                // rescheduled later
+                EventType::Stalled => return ActorState::Continue(event.time),
 
                 EventType::Null => {} //unreachable!(),
 
@@ -299,41 +252,9 @@ impl Advancer<Time, u64> for Server {
                     // both of these might schedule packets and timeouts
                     let (packets, timeouts) = match net_event {
                         // TIMEOUT ==============================
-                        NetworkEvent::Timeout => {
-                            // TODO process ties in one go?
-                            // See if we can process any timeouts
-                            let mut res = (vec![], vec![]);
-                            if let Some(Reverse((t, flow_id, seq_num))) = self.timeouts.peek() {
-                                // process (should always be == or >)
-                                if *t <= event.time {
-                                    // Get packets and timeout to send
-                                    //print!("@{} ", event.time);
-                                    res = self.flows.get_mut(*flow_id).unwrap().timeout(*seq_num);
-
-                                    // advance the heap
-                                    self.timeouts.pop();
-                                }
-                            }
-
-                            // Schedule next timeout, default min_rto
-                            let mut timeout_event = Event {
-                                event_type: EventType::ModelEvent(NetworkEvent::Timeout),
-                                src: self.id,
-                                time: event.time + MIN_RTO,
-                            };
-
-                            // or next timeout if there's one before then...
-                            if let Some(Reverse((t, _, _))) = self.timeouts.peek() {
-                                if *t < timeout_event.time {
-                                    timeout_event.time = *t;
-                                }
-                            }
-
-                            // actually schedule the timeout
-                            self.out_queues[0].push(timeout_event).unwrap();
-
-                            // return our packets
-                            res
+                        NetworkEvent::Timeout(flow_id, seq_num) => {
+                            self.timeouts.remove(&(flow_id, seq_num));
+                            self.flows.get_mut(flow_id).unwrap().timeout(seq_num)
                         }
 
                         // FLOW =================================
@@ -355,6 +276,12 @@ impl Advancer<Time, u64> for Server {
                         // PACKET ===============================
                         NetworkEvent::Packet(mut packet) => {
                             if packet.is_ack {
+                                // no need to time it out anymore
+                                let key = (packet.flow_id, packet.seq_num);
+                                if let Some(timeout) = self.timeouts.remove(&key) {
+                                    self.merger.cancel(timeout);
+                                }
+
                                 let flow = self.flows.get_mut(packet.flow_id).unwrap();
                                 flow.src_receive(event.time, packet)
                             } else {
@@ -367,21 +294,22 @@ impl Advancer<Time, u64> for Server {
 
                                 // since we're only sending one packet, no timeout, skip to the next event
                                 let (tx_end, rx_end) = tx_rx_time(
-                                    self.tor_time,
+                                    std::cmp::max(event.time, self.tor_time),
                                     packet.size_byte,
-                                    self.latency_ns,
+                                    self.latency,
                                     self.bandwidth_gbps,
                                 );
 
-                                tor_q
-                                    .push(Event {
+                                self.merger.send(
+                                    TOR,
+                                    Event {
                                         event_type: EventType::ModelEvent(NetworkEvent::Packet(
                                             packet,
                                         )),
                                         src: self.id,
                                         time: rx_end,
-                                    })
-                                    .unwrap();
+                                    },
+                                );
 
                                 self.tor_time = tx_end;
                                 continue;
@@ -389,17 +317,18 @@ impl Advancer<Time, u64> for Server {
                         }
                     };
 
-                    // send the packets
+                    // send the packets, the link might have been idle until now
                     let mut tx_end = self.tor_time;
                     for p in packets {
                         /*let (tx_end, rx_end) = tx_rx_time(
                             self.tor_time,
                             p.size_byte,
-                            self.latency_ns,
+                            self.latency,
                             self.bandwidth_gbps,
                         );*/
-                        tx_end += p.size_byte * 8 / self.bandwidth_gbps;
-                        let rx_end = tx_end + self.latency_ns;
+                        tx_end = std::cmp::max(tx_end, event.time)
+                            + Time::transmission(p.size_byte, self.bandwidth_gbps);
+                        let rx_end = tx_end + self.latency;
 
                         let event = Event {
                             event_type: EventType::ModelEvent(NetworkEvent::Packet(p)),
@@ -407,21 +336,70 @@ impl Advancer<Time, u64> for Server {
                             time: rx_end,
                         };
 
-                        tor_q.push(event).unwrap();
+                        self.merger.send(TOR, event);
                     }
 
                     self.tor_time = tx_end;
 
                     // schedule the timeouts
                     for (delay, flow_id, seq_num) in timeouts {
-                        self.timeouts
-                            .push(Reverse((event.time + delay, flow_id, seq_num)));
+                        let timeout = self.merger.schedule_self(
+                            event.time + delay,
+                            NetworkEvent::Timeout(flow_id, seq_num),
+                        );
+                        if let Some(previous) = self.timeouts.insert((flow_id, seq_num), timeout) {
+                            self.merger.cancel(previous);
+                        }
                     }
                 }
             }
         }
 
         //println!("Server {} done. {} count", self.id, self.count);
-        ActorState::Done(self.count)
+        // the last packets might still be waiting for room
+        match self.merger.finish() {
+            None => ActorState::Done(self.count),
+            Some(time) => ActorState::Continue(time),
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<Time>> {
+        self.merger.last_delivered()
+    }
+
+    fn finish(&mut self) {
+        // report the flows we completed
+        if let Some(records) = &self.records {
+            for record in self.flows.iter().filter_map(|f| f.record()) {
+                records.send(record).unwrap();
+            }
+        }
+    }
+}
+
+impl Checkpoint<Time, u64> for Server {
+    fn merger(&mut self) -> &mut dyn Pausable<Time> {
+        &mut self.merger
+    }
+
+    fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+        save_state(
+            out,
+            &(self.tor_time, &self.timeouts, &self.flows, self.count),
+        )
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+        let (tor_time, timeouts, flows, count) = load_state(input)?;
+        self.tor_time = tor_time;
+        self.timeouts = timeouts;
+        self.flows = flows;
+        self.count = count;
+
+        Ok(())
     }
 }
@@ -0,0 +1,217 @@
+//! Session module
+
+use crate::{
+    push_flows, write_records, DeviceIds, FlowDesc, FlowRecord, ModelEvent, Results, Router,
+    Server, Time,
+};
+use rustasim::spsc::Producer;
+use rustasim::{
+    ActorPanic, ActorState, Advancer, Checkpoint, CheckpointError, Delivered, Driver, Event,
+    EventType, Pausable,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::mpsc::Receiver;
+
+/// Any device of the datacenter, as a [`Session`](struct.Session.html) holds them
+// boxed by the session anyway
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Device {
+    /// A server
+    Server(Server),
+
+    /// A switch
+    Router(Router),
+}
+
+impl Advancer<Time, u64> for Device {
+    fn advance(&mut self) -> ActorState<Time, u64> {
+        match self {
+            Device::Server(server) => server.advance(),
+            Device::Router(router) => router.advance(),
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        match self {
+            Device::Server(server) => server.driven_by(driver),
+            Device::Router(router) => router.driven_by(driver),
+        }
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<Time>> {
+        match self {
+            Device::Server(server) => server.last_delivered(),
+            Device::Router(router) => router.last_delivered(),
+        }
+    }
+
+    fn finish(&mut self) {
+        match self {
+            Device::Server(server) => server.finish(),
+            Device::Router(router) => router.finish(),
+        }
+    }
+}
+
+impl Checkpoint<Time, u64> for Device {
+    fn merger(&mut self) -> &mut dyn Pausable<Time> {
+        match self {
+            Device::Server(server) => server.merger(),
+            Device::Router(router) => router.merger(),
+        }
+    }
+
+    fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+        match self {
+            Device::Server(server) => server.save(out),
+            Device::Router(router) => router.save(out),
+        }
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+        match self {
+            Device::Server(server) => server.load(input),
+            Device::Router(router) => router.load(input),
+        }
+    }
+}
+
+/// A simulation run bit by bit, see [`World::session`](struct.World.html#method.session)
+///
+/// Each [`run_until`](#method.run_until) takes the devices up to a time and leaves them there,
+/// to look at how they're doing and add flows before carrying on. The flows are only given to the
+/// servers as the simulation gets to them.
+#[derive(Debug)]
+pub struct Session {
+    session: rustasim::Session<Time, u64, Device>,
+    ids: DeviceIds,
+
+    /// Communication channels from us (the world) to the devices
+    chans: HashMap<usize, Producer<ModelEvent>>,
+
+    /// Flows the simulation didn't get to yet
+    flows: Vec<(Time, FlowDesc)>,
+
+    /// Completed flows, reported by the servers
+    records: Receiver<FlowRecord>,
+
+    done: Time,
+}
+
+impl Session {
+    pub(crate) fn new(
+        session: rustasim::Session<Time, u64, Device>,
+        ids: DeviceIds,
+        chans: HashMap<usize, Producer<ModelEvent>>,
+        flows: Vec<(Time, FlowDesc)>,
+        records: Receiver<FlowRecord>,
+        done: Time,
+    ) -> Session {
+        let mut session = Session {
+            session,
+            ids,
+            chans,
+            flows: Vec::new(),
+            records,
+            done,
+        };
+        session.add_flows(flows);
+
+        session
+    }
+
+    /// Runs the simulation up to `time`, or to the end if that's later
+    ///
+    /// Returns whether all the devices are done. If a device panics, the panic is returned
+    /// instead and the session can't carry on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is earlier than the previous run went up to.
+    pub fn run_until(&mut self, time: Time) -> Result<bool, ActorPanic> {
+        let time = std::cmp::min(time, self.done);
+
+        // the rest waits for the next run
+        let later = self.flows.partition_point(|&(start, _)| start < time);
+        let flows = self.flows.drain(..later).collect();
+        push_flows(&self.chans, flows);
+        for c in self.chans.values() {
+            c.push(Event {
+                time,
+                src: 0,
+                event_type: EventType::Null,
+            })
+            .expect("World queues are unbounded");
+        }
+
+        let (done, _) = self.session.run_until(time)?;
+        Ok(done)
+    }
+
+    /// Runs the simulation to the end, and writes the completed flows to stdout like
+    /// [`World::start`](struct.World.html#method.start)
+    ///
+    /// What each device returned comes back by id.
+    pub fn finish(mut self) -> Result<Results, ActorPanic> {
+        push_flows(&self.chans, std::mem::take(&mut self.flows));
+        for c in self.chans.values() {
+            c.push(Event {
+                time: self.done,
+                src: 0,
+                event_type: EventType::Null,
+            })
+            .expect("World queues are unbounded");
+        }
+
+        let (counts, _) = self.session.finish()?;
+        write_records(&self.records);
+        Ok(self.ids.results(counts.into_iter().enumerate()))
+    }
+
+    /// Adds flows, from now on
+    ///
+    /// # Panics
+    ///
+    /// Panics if a flow starts before the time the simulation is at.
+    pub fn add_flows(&mut self, flows: Vec<(Time, FlowDesc)>) {
+        let now = self.time();
+        for (time, flow) in flows {
+            assert!(
+                time >= now,
+                "Flow {:?} can't start at {}, the simulation is already at {}",
+                flow,
+                time,
+                now
+            );
+            let at = self.flows.partition_point(|&(start, _)| start <= time);
+            self.flows.insert(at, (time, flow));
+        }
+    }
+
+    /// Time the simulation is at
+    pub fn time(&self) -> Time {
+        self.session.time()
+    }
+
+    /// The servers that aren't done yet
+    pub fn servers(&self) -> impl Iterator<Item = &Server> {
+        self.session
+            .actors()
+            .filter_map(|(_, device)| match device {
+                Device::Server(server) => Some(server),
+                Device::Router(_) => None,
+            })
+    }
+
+    /// The routers that aren't done yet
+    pub fn routers(&self) -> impl Iterator<Item = &Router> {
+        self.session
+            .actors()
+            .filter_map(|(_, device)| match device {
+                Device::Router(router) => Some(router),
+                Device::Server(_) => None,
+            })
+    }
+}
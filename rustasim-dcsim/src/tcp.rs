@@ -1,6 +1,7 @@
 //! Implements a basic version of TCP
 
 use crate::Time;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 /// Contains the timeout time, flow_id and seq_num
@@ -10,13 +11,13 @@ pub type Timeout = (Time, usize, usize);
 pub const BYTES_PER_PACKET: u64 = 1500;
 
 /// Smallest RTO
-pub const MIN_RTO: Time = 2_000_000;
+pub const MIN_RTO: Time = Time::from_ns(2_000_000);
 
 /// Describes a TCP/IP packet
 ///
 /// The two protocols are merged together. Although not technically accurate, it is rare for TCP
 /// packets to be split, at least not in datacenter networks.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Packet {
     /// ID of the packet's source
     pub src: usize,
@@ -38,16 +39,78 @@ pub struct Packet {
 
     ///// How many more hops can this packet go?
     //pub ttl: usize,
-    /// When was this packet originally created, in ns
-    pub sent_ns: Time,
+    /// When was this packet originally created
+    pub sent: Time,
 }
 
 // flow_id, src, dst, size_bytes
 /// Short description of a flow object
 pub type FlowDesc = (usize, usize, u64);
 
+/// Summary of a completed flow, one line of the simulation output
+///
+/// Records are ordered by completion time first, then by source and destination, which gives the
+/// output a stable order no matter which server finished first in real time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FlowRecord {
+    /// When the last byte was acknowledged
+    pub end: Time,
+
+    /// ID of the originating server
+    pub src: usize,
+
+    /// ID of the destination server
+    pub dst: usize,
+
+    /// When the flow started
+    pub start: Time,
+
+    /// Flow size in bytes
+    pub size_byte: u64,
+}
+
+impl FlowRecord {
+    /// CSV header matching the `Display` output
+    pub const CSV_HEADER: &'static str = "src,dst,start,end,size_byte,fct_ns";
+}
+
+impl std::fmt::Display for FlowRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{src},{dst},{start},{end},{size_byte},{fct}",
+            src = self.src,
+            dst = self.dst,
+            size_byte = self.size_byte,
+            start = self.start,
+            end = self.end,
+            fct = self.end - self.start,
+        )
+    }
+}
+
+impl std::str::FromStr for FlowRecord {
+    type Err = String;
+
+    /// Reads a record back from its `Display` output
+    fn from_str(s: &str) -> Result<Self, String> {
+        let bad = |e: String| format!("Bad flow record {}: {}", s, e);
+        let fields: Vec<&str> = s.split(',').collect();
+        match fields[..] {
+            [src, dst, start, end, size_byte, _] => Ok(FlowRecord {
+                end: end.parse().map_err(bad)?,
+                src: src.parse().map_err(|e| bad(format!("{}", e)))?,
+                dst: dst.parse().map_err(|e| bad(format!("{}", e)))?,
+                start: start.parse().map_err(bad)?,
+                size_byte: size_byte.parse().map_err(|e| bad(format!("{}", e)))?,
+            }),
+            _ => Err(bad(format!("{} fields", fields.len()))),
+        }
+    }
+}
+
 /// Flow data structure
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Flow {
     /// ID of the flow
     pub flow_id: usize,
@@ -60,6 +123,7 @@ pub struct Flow {
     size_byte: u64,
 
     start: Time,
+    end: Option<Time>,
 
     cwnd: usize,
     outstanding: usize,
@@ -79,7 +143,8 @@ impl Flow {
             dst,
 
             size_byte,
-            start: 0,
+            start: Time::ZERO,
+            end: None,
 
             cwnd: 30,
             outstanding: 0,
@@ -91,6 +156,17 @@ impl Flow {
         }
     }
 
+    /// Returns the flow's summary if it has completed
+    pub fn record(&self) -> Option<FlowRecord> {
+        self.end.map(|end| FlowRecord {
+            end,
+            src: self.src,
+            dst: self.dst,
+            start: self.start,
+            size_byte: self.size_byte,
+        })
+    }
+
     /// Computes the current timeout
     fn rto(&self) -> Time {
         MIN_RTO
@@ -108,7 +184,7 @@ impl Flow {
             is_ack: false,
 
             //ttl: 10,
-            sent_ns: 0,
+            sent: Time::ZERO,
         }
     }
 
@@ -139,15 +215,7 @@ impl Flow {
             self.outstanding -= 1;
             self.n_acked += 1;
             if self.n_acked * BYTES_PER_PACKET >= self.size_byte {
-                println!(
-                    "{src},{dst},{start},{end},{size_byte},{fct}",
-                    src = self.src,
-                    dst = self.dst,
-                    size_byte = self.size_byte,
-                    start = self.start,
-                    end = time,
-                    fct = time - self.start,
-                );
+                self.end = Some(time);
             }
             //self.cwnd += 1/self.cwnd;
         }
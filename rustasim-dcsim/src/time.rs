@@ -0,0 +1,199 @@
+//! Simulated time, in picoseconds
+//!
+//! At 400 Gbps a 10 byte ACK is on the wire for 200 ps, which whole nanoseconds round down to
+//! nothing. Time is therefore kept in picoseconds, which still leaves over 200 days of simulation,
+//! and the arithmetic panics rather than wrapping around if it ever gets there.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
+
+const PS_PER_NS: u64 = 1_000;
+const PS_PER_SEC: u64 = 1_000_000_000_000;
+
+/// A point in simulated time, or a duration, in picoseconds
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Time(u64);
+
+impl Time {
+    /// The start of the simulation
+    pub const ZERO: Time = Time(0);
+
+    /// The furthest time there is
+    pub const MAX: Time = Time(u64::MAX);
+
+    /// `ps` picoseconds
+    pub const fn from_ps(ps: u64) -> Time {
+        Time(ps)
+    }
+
+    /// `ns` nanoseconds
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is past [`MAX`](#associatedconstant.MAX).
+    pub const fn from_ns(ns: u64) -> Time {
+        match ns.checked_mul(PS_PER_NS) {
+            Some(ps) => Time(ps),
+            None => panic!("Time overflow"),
+        }
+    }
+
+    /// `secs` seconds, to the closest picosecond, `None` if it is negative or past
+    /// [`MAX`](#associatedconstant.MAX)
+    pub fn try_from_secs_f64(secs: f64) -> Option<Time> {
+        let ps = (secs * PS_PER_SEC as f64).round();
+        if ps >= 0. && ps < u64::MAX as f64 {
+            Some(Time(ps as u64))
+        } else {
+            None
+        }
+    }
+
+    /// How long `size_byte` bytes take to go through a `bandwidth_gbps` link
+    ///
+    /// This is exact for any bandwidth that divides 8000 Gbps, it is rounded up to the next
+    /// picosecond otherwise, so that nothing ever goes through in no time.
+    pub fn transmission(size_byte: u64, bandwidth_gbps: u64) -> Time {
+        let bits = size_byte.checked_mul(8).expect("Time overflow");
+        Time::from_ns(bits).div_ceil(bandwidth_gbps)
+    }
+
+    /// This many picoseconds
+    pub const fn as_ps(self) -> u64 {
+        self.0
+    }
+
+    /// This many whole nanoseconds
+    pub const fn as_ns(self) -> u64 {
+        self.0 / PS_PER_NS
+    }
+
+    /// This many seconds
+    pub fn as_secs_f64(self) -> f64 {
+        self.0 as f64 / PS_PER_SEC as f64
+    }
+
+    /// A `1/n`th of this, rounded up
+    fn div_ceil(self, n: u64) -> Time {
+        Time(self.0.div_ceil(n))
+    }
+}
+
+impl Add for Time {
+    type Output = Time;
+
+    fn add(self, other: Time) -> Time {
+        Time(self.0.checked_add(other.0).expect("Time overflow"))
+    }
+}
+
+impl AddAssign for Time {
+    fn add_assign(&mut self, other: Time) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Time {
+    type Output = Time;
+
+    fn sub(self, other: Time) -> Time {
+        Time(self.0.checked_sub(other.0).expect("Time underflow"))
+    }
+}
+
+impl num::Zero for Time {
+    fn zero() -> Time {
+        Time::ZERO
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Nanoseconds, with the picoseconds as decimals if there are any
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 % PS_PER_NS {
+            0 => write!(f, "{}", self.as_ns()),
+            ps => write!(f, "{}.{:03}", self.as_ns(), ps),
+        }
+    }
+}
+
+/// Plain picoseconds, as they appear in traces
+impl fmt::Debug for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Nanoseconds, as written by `Display`
+impl FromStr for Time {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Time, String> {
+        let bad = || format!("Bad time {}", s);
+        let (ns, ps) = match s.split_once('.') {
+            Some((ns, ps)) if ps.len() == 3 => (ns, ps.parse::<u64>().map_err(|_| bad())?),
+            Some(_) => return Err(bad()),
+            None => (s, 0),
+        };
+        let ns = ns.parse::<u64>().map_err(|_| bad())?;
+
+        ns.checked_mul(PS_PER_NS)
+            .and_then(|ps_of_ns| ps_of_ns.checked_add(ps))
+            .map(Time)
+            .ok_or_else(bad)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::time::*;
+
+    #[test]
+    fn test_transmission() {
+        // too short for nanoseconds
+        assert_eq!(Time::transmission(10, 400), Time::from_ps(200));
+        assert_eq!(Time::transmission(1500, 400), Time::from_ns(30));
+        assert_eq!(Time::transmission(1500, 10), Time::from_ns(1200));
+
+        // 8000 bits over 3 Gbps, rounded up
+        assert_eq!(Time::transmission(1000, 3), Time::from_ps(2_666_667));
+    }
+
+    #[test]
+    fn test_text() {
+        for time in [
+            Time::ZERO,
+            Time::from_ns(1200),
+            Time::from_ps(51_200),
+            Time::from_ps(7),
+        ] {
+            assert_eq!(time.to_string().parse(), Ok(time));
+        }
+        assert_eq!(Time::from_ps(51_200).to_string(), "51.200");
+        assert_eq!(Time::from_ps(7).to_string(), "0.007");
+        assert!("1.5".parse::<Time>().is_err());
+    }
+
+    #[test]
+    fn test_secs() {
+        assert_eq!(
+            Time::try_from_secs_f64(0.0005),
+            Some(Time::from_ns(500_000))
+        );
+        assert_eq!(Time::try_from_secs_f64(-1.), None);
+        assert_eq!(Time::try_from_secs_f64(1e9), None);
+        assert_eq!(Time::from_ns(500_000).as_secs_f64(), 0.0005);
+    }
+
+    #[test]
+    #[should_panic(expected = "Time overflow")]
+    fn test_overflow() {
+        let _ = Time::MAX + Time::from_ps(1);
+    }
+}
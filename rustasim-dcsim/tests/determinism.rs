@@ -0,0 +1,131 @@
+use std::process::Command;
+
+/// Runs the simulator binary on `flow_file` and returns its stdout
+///
+/// `executor` picks how it runs, e.g. `["--cores", "2"]` or `["--sequential"]`.
+fn run(flow_file: &str, seed: u64, executor: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rustasim-dcsim"))
+        .args(["--fc", "4", "--limit", "0.0005", "--flows", flow_file])
+        .args(["--seed", &seed.to_string()])
+        .args(executor)
+        .output()
+        .expect("Failed to run the simulator");
+
+    assert!(output.status.success(), "Simulator failed: {:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn same_output_regardless_of_cores() {
+    // 12 hosts, some flows going through the other racks to exercise ECMP
+    let mut flows = String::new();
+    for i in 0..60_u64 {
+        let src = (i * 7) % 12;
+        let dst = (i * 5 + 3) % 12;
+        if src == dst {
+            continue;
+        }
+        let size_byte = 1_500 * (1 + (i * 13) % 40);
+        let time = i * 1_000;
+        flows.push_str(&format!("{} {} {} {}\n", src, dst, size_byte, time));
+    }
+
+    let flow_file = std::env::temp_dir().join(format!("rustasim-flows-{}.txt", std::process::id()));
+    std::fs::write(&flow_file, flows).unwrap();
+    let flow_file = flow_file.to_str().unwrap();
+
+    let reference = run(flow_file, 42, &["--cores", "1"]);
+    assert!(reference.lines().count() > 1, "No flow finished");
+
+    for cores in &["2", "4", "8"] {
+        let output = run(flow_file, 42, &["--cores", cores]);
+        assert_eq!(output, reference, "{} cores differ from 1 core", cores);
+    }
+
+    let output = run(flow_file, 42, &["--cores", "4", "--scheduler", "priority"]);
+    assert_eq!(output, reference, "priority scheduler differs from 1 core");
+
+    let output = run(flow_file, 42, &["--cores", "1", "--scheduler", "priority"]);
+    assert_eq!(output, reference, "priority scheduler on 1 core differs");
+
+    let output = run(flow_file, 42, &["--cores", "4", "--queues", "segmented"]);
+    assert_eq!(output, reference, "segmented queues differ from 1 core");
+
+    let output = run(flow_file, 42, &["--cores", "3", "--partition", "--pin"]);
+    assert_eq!(output, reference, "partitioned workers differ from 1 core");
+
+    let output = run(
+        flow_file,
+        42,
+        &["--cores", "3", "--scheduler", "priority", "--partition"],
+    );
+    assert_eq!(output, reference, "partitioned priority workers differ");
+
+    let output = run(flow_file, 42, &["--cores", "3", "--window"]);
+    assert_eq!(output, reference, "windows differ from 1 core");
+
+    let output = run(flow_file, 42, &["--cores", "3", "--window", "--partition"]);
+    assert_eq!(output, reference, "partitioned windows differ from 1 core");
+
+    let output = run(flow_file, 42, &["--cores", "3", "--watchdog", "60"]);
+    assert_eq!(output, reference, "watched workers differ from 1 core");
+
+    let output = run(flow_file, 42, &["--sequential"]);
+    assert_eq!(output, reference, "sequential executor differs from 1 core");
+
+    let output = run(flow_file, 42, &["--cores", "1", "--processes", "3"]);
+    assert_eq!(output, reference, "3 processes differ from 1 core");
+
+    std::fs::remove_file(flow_file).unwrap();
+}
+
+#[test]
+fn same_output_after_resuming() {
+    let mut flows = String::new();
+    for i in 0..40_u64 {
+        let src = (i * 5) % 12;
+        let dst = (i * 7 + 1) % 12;
+        if src == dst {
+            continue;
+        }
+        let size_byte = 1_500 * (1 + (i * 11) % 30);
+        let time = i * 5_000;
+        flows.push_str(&format!("{} {} {} {}\n", src, dst, size_byte, time));
+    }
+
+    let tmp_dir = std::env::temp_dir();
+    let flow_file = tmp_dir.join(format!("rustasim-resume-flows-{}.txt", std::process::id()));
+    std::fs::write(&flow_file, flows).unwrap();
+    let flow_file = flow_file.to_str().unwrap();
+    let checkpoint = tmp_dir.join(format!("rustasim-resume-{}.bin", std::process::id()));
+    let checkpoint = checkpoint.to_str().unwrap();
+
+    let reference = run(flow_file, 7, &["--cores", "2"]);
+    assert!(reference.lines().count() > 1, "No flow finished");
+
+    for at in &["0.00005", "0.0002", "0.00045"] {
+        let saved = [
+            "--cores",
+            "3",
+            "--checkpoint",
+            checkpoint,
+            "--checkpoint-at",
+            at,
+        ];
+        let output = run(flow_file, 7, &saved);
+        assert_eq!(
+            output, reference,
+            "carrying on after a checkpoint at {}",
+            at
+        );
+
+        let output = run(flow_file, 7, &["--cores", "2", "--resume", checkpoint]);
+        assert_eq!(output, reference, "resuming from a checkpoint at {}", at);
+
+        let output = run(flow_file, 7, &["--sequential", "--resume", checkpoint]);
+        assert_eq!(output, reference, "resuming sequentially from {}", at);
+    }
+
+    std::fs::remove_file(flow_file).unwrap();
+    std::fs::remove_file(checkpoint).unwrap();
+}
@@ -0,0 +1,85 @@
+use rustasim_dcsim::{connect, Executor, Network, SimConfig, Time, World};
+
+/// Two servers on a switch, server 1 sends 15kB to server 2 from time 0, run on `n_workers`
+fn single_rack(n_workers: usize) -> World {
+    let config = SimConfig {
+        latency: Time::from_ns(1_000),
+        executor: Executor::Parallel(n_workers),
+        ..SimConfig::default()
+    };
+
+    let mut net = Network::new();
+    net.insert(1, vec![]);
+    net.insert(2, vec![]);
+    net.insert(3, vec![]);
+    connect(&mut net, 1, 3);
+    connect(&mut net, 2, 3);
+
+    let mut world = World::new_from_network(net, &config, 2);
+    world.add_flows(vec![(Time::ZERO, (1, 2, 15_000))]);
+    world
+}
+
+#[test]
+fn run_inspect_inject() {
+    let done = Time::from_ns(100_000);
+    let mut session = single_rack(2).session(done);
+
+    // the flow takes 17_216ns
+    assert!(!session.run_until(Time::from_ns(5_000)).unwrap());
+    assert_eq!(session.time(), Time::from_ns(5_000));
+    let in_progress = |session: &rustasim_dcsim::Session, id| {
+        let server = session.servers().find(|s| s.id == id).unwrap();
+        server.flows_in_progress()
+    };
+    assert_eq!(in_progress(&session, 1), 1);
+
+    // packets are lined up behind each other on the way to server 2
+    let router = session.routers().next().unwrap();
+    let delays = router.queue_delays(session.time());
+    assert_eq!(
+        delays.iter().map(|&(id, _)| id).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert!(delays[1].1 > Time::ZERO);
+
+    // the same flow back, later on
+    session.add_flows(vec![(Time::from_ns(30_000), (2, 1, 15_000))]);
+    session.run_until(Time::from_ns(20_000)).unwrap();
+    assert_eq!(in_progress(&session, 1), 0);
+    assert_eq!(in_progress(&session, 2), 0);
+
+    session.run_until(Time::from_ns(35_000)).unwrap();
+    assert_eq!(in_progress(&session, 2), 1);
+
+    // one flow each way, each server sends one and acks the other
+    let results = session.finish().unwrap();
+    assert_eq!(results.servers.values().collect::<Vec<_>>(), vec![&21, &21]);
+    assert_eq!(results.routers[&3], 2 * 20);
+}
+
+#[test]
+#[should_panic(expected = "already at")]
+fn flow_in_the_past() {
+    let mut session = single_rack(1).session(Time::from_ns(100_000));
+    session.run_until(Time::from_ns(5_000)).unwrap();
+    session.add_flows(vec![(Time::from_ns(1_000), (2, 1, 15_000))]);
+}
+
+#[test]
+fn more_flows_than_a_queue_holds() {
+    // all pushed before the run, none starts before it's done
+    let later: Vec<_> = (0..20_000)
+        .map(|i| (Time::from_ns(200_000 + i), (2, 1, 1_500)))
+        .collect();
+
+    let mut world = single_rack(2);
+    world.add_flows(later.clone());
+    let results = world.start(Time::from_ns(100_000)).unwrap();
+    assert_eq!(results.servers[&1], 1 + 10);
+
+    let mut world = single_rack(2);
+    world.add_flows(later);
+    let results = world.session(Time::from_ns(100_000)).finish().unwrap();
+    assert_eq!(results.servers[&1], 1 + 10);
+}
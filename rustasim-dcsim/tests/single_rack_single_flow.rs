@@ -1,17 +1,14 @@
-use rustasim_dcsim::{connect, ActorResult, Network, SimConfig, Topology, World};
+use rustasim_dcsim::{connect, Executor, Network, SimConfig, Time, World};
 
 #[test]
 fn single_flow() {
-    #[allow(clippy::zero_prefixed_literal)]
-    let time_limit: u64 = 16_020; // exact fct is 16_016
+    let time_limit = Time::from_ns(17_220); // exact fct is 17_216
 
     let config = SimConfig {
-        time_limit,                            // bogus
-        topology: Topology::FullyConnected(1), // bogus
-        flow_file: "".to_string(),             // bogus
-
-        bandwidth_gbps: 10,
-        latency_ns: 1_000,
+        time_limit,
+        latency: Time::from_ns(1_000),
+        executor: Executor::Parallel(1),
+        ..SimConfig::default()
     };
 
     // build simple network
@@ -26,14 +23,17 @@ fn single_flow() {
     let mut world = World::new_from_network(net, &config, n_hosts);
 
     // single flow
-    let mut flows = Vec::new();
-    flows.push((0, (1, 2, 15_000)));
+    let flows = vec![(Time::ZERO, (1, 2, 15_000))];
     world.add_flows(flows);
 
-    let counts = world.start(1, config.time_limit);
+    let results = world.start(config.time_limit).unwrap();
+
+    println!("{:?}", results);
+    assert_eq!(results.n_devices(), 3);
+    assert_eq!(results.total(), 1 + 10 + 20 + 10);
 
-    let sum_count = counts.iter().sum::<ActorResult>();
-    println!("{:?}", counts);
-    assert_eq!(counts.len(), 3);
-    assert_eq!(sum_count, 1 + 10 + 20 + 10);
+    // the sender starts the flow and gets the acks, the switch sees everything
+    assert_eq!(results.servers[&1], 1 + 10);
+    assert_eq!(results.servers[&2], 10);
+    assert_eq!(results.routers[&3], 20);
 }
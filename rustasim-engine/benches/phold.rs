@@ -1,12 +1,12 @@
 //! PHOLD model
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 
 use rand::distributions::{Distribution, Uniform};
 use rand_distr::Exp;
-use rustasim::spsc;
-use rustasim::spsc::{Consumer, Producer};
-use rustasim::{ActorState, Advancer, Event, EventType, Merger};
+use rustasim::{
+    ActorState, Advancer, Driver, Event, EventType, GraphBuilder, Merger, Node, Scheduler,
+};
 use std::time::Instant;
 
 type Time = u64;
@@ -19,18 +19,13 @@ const LOOKAHEAD: Time = 1 as Time * T_MULT;
 /// Generic event
 type PHOLDEvent = ();
 
-type FullEvent = rustasim::Event<Time, PHOLDEvent>;
-
 /// PHOLD actor
 #[derive(Debug)]
 struct Actor {
-    pub id: usize,
-    time_limit: Time,
     //last_stall: Time,
     unif: Uniform<usize>,
 
     merger: Merger<Time, PHOLDEvent>,
-    out_queues: Vec<Producer<FullEvent>>,
     out_times: Vec<Time>,
 
     //_ix_to_id: Vec<usize>,
@@ -38,19 +33,14 @@ struct Actor {
 }
 
 impl Actor {
-    fn new(
-        id: usize,
-        out_queues: Vec<Producer<FullEvent>>,
-        in_queues: Vec<Consumer<FullEvent>>,
-        time_limit: Time,
-    ) -> Actor {
-        let mut _ix_to_id = Vec::new();
+    fn new(node: Node<Time, PHOLDEvent>) -> Actor {
+        let id = node.id;
         let mut out_times = Vec::new();
-        for (ix, q) in out_queues.iter().enumerate() {
-            _ix_to_id.push(ix);
+        let mut merger = node.merger;
+        for (dst, q) in node.outputs {
             out_times.push(0);
 
-            if ix == id {
+            if dst == id {
                 // send ourselves an initial event
                 //for _ in 0..out_queues.len() {
                 for _ in 0..100 {
@@ -61,25 +51,17 @@ impl Actor {
                     })
                     .unwrap();
                 }
-            } else {
-                // initialize everyone else
-                q.push(Event {
-                    event_type: EventType::Null,
-                    src: id,
-                    time: LOOKAHEAD,
-                })
-                .unwrap();
             }
+
+            // the merger takes care of the null-messages
+            merger = merger.output(q, LOOKAHEAD);
         }
 
         Actor {
-            id,
-            time_limit,
             //last_stall: 0,
-            unif: Uniform::from(0..out_queues.len()),
+            unif: Uniform::from(0..out_times.len()),
 
-            merger: Merger::new(in_queues, id, _ix_to_id),
-            out_queues,
+            merger,
             out_times,
 
             //_ix_to_id,
@@ -95,44 +77,13 @@ impl Advancer<Time, Res> for Actor {
         while let Some(mut event) = self.merger.next() {
             //println!("{}: {:?}", self.id, event);
 
-            if event.time > self.time_limit {
-                println!("{} done", self.id);
-                for chan in &self.out_queues {
-                    chan.push(Event {
-                        event_type: EventType::Close,
-                        src: self.id,
-                        time: event.time,
-                    })
-                    .unwrap();
-                }
-                break;
-            }
-
             let mut rng = rand::thread_rng();
             let exp = Exp::new(1.0).unwrap();
             match event.event_type {
+                // the engine ends the run at the time limit
                 EventType::Close => unreachable!(),
                 EventType::Null => unreachable!(),
-                EventType::Stalled => {
-                    for (dst_ix, out_time) in self.out_times.iter_mut().enumerate() {
-                        // equal because they might just need a jog, blocking happens in the
-                        // iterator, so no infinite loop risk
-                        if *out_time < event.time {
-                            //let cur_time = std::cmp::max(event.time, out_time);
-                            self.out_queues[dst_ix]
-                                .push(Event {
-                                    event_type: EventType::Null,
-                                    src: self.id,
-                                    time: event.time + LOOKAHEAD,
-                                })
-                                .unwrap();
-
-                            *out_time = event.time;
-                        }
-                    }
-
-                    return ActorState::Continue(event.time);
-                }
+                EventType::Stalled => return ActorState::Continue(event.time),
                 EventType::ModelEvent(_) => {
                     self.count += 1;
                     // pick a destination, time
@@ -145,78 +96,44 @@ impl Advancer<Time, Res> for Actor {
                     event.time = dst_time + LOOKAHEAD;
 
                     // send event
-                    self.out_queues[dst_ix].push(event).unwrap();
+                    self.merger.send(dst_ix, event);
                     self.out_times[dst_ix] = dst_time;
                 }
             }
         }
 
-        ActorState::Done(self.count)
-    }
-}
-
-/// Transposes incoming rectangular 2d array
-///
-/// # Examples
-/// ```
-/// use rustasim::phold::transpose;
-/// let v = vec![vec![1, 2, 3], vec![4, 5, 6]];
-/// let t = transpose(v);
-///
-/// let expected = vec![vec![1, 4], vec![2, 5], vec![3, 6]];
-/// assert_eq!(t, expected);
-/// ```
-fn transpose<T>(in_vector: Vec<Vec<T>>) -> Vec<Vec<T>> {
-    let mut result: Vec<Vec<T>> = Vec::new();
-
-    // initialize the columns
-    for _ in 0..in_vector[0].len() {
-        result.push(Vec::new());
-    }
-
-    for mut col in in_vector {
-        for (i, element) in col.drain(..).enumerate() {
-            result[i].push(element);
+        // the last events might still be waiting for room
+        match self.merger.finish() {
+            None => ActorState::Done(self.count),
+            Some(time) => ActorState::Continue(time),
         }
     }
 
-    result
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
 }
 
 /// Builds and runs a PHOLD model as described by the passed arguments
-fn run(n_actors: usize, mut time_limit: Time, n_threads: usize) {
+fn run(n_actors: usize, mut time_limit: Time, n_threads: usize, scheduler: Scheduler) {
     time_limit *= T_MULT;
     println!("Setup...");
 
-    // Queues
-    let mut out_queues = Vec::new();
-    let mut in_queues = Vec::new();
-    for _ in 0..n_actors {
-        // The ins and outs for each actor
-        let mut outs = Vec::new();
-        let mut ins = Vec::new();
-
-        for _ in 0..n_actors {
-            // we need self loops
-            let (prod, cons) = spsc::new(Q_SIZE);
-            outs.push(prod);
-            ins.push(cons);
+    // Queues, everyone is linked to everyone, self loops included
+    let mut graph = GraphBuilder::new().capacity(Q_SIZE);
+    for id in 0..n_actors {
+        graph.add_actor(id);
+    }
+    for src in 0..n_actors {
+        for dst in 0..n_actors {
+            graph.link(src, dst);
         }
-
-        out_queues.push(outs);
-        in_queues.push(ins);
     }
 
-    // transpose consumers they're on the other end of (src, dst)
-    let mut in_queues = transpose(in_queues);
-
     // Actors
     let mut actors = Vec::new();
-    for id in 0..n_actors {
-        let outs = out_queues.pop().unwrap();
-        let ins = in_queues.pop().unwrap();
-
-        let a = Actor::new(id, outs, ins, time_limit); // TODO
+    for node in graph.build() {
+        let a = Actor::new(node);
         actors.push(Box::new(a) as Box<dyn Advancer<Time, Res> + Send>);
     }
 
@@ -224,7 +141,8 @@ fn run(n_actors: usize, mut time_limit: Time, n_threads: usize) {
 
     println!("Run...");
     let start = Instant::now();
-    let counts = rustasim::start(num_cpus::get() - 1, actors);
+    let (counts, report) =
+        rustasim::start_with(scheduler, n_threads, actors, Some(time_limit)).unwrap();
     let duration = start.elapsed();
 
     // stats...
@@ -244,24 +162,40 @@ fn run(n_actors: usize, mut time_limit: Time, n_threads: usize) {
     );
     println!(
         "  {:.3}M count/sec, {:.3}M /actors, {:.3}M /thread",
-        (1e6 / ns_per_count as f64),
+        (1e6 / ns_per_count),
         (1e6 / (ns_per_count * n_actors as f64)),
         (1e6 / (ns_per_count * n_threads as f64)),
     );
     println!(
         "  {:.1} ns/count, {:.1} ns/actor, {:.1} ns/thread",
-        ns_per_count / 1000. as f64,
+        ns_per_count / 1000_f64,
         ns_per_count * n_actors as f64 / 1000.,
         ns_per_count * n_threads as f64 / 1000.,
     );
 
+    let total = report.total();
+    println!(
+        "  {} events, {} nulls sent, {} stalls, {:.1}% idle",
+        total.events,
+        total.nulls_sent,
+        total.stalls,
+        100. * report.idle_fraction(),
+    );
+
     println!("done");
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let n_cpus = num_cpus::get() - 1;
+    let n_cpus = std::cmp::max(1, num_cpus::get() - 1);
     println!("hey?");
-    c.bench_function("fib 20", |b| b.iter(|| run(7 * 8 * 4, 0_000_100, n_cpus)));
+    #[allow(clippy::zero_prefixed_literal)]
+    c.bench_function("phold fifo", |b| {
+        b.iter(|| run(7 * 8 * 4, 0_000_100, n_cpus, Scheduler::Fifo))
+    });
+    #[allow(clippy::zero_prefixed_literal)]
+    c.bench_function("phold priority", |b| {
+        b.iter(|| run(7 * 8 * 4, 0_000_100, n_cpus, Scheduler::Priority))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,603 @@
+//! Actors written as `async` functions
+//!
+//! Rather than a state machine in [`Advancer::advance`](trait.Advancer.html), an
+//! [`AsyncActor`](struct.AsyncActor.html) is an `async` block awaiting its events from a
+//! [`Ctx`](struct.Ctx.html), which owns its `Merger`. Sub-protocols, a handshake for instance, are
+//! `async` functions of their own taking the `Ctx`. The executors drive it like any other actor:
+//! every `advance` polls it once, and it gives the hand back whenever its `Merger` stalls. It may
+//! therefore only await its `Ctx`, or futures that do, nothing would ever wake it up otherwise.
+//!
+//! Delays and timeouts are [wake-ups](struct.Merger.html#method.wake_at) of the `Merger`, which
+//! hands them out in order with the events of the inputs. They work with any executor but the
+//! optimistic one, which has no timers.
+
+use crate::engine::{Event, EventType, Merger, TIMER};
+use crate::failure::Delivered;
+use crate::worker::{ActorState, Advancer, Driver};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+thread_local! {
+    /// Time at which the actor being polled stalled, if it did
+    static STALLED: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+}
+
+/// Gives the hand back once, the worker polls again when it advances the actor next
+struct Suspend(bool);
+
+impl Future for Suspend {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            Poll::Pending
+        }
+    }
+}
+
+/// Has the actor give the hand back, stalled at `time`
+async fn stall<T: 'static>(time: T) {
+    STALLED.with(|stalled| *stalled.borrow_mut() = Some(Box::new(time)));
+    Suspend(false).await
+}
+
+/// What the actor and its `Ctx` tell each other about the `Merger`, which the future holds
+#[derive(Debug)]
+struct Shared<T> {
+    // for the `Merger` to pick up the next time it's used
+    driver: Option<Driver>,
+
+    // last event the `Merger` handed out, in case the actor panics
+    last: Option<Delivered<T>>,
+}
+
+/// An actor written as an `async` block, see the module documentation
+pub struct AsyncActor<T, R> {
+    id: usize,
+    future: Pin<Box<dyn Future<Output = R> + Send>>,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T, R> Debug for AsyncActor<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncActor").field("id", &self.id).finish()
+    }
+}
+
+impl<T, R> AsyncActor<T, R>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    /// Builds the actor out of what `body` returns, given a `Ctx` over `merger`
+    ///
+    /// The actor is done with whatever the future returns, it should
+    /// [`finish`](struct.Ctx.html#method.finish) first.
+    pub fn new<U, F, Fut>(merger: Merger<T, U>, body: F) -> Self
+    where
+        U: Debug,
+        F: FnOnce(Ctx<T, U>) -> Fut,
+        Fut: 'static + Future<Output = R> + Send,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            driver: None,
+            last: None,
+        }));
+        AsyncActor {
+            id: merger.id(),
+            shared: Arc::clone(&shared),
+            future: Box::pin(body(Ctx {
+                merger,
+                shared,
+                now: T::zero(),
+                held: None,
+                waiting: VecDeque::new(),
+            })),
+        }
+    }
+}
+
+impl<T, R> Advancer<T, R> for AsyncActor<T, R>
+where
+    T: 'static + Ord + Copy + num::Zero,
+{
+    fn advance(&mut self) -> ActorState<T, R> {
+        let mut cx = Context::from_waker(Waker::noop());
+        match self.future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => ActorState::Done(result),
+            Poll::Pending => {
+                let stalled = STALLED.with(|stalled| stalled.borrow_mut().take());
+                let stalled = stalled.expect("Async actors may only await their `Ctx`");
+                ActorState::Continue(*stalled.downcast().unwrap())
+            }
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.shared.lock().driver = Some(driver);
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<T>> {
+        self.shared.lock().last
+    }
+}
+
+/// What an [`AsyncActor`](struct.AsyncActor.html) gets its events from and sends them with
+#[derive(Debug)]
+pub struct Ctx<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    merger: Merger<T, U>,
+    shared: Arc<Mutex<Shared<T>>>,
+    now: T,
+
+    // pulled from the `Merger` past a deadline, handed out once we get there
+    held: Option<Event<T, U>>,
+
+    // arrived during a delay, handed out first
+    waiting: VecDeque<Event<T, U>>,
+}
+
+impl<T, U> Ctx<T, U>
+where
+    U: Debug,
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    /// Id of the actor, as given to its `Merger`
+    pub fn id(&self) -> usize {
+        self.merger.id()
+    }
+
+    /// Time of the latest event handed out, or of the end of the latest delay or timeout
+    pub fn now(&self) -> T {
+        self.now
+    }
+
+    /// The `Merger`, to attach and retire links
+    pub fn merger(&mut self) -> &mut Merger<T, U> {
+        if let Some(driver) = self.shared.lock().driver.take() {
+            self.merger.driven_by(driver);
+        }
+        &mut self.merger
+    }
+
+    /// Next event from the `Merger`, noted for the actor in case it panics
+    fn next_from_merger(&mut self) -> Option<Event<T, U>> {
+        let mut shared = self.shared.lock();
+        if let Some(driver) = shared.driver.take() {
+            self.merger.driven_by(driver);
+        }
+        let event = self.merger.next();
+        shared.last = self.merger.last_delivered();
+        event
+    }
+
+    /// Sends `event` on output `ix`, see [`Merger::send`](struct.Merger.html#method.send)
+    pub fn send(&mut self, ix: usize, event: Event<T, U>) {
+        self.merger().send(ix, event);
+    }
+
+    /// Next event, a model event or `Close`
+    ///
+    /// At the [end of the run](engine/index.html#end-of-the-run), where the `Merger` has nothing left to
+    /// give, it is a `Close` at the end time from [`TIMER`](constant.TIMER.html).
+    ///
+    /// # Panics
+    ///
+    /// Panics once the actor [finished](#method.finish).
+    pub async fn next(&mut self) -> Event<T, U> {
+        match self.waiting.pop_front() {
+            Some(event) => event,
+            None => self.pull(None).await.unwrap(),
+        }
+    }
+
+    /// Next event if it comes before `deadline`, `None` once nothing can anymore
+    ///
+    /// Timing out takes the actor to `deadline`. Events at the deadline itself come after.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the actor is run by the optimistic executor.
+    pub async fn next_before(&mut self, deadline: T) -> Option<Event<T, U>> {
+        if let Some(event) = self.waiting.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.pull(Some(deadline)).await;
+        if event.is_none() {
+            self.now = std::cmp::max(self.now, deadline);
+        }
+        event
+    }
+
+    /// Waits until `delay` after now, see [`sleep_until`](#method.sleep_until)
+    pub async fn sleep(&mut self, delay: T) {
+        self.sleep_until(self.now + delay).await
+    }
+
+    /// Waits until `time`
+    ///
+    /// Events that arrive in the meantime are kept for the next calls to
+    /// [`next`](#method.next) and [`next_before`](#method.next_before), in order, with the time
+    /// they arrived at: they are in the past by then. A `Close` ends the wait early, nothing comes
+    /// after it. It panics like `next_before`.
+    pub async fn sleep_until(&mut self, time: T) {
+        while let Some(event) = self.pull(Some(time)).await {
+            let close = matches!(event.event_type, EventType::Close);
+            self.waiting.push_back(event);
+            if close {
+                return;
+            }
+        }
+        self.now = std::cmp::max(self.now, time);
+    }
+
+    /// Stops taking events and waits for everything sent to make it out, see
+    /// [`Merger::finish`](struct.Merger.html#method.finish)
+    pub async fn finish(&mut self) {
+        while let Some(time) = self.merger().finish() {
+            stall(time).await;
+        }
+    }
+
+    /// Next event from the `Merger` before `deadline` if there's one, giving the hand back
+    /// whenever it stalls
+    async fn pull(&mut self, deadline: Option<T>) -> Option<Event<T, U>> {
+        if let Some(event) = self.held.take() {
+            return self.hand_out(event, deadline);
+        }
+
+        // nothing comes before now, and the `Merger` can't stall past the wake-up
+        let wake_up = match deadline {
+            Some(deadline) if deadline < self.now => return None,
+            Some(deadline) => Some(self.merger().wake_at(deadline)),
+            None => None,
+        };
+
+        let event = loop {
+            let event = match self.next_from_merger() {
+                Some(event) => event,
+                None => Event {
+                    time: self.merger.end().expect("The actor finished already"),
+                    src: TIMER,
+                    event_type: EventType::Close,
+                },
+            };
+
+            match event.event_type {
+                EventType::Stalled if event.src == TIMER => break None,
+                EventType::Stalled => stall(event.time).await,
+                _ => break self.hand_out(event, deadline),
+            }
+        };
+
+        if let Some(wake_up) = wake_up {
+            self.merger.cancel(wake_up);
+        }
+        event
+    }
+
+    /// `event` if it comes before `deadline`, held until we get there otherwise
+    fn hand_out(&mut self, event: Event<T, U>, deadline: Option<T>) -> Option<Event<T, U>> {
+        if deadline.is_some_and(|deadline| event.time >= deadline) {
+            self.held = Some(event);
+            return None;
+        }
+
+        self.now = std::cmp::max(self.now, event.time);
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_actor::*;
+    use crate::{spsc, start_sequential, start_windowed, start_with, Partition, Scheduler};
+
+    const LOOKAHEAD: u64 = 1;
+
+    /// Replies to each request after working on it for `work`, drops the first of those it's
+    /// told to
+    async fn server(mut ctx: Ctx<u64, u64>, work: u64, mut dropped: Vec<u64>) -> u64 {
+        let mut n_replies = 0;
+        loop {
+            let event = ctx.next().await;
+            match event.event_type {
+                EventType::ModelEvent(n) if dropped.contains(&n) => dropped.retain(|&d| d != n),
+                EventType::ModelEvent(n) => {
+                    ctx.sleep(work).await;
+                    let reply = Event {
+                        time: ctx.now() + LOOKAHEAD,
+                        src: ctx.id(),
+                        event_type: EventType::ModelEvent(n),
+                    };
+                    ctx.send(0, reply);
+                    n_replies += 1;
+                }
+                _ => break,
+            }
+        }
+
+        ctx.finish().await;
+        n_replies
+    }
+
+    /// Sends request `n` until it gets the reply, trying again after `timeout`
+    async fn request(ctx: &mut Ctx<u64, u64>, n: u64, timeout: u64) -> u64 {
+        loop {
+            let request = Event {
+                time: ctx.now() + LOOKAHEAD,
+                src: ctx.id(),
+                event_type: EventType::ModelEvent(n),
+            };
+            ctx.send(0, request);
+
+            let deadline = ctx.now() + timeout;
+            while let Some(reply) = ctx.next_before(deadline).await {
+                if let EventType::ModelEvent(m) = reply.event_type {
+                    if m == n {
+                        return reply.time;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes `n_requests` requests one after the other, returns when it got the last reply
+    async fn client(mut ctx: Ctx<u64, u64>, n_requests: u64, timeout: u64) -> u64 {
+        for n in 0..n_requests {
+            request(&mut ctx, n, timeout).await;
+        }
+
+        let close = Event {
+            time: ctx.now() + LOOKAHEAD,
+            src: ctx.id(),
+            event_type: EventType::Close,
+        };
+        ctx.send(0, close);
+        ctx.finish().await;
+        ctx.now()
+    }
+
+    /// Makes a request every `period` until the end of the run, returns the number of replies
+    async fn ticker(mut ctx: Ctx<u64, u64>, period: u64) -> u64 {
+        let mut n_replies = 0;
+        for n in 0.. {
+            let request = Event {
+                time: ctx.now() + LOOKAHEAD,
+                src: ctx.id(),
+                event_type: EventType::ModelEvent(n),
+            };
+            ctx.send(0, request);
+
+            ctx.sleep(period).await;
+            while let Some(event) = ctx.next_before(ctx.now()).await {
+                match event.event_type {
+                    EventType::ModelEvent(_) => n_replies += 1,
+                    _ => {
+                        ctx.finish().await;
+                        return n_replies;
+                    }
+                }
+            }
+        }
+
+        unreachable!()
+    }
+
+    /// A client and a server, with their links
+    fn actors(
+        work: u64,
+        dropped: Vec<u64>,
+        timeout: u64,
+    ) -> Vec<Box<dyn Advancer<u64, u64> + Send>> {
+        let (to_server, from_client) = spsc::new(4);
+        let (to_client, from_server) = spsc::new(4);
+        let client_merger = Merger::new(vec![from_server], 0, vec![1]).output(to_server, LOOKAHEAD);
+        let server_merger = Merger::new(vec![from_client], 1, vec![0]).output(to_client, LOOKAHEAD);
+
+        vec![
+            Box::new(AsyncActor::new(client_merger, move |ctx| {
+                client(ctx, 10, timeout)
+            })),
+            Box::new(AsyncActor::new(server_merger, move |ctx| {
+                server(ctx, work, dropped)
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            for n_cpus in [1, 2] {
+                // a round trip is both ways and the work: 10 of them
+                let (mut results, _) =
+                    start_with(scheduler, n_cpus, actors(2, vec![], 100), None).unwrap();
+                results.sort();
+                assert_eq!(results, vec![10, 10 * (LOOKAHEAD + 2 + LOOKAHEAD)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            for n_cpus in [1, 2] {
+                // two requests are dropped, each costs a timeout from when it was sent
+                let (mut results, _) =
+                    start_with(scheduler, n_cpus, actors(2, vec![3, 7], 20), None).unwrap();
+                results.sort();
+                assert_eq!(results, vec![10, 10 * (LOOKAHEAD + 2 + LOOKAHEAD) + 2 * 20]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_end() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            for n_cpus in [1, 2] {
+                let (to_server, from_client) = spsc::new(4);
+                let (to_client, from_server) = spsc::new(4);
+                let client_merger =
+                    Merger::new(vec![from_server], 0, vec![1]).output(to_server, LOOKAHEAD);
+                let server_merger =
+                    Merger::new(vec![from_client], 1, vec![0]).output(to_client, LOOKAHEAD);
+                let actors: Vec<Box<dyn Advancer<u64, u64> + Send>> = vec![
+                    Box::new(AsyncActor::new(client_merger, |ctx| ticker(ctx, 10))),
+                    Box::new(AsyncActor::new(server_merger, |ctx| server(ctx, 2, vec![]))),
+                ];
+
+                // requests from 0 to 90, the last reply is back at 94
+                let (results, _) = start_with(scheduler, n_cpus, actors, Some(100)).unwrap();
+                assert_eq!(results, vec![10, 10]);
+            }
+        }
+    }
+
+    /// Sends an event every 100, far ahead of when it sends it, then closes
+    ///
+    /// Output 0 goes to the relay, output 1 loops back to itself.
+    async fn pacer(mut ctx: Ctx<u64, u64>) -> u64 {
+        for n in 0..10 {
+            ctx.sleep(100).await;
+            let event = Event {
+                time: ctx.now() + 100,
+                src: ctx.id(),
+                event_type: EventType::ModelEvent(n),
+            };
+            ctx.send(0, event);
+        }
+
+        let close = Event {
+            time: ctx.now() + 100,
+            src: ctx.id(),
+            event_type: EventType::Close,
+        };
+        ctx.send(0, close);
+        ctx.finish().await;
+        0
+    }
+
+    /// Passes on what it gets, and ticks whenever nothing came for `period`
+    async fn relay(mut ctx: Ctx<u64, u64>, period: u64) -> u64 {
+        let mut n_ticks = 0;
+        loop {
+            let event_type = match ctx.next_before(ctx.now() + period).await {
+                Some(event) if matches!(event.event_type, EventType::Close) => break,
+                Some(event) => event.event_type,
+                None => {
+                    n_ticks += 1;
+                    EventType::ModelEvent(n_ticks)
+                }
+            };
+            let event = Event {
+                time: ctx.now() + LOOKAHEAD,
+                src: ctx.id(),
+                event_type,
+            };
+            ctx.send(0, event);
+        }
+
+        let close = Event {
+            time: ctx.now() + LOOKAHEAD,
+            src: ctx.id(),
+            event_type: EventType::Close,
+        };
+        ctx.send(0, close);
+        ctx.finish().await;
+        n_ticks
+    }
+
+    /// Counts the events it gets, checking that nothing, null-messages included, goes back in time
+    #[derive(Debug)]
+    struct Recorder {
+        input: spsc::Consumer<Event<u64, u64>>,
+        time: u64,
+        n_events: u64,
+    }
+
+    impl Advancer<u64, u64> for Recorder {
+        fn advance(&mut self) -> ActorState<u64, u64> {
+            while let Ok(event) = self.input.pop() {
+                assert!(
+                    event.time >= self.time,
+                    "Got {:?} after {}",
+                    event,
+                    self.time
+                );
+                self.time = event.time;
+                match event.event_type {
+                    EventType::ModelEvent(_) => self.n_events += 1,
+                    EventType::Close => return ActorState::Done(self.n_events),
+                    _ => {}
+                }
+            }
+
+            ActorState::Continue(self.time)
+        }
+
+        fn driven_by(&mut self, _: Driver) {}
+    }
+
+    /// A pacer sending to a relay which ticks in between, and a recorder reading what the relay
+    /// sends
+    fn relayed() -> Vec<Box<dyn Advancer<u64, u64> + Send>> {
+        let (to_relay, from_pacer) = spsc::new(4);
+        let (to_pacer, from_itself) = spsc::new(4);
+        let (to_recorder, from_relay) = spsc::new(4);
+        let pacer_merger = Merger::new(vec![from_itself], 0, vec![0])
+            .output(to_relay, 100)
+            .output(to_pacer, 100);
+        let relay_merger = Merger::new(vec![from_pacer], 1, vec![0]).output(to_recorder, LOOKAHEAD);
+        let recorder = Recorder {
+            input: from_relay,
+            time: 0,
+            n_events: 0,
+        };
+
+        vec![
+            Box::new(AsyncActor::new(pacer_merger, pacer)),
+            Box::new(AsyncActor::new(relay_merger, |ctx| relay(ctx, 7))),
+            Box::new(recorder),
+        ]
+    }
+
+    #[test]
+    fn test_timeout_before_stall() {
+        // the pacer has the relay stall far past its timeouts, the recorder pops the null-messages
+        // it sends then as they come
+        let (expected, _) = start_with(Scheduler::Fifo, 1, relayed(), None).unwrap();
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            for n_cpus in [1, 2] {
+                let (results, _) = start_with(scheduler, n_cpus, relayed(), None).unwrap();
+                assert_eq!(results, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_other_executors() {
+        // the timeouts are timers, which every executor but the optimistic one hands out
+        let expected = vec![10, 10 * (LOOKAHEAD + 2 + LOOKAHEAD) + 2 * 20];
+        let mut sequential = start_sequential(actors(2, vec![3, 7], 20), None);
+        sequential.sort();
+        assert_eq!(sequential, expected);
+
+        let partition = Partition::round_robin(2, 2);
+        let (mut windowed, _) =
+            start_windowed(&partition, actors(2, vec![3, 7], 20), None).unwrap();
+        windowed.sort();
+        assert_eq!(windowed, expected);
+    }
+}
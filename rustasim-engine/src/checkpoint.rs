@@ -0,0 +1,412 @@
+//! Saves a running simulation to disk and resumes it later
+//!
+//! A checkpoint is taken at a simulated time: every actor's `Merger` holds back the events from
+//! that time on, the workers stop once all the actors are held, and their states are written in
+//! the order the actors were given. The cut is consistent, everything before the checkpoint time
+//! has been processed and nothing after it has.
+//!
+//! Actors opt in by implementing [`Checkpoint`](trait.Checkpoint.html). The engine saves the
+//! `Merger` itself along with the events waiting in its input queues, the actor only saves its own
+//! state. Resuming builds the actors the same way as for the original run, and loads the saved
+//! states into them.
+
+use crate::failure::Delivered;
+use crate::worker::{ActorState, Advancer, Driver};
+use crate::{start_with, ActorPanic, Report, Scheduler};
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Identifies checkpoint files
+const MAGIC: [u8; 8] = *b"rustasim";
+
+/// Bumped whenever the layout of checkpoint files changes
+const VERSION: u32 = 2;
+
+/// Error while writing or reading a checkpoint
+pub type CheckpointError = bincode::Error;
+
+/// An actor that can be saved to disk and restored
+///
+/// The restored actor must be built the same way as the saved one, connected to the same
+/// neighbours in the same order: only what changes over the course of the simulation is saved.
+pub trait Checkpoint<T, R>: Advancer<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// The `Merger` the actor gets its events from
+    fn merger(&mut self) -> &mut dyn Pausable<T>;
+
+    /// Writes the state of the actor, see [`save_state`](fn.save_state.html)
+    fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError>;
+
+    /// Reads back what [`save`](#tymethod.save) wrote, see [`load_state`](fn.load_state.html)
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError>;
+}
+
+/// A `Merger` as seen by the checkpoints
+pub trait Pausable<T> {
+    /// Holds back the events from `time` on, until called again with a later time or `None`
+    ///
+    /// The actor gets `Stalled` events in the meantime.
+    fn pause_at(&mut self, time: Option<T>);
+
+    /// Whether the actor won't do anything more until the pause is lifted
+    fn is_paused(&self) -> bool;
+
+    /// Writes the state of the merger, including the events waiting in its input queues
+    fn save(&mut self, out: &mut dyn Write) -> Result<(), CheckpointError>;
+
+    /// Reads back what [`save`](#tymethod.save) wrote
+    ///
+    /// Events already in the input queues are dropped, the restored ones are delivered before
+    /// anything pushed from then on.
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError>;
+}
+
+/// Writes `state` for [`load_state`](fn.load_state.html) to read back
+pub fn save_state<S: Serialize + ?Sized>(
+    out: &mut dyn Write,
+    state: &S,
+) -> Result<(), CheckpointError> {
+    bincode::serialize_into(out, state)
+}
+
+/// Reads back a state written by [`save_state`](fn.save_state.html)
+pub fn load_state<S: DeserializeOwned>(input: &mut dyn Read) -> Result<S, CheckpointError> {
+    bincode::deserialize_from(input)
+}
+
+/// Boxed checkpointable actor
+pub type CheckpointActor<T, R> = Box<dyn Checkpoint<T, R> + Send>;
+
+/// Actors still running after a checkpoint, in their original order, and the results of those
+/// that were done before it, along with their index
+pub type Resumed<T, R> = (Vec<CheckpointActor<T, R>>, Vec<(usize, R)>);
+
+/// Where the actors are handed back once paused, by their original index
+type Parked<A> = Arc<Mutex<Vec<Option<Box<A>>>>>;
+
+/// Actors paused, by index, and the results of those that are done, along with their index
+pub(crate) type Paused<A, R> = (Vec<Option<Box<A>>>, Vec<(usize, R)>, Report);
+
+/// Advances an actor until its merger is paused, then hands it back
+struct Pausing<T, A: ?Sized> {
+    ix: usize,
+    time: Option<T>,
+    actor: Option<Box<A>>,
+    paused: Parked<A>,
+}
+
+impl<T, A: ?Sized> Debug for Pausing<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pausing").field("ix", &self.ix).finish()
+    }
+}
+
+impl<T, R, A> Advancer<T, (usize, Option<R>)> for Pausing<T, A>
+where
+    T: Ord + Copy + num::Zero,
+    A: ?Sized + Checkpoint<T, R>,
+{
+    fn advance(&mut self) -> ActorState<T, (usize, Option<R>)> {
+        let actor = self.actor.as_mut().unwrap();
+        actor.merger().pause_at(self.time);
+
+        match actor.advance() {
+            ActorState::Done(result) => ActorState::Done((self.ix, Some(result))),
+            ActorState::Continue(time) => {
+                if !actor.merger().is_paused() {
+                    return ActorState::Continue(time);
+                }
+
+                self.paused.lock()[self.ix] = self.actor.take();
+                ActorState::Done((self.ix, None))
+            }
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.actor.as_mut().unwrap().driven_by(driver);
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<T>> {
+        self.actor.as_ref().and_then(|actor| actor.last_delivered())
+    }
+
+    // only those still here are done, the others are paused
+    fn finish(&mut self) {
+        if let Some(actor) = self.actor.as_mut() {
+            actor.finish();
+        }
+    }
+}
+
+/// Runs the actors up to `time`, or to the end without one, on `num_cpus` workers
+///
+/// The run ends at `end` if given, like with [`start_with`](../fn.start_with.html). Actors are
+/// given along with their index. Those that get there are handed back paused, by
+/// index, the others come back with their result.
+pub(crate) fn pause<T, R, A>(
+    scheduler: Scheduler,
+    num_cpus: usize,
+    actors: Vec<(usize, Box<A>)>,
+    n_actors: usize,
+    time: Option<T>,
+    end: Option<T>,
+) -> Result<Paused<A, R>, ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    R: 'static + Send,
+    A: 'static + ?Sized + Checkpoint<T, R> + Send,
+{
+    let paused: Parked<A> = Arc::new(Mutex::new((0..n_actors).map(|_| None).collect()));
+
+    let mut pausing = Vec::new();
+    for (ix, actor) in actors {
+        let actor = Pausing {
+            ix,
+            time,
+            actor: Some(actor),
+            paused: Arc::clone(&paused),
+        };
+        pausing.push(Box::new(actor) as Box<dyn Advancer<T, (usize, Option<R>)> + Send>);
+    }
+
+    let (results, report) = start_with(scheduler, num_cpus, pausing, end)?;
+    let results = results
+        .into_iter()
+        .filter_map(|(ix, result)| result.map(|result| (ix, result)))
+        .collect();
+    let paused = std::mem::take(&mut *paused.lock());
+
+    Ok((paused, results, report))
+}
+
+/// Runs the actors up to `time`, and saves them to `path`
+///
+/// The actors are run on `num_cpus` workers scheduled by `scheduler`, with the run ending at `end`
+/// if given. They are handed back paused at `time` and can carry on with
+/// [`start_with`](fn.start_with.html), given the same `end`. The file is only replaced
+/// once the new checkpoint is complete, a crash while writing it leaves the previous one intact.
+///
+/// Actors that got past `time` before the call aren't brought back to it, checkpoints of the same
+/// simulation must be taken at increasing times. An actor panicking on the way there is an error.
+pub fn checkpoint<T, R>(
+    scheduler: Scheduler,
+    num_cpus: usize,
+    actors: Vec<CheckpointActor<T, R>>,
+    time: T,
+    end: Option<T>,
+    path: &Path,
+) -> Result<Resumed<T, R>, CheckpointError>
+where
+    T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    R: 'static + Send + Serialize,
+{
+    let n_actors = actors.len();
+    let (mut paused, done, _) = pause(
+        scheduler,
+        num_cpus,
+        actors.into_iter().enumerate().collect(),
+        n_actors,
+        Some(time),
+        end,
+    )
+    .map_err(|panic| bincode::ErrorKind::Custom(panic.to_string()))?;
+
+    let mut results: Vec<Option<R>> = (0..n_actors).map(|_| None).collect();
+    for (ix, result) in done {
+        results[ix] = Some(result);
+    }
+
+    // write it all next to the previous checkpoint, and only then replace it
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut out = BufWriter::new(File::create(&tmp_path)?);
+    save_state(&mut out, &(MAGIC, VERSION, n_actors))?;
+    for (result, actor) in results.iter().zip(&mut paused) {
+        save_state(&mut out, result)?;
+        if let Some(actor) = actor {
+            actor.merger().save(&mut out)?;
+            actor.save(&mut out)?;
+        }
+    }
+    out.flush()?;
+    out.get_ref().sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // everyone can carry on
+    let mut running = Vec::new();
+    for mut actor in paused.into_iter().flatten() {
+        actor.merger().pause_at(None);
+        running.push(actor);
+    }
+
+    let done = results
+        .into_iter()
+        .enumerate()
+        .filter_map(|(ix, result)| result.map(|result| (ix, result)))
+        .collect();
+    Ok((running, done))
+}
+
+/// Restores actors saved by [`checkpoint`](fn.checkpoint.html) in `path`
+///
+/// `actors` must be built the same way as the ones that were saved, in the same order. The events
+/// sent to them in the meantime are dropped, those that were in flight at the checkpoint are
+/// restored instead.
+pub fn restore<T, R>(
+    actors: Vec<CheckpointActor<T, R>>,
+    path: &Path,
+) -> Result<Resumed<T, R>, CheckpointError>
+where
+    T: Ord + Copy + num::Zero,
+    R: DeserializeOwned,
+{
+    let mut input = BufReader::new(File::open(path)?);
+
+    let (magic, version, n_actors): ([u8; 8], u32, usize) = load_state(&mut input)?;
+    if magic != MAGIC || version != VERSION {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "{} is not a version {} checkpoint",
+            path.display(),
+            VERSION
+        ))));
+    }
+    if n_actors != actors.len() {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "{} has {} actors, {} were given",
+            path.display(),
+            n_actors,
+            actors.len()
+        ))));
+    }
+
+    let mut running = Vec::new();
+    let mut results = Vec::new();
+    for (ix, mut actor) in actors.into_iter().enumerate() {
+        // the actors that were done only left their result
+        if let Some(result) = load_state(&mut input)? {
+            results.push((ix, result));
+            continue;
+        }
+
+        actor.merger().load(&mut input)?;
+        actor.load(&mut input)?;
+        running.push(actor);
+    }
+
+    Ok((running, results))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checkpoint::*;
+    use crate::fixtures::{checkpointed_burst, upcast};
+    use crate::start;
+
+    /// Carries on with the actors still running, and puts all the results back in order
+    fn carry_on(actors: Vec<CheckpointActor<u64, u64>>, done: Vec<(usize, u64)>) -> Vec<u64> {
+        let mut results = vec![None; actors.len() + done.len()];
+        for (ix, result) in done {
+            results[ix] = Some(result);
+        }
+
+        let mut running = start(2, upcast(actors), None).unwrap().0.into_iter();
+        for result in results.iter_mut().filter(|result| result.is_none()) {
+            *result = running.next();
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[test]
+    fn test_resume() {
+        let limit = 300;
+        let path = std::env::temp_dir().join(format!("rustasim-ckpt-{}", std::process::id()));
+
+        for &capacity in &[2, 1024] {
+            let expected = start(2, upcast(checkpointed_burst(limit, capacity)), None)
+                .unwrap()
+                .0;
+
+            for &time in &[1, 150, 1_000] {
+                // carrying on after the checkpoint
+                let (actors, done) = checkpoint(
+                    Scheduler::Fifo,
+                    2,
+                    checkpointed_burst(limit, capacity),
+                    time,
+                    None,
+                    &path,
+                )
+                .unwrap();
+                let results = carry_on(actors, done);
+                assert_eq!(
+                    results, expected,
+                    "capacity {}, carry on at {}",
+                    capacity, time
+                );
+
+                // from the file
+                let (actors, done) = restore(checkpointed_burst(limit, capacity), &path).unwrap();
+                let results = carry_on(actors, done);
+                assert_eq!(
+                    results, expected,
+                    "capacity {}, restore at {}",
+                    capacity, time
+                );
+            }
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_actors() {
+        let path = std::env::temp_dir().join(format!("rustasim-ckpt-wrong-{}", std::process::id()));
+        checkpoint(
+            Scheduler::Fifo,
+            2,
+            checkpointed_burst(100, 16),
+            50,
+            None,
+            &path,
+        )
+        .unwrap();
+
+        let mut actors = checkpointed_burst(100, 16);
+        actors.pop();
+        assert!(restore(actors, &path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_next_to_other_files() {
+        // the temporary file doesn't replace the extension
+        let path = std::env::temp_dir().join(format!("rustasim-ckpt-{}.a", std::process::id()));
+        let other = path.with_extension("tmp");
+        fs::write(&other, "not ours").unwrap();
+
+        checkpoint(
+            Scheduler::Fifo,
+            2,
+            checkpointed_burst(100, 16),
+            50,
+            None,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&other).unwrap(), "not ours");
+        assert!(restore(checkpointed_burst(100, 16), &path).is_ok());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! Ends a run at a given simulated time
+//!
+//! The executors take the end time of the run, and hand it to the `Merger`s through a
+//! thread-local of each worker. A `Merger` holds back the events from then on and, once nothing
+//! earlier can arrive anymore, stops returning events to its actor, see the
+//! [engine documentation](../engine/index.html#end-of-the-run).
+
+use std::any::Any;
+use std::cell::RefCell;
+
+thread_local! {
+    /// End time of the run the current thread works on, if it has one
+    static END: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+}
+
+/// Runs `work` with `end` as the end time of the `Merger`s it advances
+pub(crate) fn with<T: 'static, R>(end: Option<T>, work: impl FnOnce() -> R) -> R {
+    END.with(|e| *e.borrow_mut() = end.map(|end| Box::new(end) as Box<dyn Any>));
+    let result = work();
+    END.with(|e| *e.borrow_mut() = None);
+
+    result
+}
+
+/// End time of the run the current thread works on
+pub(crate) fn get<T: 'static + Copy>() -> Option<T> {
+    END.with(|end| {
+        end.borrow().as_ref().map(|end| {
+            *end.downcast_ref::<T>()
+                .expect("Merger and executor disagree on the time type")
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixtures::{finishing, ring};
+    use crate::{start_sequential, start_windowed, start_with, Partition, Scheduler};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_end() {
+        // the tokens go on forever, only the end of the run stops them
+        let expected = start_sequential(ring(7, 1_000), None);
+        let partition = Partition::round_robin(7, 3);
+        let results = vec![
+            start_sequential(ring(7, u64::MAX), Some(1_001)),
+            start_with(Scheduler::Fifo, 3, ring(7, u64::MAX), Some(1_001))
+                .unwrap()
+                .0,
+            start_with(Scheduler::Priority, 3, ring(7, u64::MAX), Some(1_001))
+                .unwrap()
+                .0,
+            start_windowed(&partition, ring(7, u64::MAX), Some(1_001))
+                .unwrap()
+                .0,
+        ];
+        for result in results {
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_finish() {
+        // every actor is finished once, whether it closed on its own or at the end of the run
+        let partition = Partition::round_robin(7, 3);
+        for &(limit, end) in &[(1_000, None), (u64::MAX, Some(1_001))] {
+            let finished = Arc::new(AtomicUsize::new(0));
+            start_sequential(finishing(ring(7, limit), &finished), end);
+            for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+                start_with(scheduler, 3, finishing(ring(7, limit), &finished), end).unwrap();
+            }
+            start_windowed(&partition, finishing(ring(7, limit), &finished), end).unwrap();
+            assert_eq!(finished.load(Ordering::SeqCst), 4 * 7);
+        }
+    }
+}
@@ -8,17 +8,102 @@
 //! sometimes causes a deadlock when two (or more) neighbours are waiting on each other.
 //!
 //! In order to resolve the deadlock, one can send "null-messages". Essentially telling neighbours
-//! that we have nothing to do. Fundamentally this is an actor-level action: there is no way the
-//! simulation engine knows enough about the simulation model to know which neighbours have to hear
-//! from us, or how long in the future it is safe for them to advance. Therefore the engine returns
-//! a "Stalled" event type at which point the actor *must* update all relevant neighbours in order
-//! for the simulation to progress.
+//! that we have nothing to do. The engine can't know on its own which neighbours have to hear from
+//! us, or how long in the future it is safe for them to advance. Actors therefore declare their
+//! output links along with their lookahead, the least delay between processing an event and
+//! anything it causes arriving on that link, see [`Merger::output`](struct.Merger.html#method.output).
+//! Whenever the actor stalls, its `Merger` sends a null-message on every output for which the
+//! receiver doesn't already know that much, and returns a "Stalled" event at which point the
+//! actor must give the hand back.
 //!
-// TODO description of when the null-message should be sent and what it should look like
-
+//! Actors may also keep their output queues to themselves and send null-messages by hand when they
+//! get a "Stalled" event. Nothing prevents mixing both.
+//!
+//! Sending on a full output doesn't fail: the `Merger` keeps the event until the receiver makes
+//! room, and has the actor give the hand back in the meantime, see
+//! [`Merger::send`](struct.Merger.html#method.send). Actors must therefore
+//! [`finish`](struct.Merger.html#method.finish) before they are done.
+//!
+//! A `Merger` can also hold back the events from a given time on, for the whole simulation to be
+//! saved at that time and resumed later, see [`checkpoint`](../fn.checkpoint.html).
+//!
+//! Links may come and go during the run. An input [attached](struct.Merger.html#method.attach)
+//! from a given time holds nothing back before that time, and one
+//! [retired](struct.Merger.html#method.retire) at a given time stops holding anything back once
+//! its sender got there. The sender does the same with
+//! [`add_output`](struct.Merger.html#method.add_output) and
+//! [`retire_output`](struct.Merger.html#method.retire_output).
+//!
+//! Actors schedule events for themselves with
+//! [`Merger::schedule_self`](struct.Merger.html#method.schedule_self) rather than through a link
+//! to themselves: the `Merger` keeps them in a future-event list of its own, in any order, and
+//! hands them out along with its inputs. They can be cancelled until then. Those that only need to
+//! wait until some time, without anything to schedule, ask for a
+//! [wake-up](struct.Merger.html#method.wake_at) instead.
+//!
+//! # End of the run
+//!
+//! Executors other than the optimistic one may be given an end time, see
+//! [`start`](../fn.start.html). Events from then on are held back, and once nothing earlier can
+//! arrive anymore each `Merger` stops returning events, and tells its receivers that it won't
+//! send anything else. The actor's loop over its events ends there, it
+//! [finishes](struct.Merger.html#method.finish) as usual without having to tell its neighbours,
+//! and the executor then calls its [`finish`](../trait.Advancer.html#method.finish) hook.
+//!
+//! # Event ordering
+//!
+//! For a simulation to be reproducible, every actor must see its events in the same order no
+//! matter how the actors are scheduled. The [`Merger`](struct.Merger.html) therefore orders events
+//! by `(time, source id, per-link sequence)`:
+//!
+//! 1. events are delivered in non-decreasing `time`,
+//! 2. ties in time are broken by the id of the source, lowest first,
+//! 3. ties from the same source are delivered in the order they were sent.
+//!
+//! Timers count as coming from the actor itself, before anything else it sent itself at the same
+//! time.
+//!
+//! The `Stalled` event obeys the same rule: an empty input is only known to not have anything
+//! before its last seen `(time, source id)`, so the actor stalls rather than deliver an event that
+//! this input could still precede. Null-messages take part in the ordering but are never
+//! delivered.
+//!
+use crate::checkpoint::{load_state, save_state, CheckpointError, Pausable};
+use crate::end;
+use crate::err::PushError;
+use crate::failure::Delivered;
+use crate::links::{self, LinkState};
+use crate::sequential;
 use crate::spsc;
-use std::cmp::Ordering;
-use std::mem;
+use crate::stats::{self, Stats};
+use crate::timewarp;
+use crate::tree::LoserTree;
+use crate::watchdog;
+use crate::window;
+use crate::worker::{Driver, Mode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+#[cfg(feature = "trace")]
+use crate::trace;
+
+/// Records what the `Merger` does when built with the `trace` feature, see the `trace` module
+///
+/// Otherwise the arguments are type-checked, but compiled away.
+macro_rules! trace {
+    ($action:ident, $($arg:expr),* $(,)?) => {
+        #[cfg(feature = "trace")]
+        trace::record(trace::Action::$action, $($arg),*);
+        #[cfg(not(feature = "trace"))]
+        if false {
+            let _ = ($($arg),*);
+        }
+    };
+}
 
 // TODO update description to match the parametrized Events we have
 /// Event types and their associated data.
@@ -31,12 +116,15 @@ use std::mem;
 /// associated actor, and are required for all simulations.
 ///
 /// The `Close` event type is sufficiently universal that it will presumably also stay here.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType<U> {
     /// Define `<U>` as you wish for your model
     ModelEvent(U),
 
-    /// The simulation is stalled, the actor must update its neighbours with null-events
+    /// The simulation is stalled, the actor must give the hand back
+    ///
+    /// Neighbours on the `Merger`'s outputs have already been sent null-events, the actor must
+    /// update the other ones itself.
     Stalled,
 
     /// It is safe for the simulaiton to advance to this time.
@@ -61,7 +149,7 @@ pub enum EventType<U> {
 /// Receivers should assume `src` is the *index* of the source, and not the id.
 ///
 /// Events are ordered by their time.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event<T, U>
 where
     T: Ord + Copy + num::Zero,
@@ -76,10 +164,52 @@ where
     pub event_type: EventType<U>,
 }
 
+impl<T: Ord + Copy + num::Zero, U> Event<T, U> {
+    /// The same event without its model data, to trace it once it's pushed
+    fn header(&self) -> Event<T, ()> {
+        Event {
+            time: self.time,
+            src: self.src,
+            event_type: match self.event_type {
+                EventType::ModelEvent(_) => EventType::ModelEvent(()),
+                EventType::Stalled => EventType::Stalled,
+                EventType::Null => EventType::Null,
+                EventType::Close => EventType::Close,
+            },
+        }
+    }
+}
+
+/// What the `src` of the events scheduled with
+/// [`Merger::schedule_self`](struct.Merger.html#method.schedule_self) is set to
+pub const TIMER: usize = usize::MAX;
+
+/// Identifies an event scheduled with
+/// [`Merger::schedule_self`](struct.Merger.html#method.schedule_self), to cancel it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TimerId<T> {
+    time: T,
+    seq: u64,
+}
+
+impl<T: Copy> TimerId<T> {
+    /// Time the event is scheduled at
+    pub fn time(&self) -> T {
+        self.time
+    }
+}
+
 /// Manages the input queues and returns the next [`Event`](struct.Event.html) to be processed.
 ///
 /// The events returned by `Merger` are monotonically increasing and come from either neighbours,
-/// or from the Merger itself upon a potential Stall.
+/// or from the Merger itself upon a potential Stall. Equal-time events are returned in the order
+/// described in the [module documentation](index.html#event-ordering). There are none left once
+/// the actor [finished](#method.finish), or at the [end of the run](index.html#end-of-the-run).
+///
+/// The executor tells it how it is run through the actor, see [`driven_by`](#method.driven_by).
+/// Events pushed straight on a queue rather than [sent](#method.send) through the `Merger` aren't
+/// seen by the sequential executor until the receiver is advanced for something else, and can't
+/// be cancelled by the optimistic executor.
 ///
 /// # Examples
 ///
@@ -91,188 +221,1105 @@ where
 {
     id: usize,
     //start: Instant,
-    // the input queues
+    // the input queues, and what we tell their senders
     in_queues: Vec<spsc::Consumer<Event<T, U>>>,
-    n_layers: usize,
-
-    paths: Vec<usize>,
+    in_links: Vec<Arc<LinkState>>,
 
     // the queue to pull from
     winner_q: usize,
     safe_time: T,
 
-    // the loser queue
-    loser_e: Vec<Event<T, U>>,
+    // next event of each input, `None` until we first pull from it
+    heads: Vec<Option<Event<T, U>>>,
 
-    // logger
-    //log: slog::Logger,
+    // picks the next head, see `key`
+    tree: LoserTree<(T, usize)>,
+
+    // ids of the actors on the other end of the inputs, also used to break ties between sources
     ix_to_id: Vec<usize>,
+
+    // when each input is live
+    spans: Vec<Span<T>>,
+
+    // events the actor scheduled for itself, by time then in the order they were scheduled,
+    // wake-ups have no payload
+    timers: BTreeMap<(T, u64), Option<U>>,
+    n_scheduled: u64,
+
+    // who is advancing us, and when the run ends if it does, known on the first event
+    driver: Driver,
+    started: bool,
+    end: Option<T>,
+
+    // to keep copies of delivered events, only set for reversible mergers
+    clone_event: Option<timewarp::CloneEvent<T, U>>,
+
+    // inputs as seen by the optimistic executor
+    timewarp: Option<timewarp::Inputs<T, U>>,
+
+    // output links, null-messages are sent on them when we stall
+    outputs: Vec<Output<T, U>>,
+
+    // number of outputs with a backlog
+    n_backlogged: usize,
+
+    // the actor is done, we're only pushing what's left of the backlogs
+    finished: bool,
+
+    // events from this time on are held back for a checkpoint
+    pause: Option<T>,
+    paused: bool,
+
+    // events restored from a checkpoint, delivered before those in the input queues
+    restored: Vec<VecDeque<Event<T, U>>>,
+    n_restored: usize,
+
+    // handed over to the worker whenever we stall
+    stats: Stats,
+
+    // time, input and type of the last event handed to the actor, asked for if it panics
+    last: Option<(T, usize, &'static str)>,
 }
 
-/// Returns indices into an 1-indexed array a tree with `n_nodes` leaves from left-to-right.
-///
-/// This means that it takes the leftmost leaf first, goes up to its parent, then the second
-/// leftmost leaf, and so on...
-///
-/// If the tree has 6 leaves, it will have 2 leaves at 4, 5, and 3:
-/// ```no-run
-///     1
-///  2     3
-/// 4 5    |
-/// ^ ^    ^
-/// ```
-///
-/// And the left-to-right walk will be `[4, 2, 5, 1, 3]`.
-///
-/// # Examples
+/// An output link of a `Merger`
+#[derive(Debug)]
+struct Output<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    queue: spsc::Producer<Event<T, U>>,
+    link: Arc<LinkState>,
+
+    // nothing sent on this link arrives sooner than this after the event that caused it
+    lookahead: T,
+
+    // the receiver knows nothing will arrive before this
+    horizon: T,
+
+    // events waiting for room in the queue, in order
+    backlog: VecDeque<Event<T, U>>,
+
+    // nothing more is sent on this link, it's only pushing what's left of the backlog
+    retired: bool,
+}
+
+/// Simulated time an input is live for
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Span<T> {
+    // nothing arrives before this
+    from: T,
+
+    // what arrives from this time on is dropped
+    until: Option<T>,
+
+    // we're past `until`, the input is ignored
+    retired: bool,
+}
+
+impl<T: Ord + Copy> Span<T> {
+    fn new(from: T) -> Self {
+        Span {
+            from,
+            until: None,
+            retired: false,
+        }
+    }
+
+    /// Whether what arrives at `time` is past the end of the span
+    #[inline]
+    fn is_past(&self, time: T) -> bool {
+        self.until.is_some_and(|until| time >= until)
+    }
+}
+
+/// What the loser tree orders the heads of the inputs by, `Stalled` ones included
 ///
-/// // TODO
+/// Ties are won by the lowest input index, see the [module documentation](index.html#event-ordering)
+/// for the order.
+#[inline]
+fn key<T, U>(ix_to_id: &[usize], event: &Event<T, U>, ix: usize) -> (T, usize)
+where
+    T: Ord + Copy + num::Zero,
+{
+    (event.time, ix_to_id[ix])
+}
+
+/// Loser tree of inputs that haven't been pulled from yet
 ///
-fn ltr_walk(n_nodes: usize) -> Vec<usize> {
-    let n_layers = (n_nodes as f32).log2().ceil() as usize;
+/// Nothing from them can come before the start of their span, they win one after the other and
+/// get replaced by the first event of their input. Retired inputs are left vacant.
+fn fresh_tree<T>(ix_to_id: &[usize], spans: &[Span<T>]) -> LoserTree<(T, usize)>
+where
+    T: Ord + Copy + num::Zero,
+{
+    let mut tree = LoserTree::new(
+        ix_to_id
+            .iter()
+            .zip(spans)
+            .map(|(&id, span)| (span.from, id))
+            .collect(),
+    );
+    for (ix, span) in spans.iter().enumerate() {
+        if span.retired {
+            tree.remove(ix);
+        }
+    }
+
+    tree
+}
+
+/// Tells the receiver on `link` that it got something, when run by the sequential executor
+#[inline]
+fn wake_consumer(mode: Mode, link: &LinkState) {
+    if mode == Mode::Sequential {
+        if let Some(slot) = link.consumer() {
+            sequential::wake(slot);
+        }
+    }
+}
+
+impl<T, U> Merger<T, U>
+where
+    U: std::fmt::Debug,
+    T: 'static + Ord + Copy + std::fmt::Debug + num::Zero,
+{
+    /// Builds a new merger from a set of input queues
+    ///
+    /// `ix_to_id` maps each input queue to the id of the actor on the other end, it is used to
+    /// order simultaneous events. If it is empty, the queue index is used as id instead.
+    pub fn new(
+        in_queues: Vec<spsc::Consumer<Event<T, U>>>,
+        id: usize,
+        ix_to_id: Vec<usize>,
+    ) -> Merger<T, U> {
+        let ix_to_id = if ix_to_id.is_empty() {
+            (0..in_queues.len()).collect()
+        } else {
+            ix_to_id
+        };
+        assert_eq!(
+            ix_to_id.len(),
+            in_queues.len(),
+            "Merger #{} needs exactly one id per input queue",
+            id
+        );
+        assert!(
+            !in_queues.is_empty(),
+            "Merger #{} needs at least one input queue",
+            id
+        );
+
+        Merger {
+            id,
+
+            winner_q: 0,
+            safe_time: T::zero(),
+
+            heads: in_queues.iter().map(|_| None).collect(),
+            tree: LoserTree::new(ix_to_id.iter().map(|&id| (T::zero(), id)).collect()),
+            spans: in_queues.iter().map(|_| Span::new(T::zero())).collect(),
+
+            in_links: in_queues.iter().map(|q| links::get(q.id())).collect(),
+            in_queues,
+            ix_to_id,
+
+            timers: BTreeMap::new(),
+            n_scheduled: 0,
+
+            driver: Driver::default(),
+            started: false,
+            end: None,
+            clone_event: None,
+            timewarp: None,
+
+            outputs: Vec::new(),
+            n_backlogged: 0,
+
+            finished: false,
+
+            pause: None,
+            paused: false,
+
+            restored: Vec::new(),
+            n_restored: 0,
+
+            stats: Stats::default(),
+            last: None,
+        }
+    }
+
+    /// Adds an output link, events are then sent on it with [`send`](#method.send)
+    ///
+    /// `lookahead` is the least time between processing an event and anything it sends on this
+    /// link arriving. Whenever the actor stalls, the merger uses it to tell the receiver how far it
+    /// can safely advance. Outputs are numbered in the order they are added, starting at 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lookahead` is zero, the receiver could then never advance past us.
+    pub fn output(mut self, queue: spsc::Producer<Event<T, U>>, lookahead: T) -> Self {
+        self.add_output(queue, lookahead);
+        self
+    }
+
+    /// Adds an output link while the actor is running, and returns its index
+    ///
+    /// This is [`output`](#method.output) for links that appear during the run. Nothing sent on
+    /// the link may arrive before the time the receiver [attached](#method.attach) it from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lookahead` is zero, or if the merger is run by the optimistic executor.
+    pub fn add_output(&mut self, queue: spsc::Producer<Event<T, U>>, lookahead: T) -> usize {
+        assert!(
+            lookahead > T::zero(),
+            "Merger #{} needs a positive lookahead on its outputs",
+            self.id
+        );
+        self.assert_not_optimistic();
+
+        self.outputs.push(Output {
+            link: links::get(queue.id()),
+            queue,
+            lookahead,
+            horizon: T::zero(),
+            backlog: VecDeque::new(),
+            retired: false,
+        });
+
+        self.outputs.len() - 1
+    }
+
+    /// Stops sending on output `ix` from time `at` on
+    ///
+    /// The receiver is told nothing else will arrive before `at`, it then drops anything from us
+    /// from that time on and retires the link on its side, see [`retire`](#method.retire). What
+    /// is still waiting for room in the queue is pushed, but nothing may be sent on the output
+    /// anymore and it gets no more null-messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output is already retired, or if the merger is run by the optimistic
+    /// executor.
+    pub fn retire_output(&mut self, ix: usize, at: T) {
+        assert!(
+            !self.outputs[ix].retired,
+            "Merger #{} already retired output {}",
+            self.id, ix
+        );
+        self.assert_not_optimistic();
+
+        self.send(
+            ix,
+            Event {
+                time: at,
+                src: self.id,
+                event_type: EventType::Null,
+            },
+        );
+        self.outputs[ix].retired = true;
+    }
+
+    /// Attaches input `queue` from actor `src_id`, and returns its index
+    ///
+    /// Nothing may arrive on it before time `from`, which can't be earlier than what the actor
+    /// was already given: until then, the input doesn't hold anything back. Events it gets are
+    /// ordered along with the others by `src_id`, like those of the inputs given to
+    /// [`new`](#method.new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from` is earlier than the last event returned, or if the merger is run by the
+    /// optimistic executor.
+    pub fn attach(&mut self, queue: spsc::Consumer<Event<T, U>>, src_id: usize, from: T) -> usize {
+        assert!(
+            from >= self.safe_time,
+            "Merger #{} can't attach an input from {:?}, it is already at {:?}",
+            self.id,
+            from,
+            self.safe_time
+        );
+        self.assert_not_optimistic();
+
+        // the sender has to be told what the others were
+        let link = links::get(queue.id());
+        link.set_consumer(self.consumer_slot());
+        link.set_blocked(self.n_backlogged > 0);
+        link.set_paused(self.paused);
+        if self.finished {
+            queue.set_done();
+        }
+
+        let ix = self.in_queues.len();
+        self.in_queues.push(queue);
+        self.in_links.push(link);
+        self.ix_to_id.push(src_id);
+        self.spans.push(Span::new(from));
+        if !self.restored.is_empty() {
+            self.restored.push(VecDeque::new());
+        }
+
+        // an empty input has nothing before `from`, which is what a stall means
+        self.heads.push(Some(Event {
+            time: from,
+            src: ix,
+            event_type: EventType::Stalled,
+        }));
+        self.tree.push(Some((from, src_id)));
+
+        ix
+    }
+
+    /// Retires input `ix` at time `at`
+    ///
+    /// Events it gets before `at` are delivered as usual. Once its sender got to `at`, having
+    /// sent an event or a null-message at or past it, whatever else arrives on it is dropped and
+    /// it stops holding anything back. A sender that retires its output at the same time, see
+    /// [`retire_output`](#method.retire_output), gets there straight away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is earlier than the last event returned, if the input is already retiring,
+    /// or if the merger is run by the optimistic executor.
+    pub fn retire(&mut self, ix: usize, at: T) {
+        assert!(
+            at >= self.safe_time,
+            "Merger #{} can't retire input {} at {:?}, it is already at {:?}",
+            self.id,
+            ix,
+            at,
+            self.safe_time
+        );
+        assert!(
+            self.spans[ix].until.is_none(),
+            "Merger #{} is already retiring input {}",
+            self.id,
+            ix
+        );
+        self.assert_not_optimistic();
+
+        self.spans[ix].until = Some(at);
+
+        // its head might already be past it
+        let past = self.heads[ix].as_ref().is_some_and(|head| {
+            !matches!(head.event_type, EventType::Stalled) && self.spans[ix].is_past(head.time)
+        });
+        if past {
+            self.close_input(ix);
+        }
+    }
+
+    /// Whether input `ix` was retired, and is ignored from now on
+    pub fn is_retired(&self, ix: usize) -> bool {
+        self.spans[ix].retired
+    }
+
+    /// Number of inputs, retired ones included
+    pub fn n_inputs(&self) -> usize {
+        self.in_queues.len()
+    }
+
+    /// Ignores input `ix` from now on, and tells its sender
+    fn close_input(&mut self, ix: usize) {
+        self.spans[ix].retired = true;
+        self.heads[ix] = None;
+        self.tree.remove(ix);
+
+        if let Some(restored) = self.restored.get_mut(ix) {
+            self.n_restored -= restored.len();
+            restored.clear();
+        }
+        let q = &self.in_queues[ix];
+        q.set_done();
+        while q.pop().is_ok() {}
+        self.wake_producer(ix);
+    }
+
+    /// The optimistic executor doesn't know about links coming and going
+    fn assert_not_optimistic(&self) {
+        assert_ne!(
+            self.driver.mode(),
+            Mode::Optimistic,
+            "Merger #{} can't change its links while running optimistically",
+            self.id
+        );
+    }
+
+    /// Number of output links
+    pub fn n_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Schedules `payload` for the actor itself at `time`, and returns what cancels it
+    ///
+    /// It is delivered as a model event with `src` set to [`TIMER`](constant.TIMER.html), in
+    /// order with the events of the inputs, see the [module documentation](index.html). Timers
+    /// don't have to be scheduled in order, nor do they need a lookahead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is earlier than the last event returned, or if the merger is run by the
+    /// optimistic executor.
+    pub fn schedule_self(&mut self, time: T, payload: U) -> TimerId<T> {
+        self.schedule(time, Some(payload))
+    }
+
+    /// Has the actor woken up at `time`, and returns what cancels it
+    ///
+    /// It is delivered as a `Stalled` event with `src` set to [`TIMER`](constant.TIMER.html), in
+    /// order with the events of the inputs like the events from
+    /// [`schedule_self`](#method.schedule_self). Unlike the `Stalled` events of the inputs, it
+    /// never comes after anything later: an actor waiting until some time gets there before
+    /// promising its receivers anything past it.
+    ///
+    /// # Panics
+    ///
+    /// Same as `schedule_self`.
+    pub fn wake_at(&mut self, time: T) -> TimerId<T> {
+        self.schedule(time, None)
+    }
+
+    /// Schedules a timer at `time`, a wake-up without `payload`
+    fn schedule(&mut self, time: T, payload: Option<U>) -> TimerId<T> {
+        assert!(
+            time >= self.safe_time,
+            "Merger #{} can't schedule a timer at {:?}, it is already at {:?}",
+            self.id,
+            time,
+            self.safe_time
+        );
+        assert_ne!(
+            self.driver.mode(),
+            Mode::Optimistic,
+            "Merger #{} can't schedule timers while running optimistically",
+            self.id
+        );
+
+        let seq = self.n_scheduled;
+        self.n_scheduled += 1;
+        self.timers.insert((time, seq), payload);
+
+        TimerId { time, seq }
+    }
+
+    /// Cancels timer `id`, and returns its payload if it didn't go off yet, wake-ups have none
+    pub fn cancel(&mut self, id: TimerId<T>) -> Option<U> {
+        self.timers.remove(&(id.time, id.seq)).flatten()
+    }
+
+    /// Number of timers that didn't go off yet
+    pub fn n_timers(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Key of the next timer to go off, ordered like the heads of the inputs, see `key`
+    #[inline]
+    fn next_timer(&self) -> Option<(T, usize)> {
+        self.timers.keys().next().map(|&(time, _)| (time, self.id))
+    }
+
+    /// Sends `event` on output `ix`
+    ///
+    /// The event must not arrive sooner than the output's lookahead after the event being
+    /// processed.
+    ///
+    /// # Backpressure
+    ///
+    /// If the output's queue is full, the event is kept, along with anything sent on that output
+    /// after it, until the receiver makes room. The merger then returns `Stalled` until the
+    /// backlog is gone so the actor gives the hand back, unless the receiver is itself waiting
+    /// for room: a cycle of full links would never drain otherwise. The receiver doesn't get
+    /// null-messages from us in the meantime, so it can't advance past the events it is missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output was [retired](#method.retire_output).
+    pub fn send(&mut self, ix: usize, event: Event<T, U>) {
+        assert!(
+            !self.outputs[ix].retired,
+            "Merger #{} can't send on retired output {}",
+            self.id, ix
+        );
+        let mode = self.driver.mode();
+        let output = &mut self.outputs[ix];
+
+        // the optimistic executor might have to cancel it, even before it's pushed
+        if mode == Mode::Optimistic {
+            let seq = output.queue.next_seq() + output.backlog.len() as u64;
+            timewarp::sent(output.link.voids(), seq);
+        }
+
+        let event = if output.backlog.is_empty() {
+            let header = event.header();
+            match output.queue.push(event) {
+                Ok(()) => {
+                    trace!(Tx, self.id, header.src, ix, &header);
+                    output.horizon = std::cmp::max(output.horizon, header.time);
+                    self.stats.sent += 1;
+                    wake_consumer(mode, &output.link);
+                    return;
+                }
+                Err(PushError(event)) => event,
+            }
+        } else {
+            event
+        };
+        trace!(Full, self.id, event.src, ix, &event);
+
+        output.backlog.push_back(event);
+        if output.backlog.len() == 1 {
+            output.link.set_waiting(self.driver.slot());
+            self.n_backlogged += 1;
+            if self.n_backlogged == 1 {
+                for link in &self.in_links {
+                    link.set_blocked(true);
+                }
+            }
+        }
+    }
+
+    /// Pushes as much of the backlogs as there is room for
+    ///
+    /// Returns whether the actor should give the hand back until the receivers make room.
+    fn flush(&mut self) -> bool {
+        if self.n_backlogged == 0 {
+            return false;
+        }
+
+        let mode = self.driver.mode();
+        let mut should_wait = false;
+        for (ix, output) in self.outputs.iter_mut().enumerate() {
+            if output.backlog.is_empty() {
+                continue;
+            }
+
+            // nobody will ever look at it
+            if output.queue.is_consumer_done() {
+                output.backlog.clear();
+            }
+
+            let mut pushed = false;
+            while let Some(event) = output.backlog.pop_front() {
+                let header = event.header();
+                if let Err(PushError(event)) = output.queue.push(event) {
+                    output.backlog.push_front(event);
+                    break;
+                }
+                trace!(Tx, self.id, header.src, ix, &header);
+                output.horizon = std::cmp::max(output.horizon, header.time);
+                self.stats.sent += 1;
+                pushed = true;
+            }
+            if pushed {
+                wake_consumer(mode, &output.link);
+            }
+
+            if output.backlog.is_empty() {
+                output.link.set_waiting(None);
+                self.n_backlogged -= 1;
+            } else {
+                should_wait |=
+                    !output.link.is_consumer_blocked() && !output.link.is_consumer_paused();
+            }
+        }
+
+        if self.n_backlogged == 0 {
+            for link in &self.in_links {
+                link.set_blocked(false);
+            }
+        }
+
+        should_wait
+    }
+
+    /// Stops taking events and pushes what it can of the backlogs
+    ///
+    /// Returns `None` once everything sent on the outputs made it out, the actor is then done.
+    /// Otherwise the actor must give the hand back at the returned time, and finish again the
+    /// next time it is advanced: the merger won't return any more events. Events for receivers
+    /// that are done themselves are dropped.
+    pub fn finish(&mut self) -> Option<T> {
+        self.finished = true;
+        match self.driver.mode() {
+            // a straggler might still call the whole thing off
+            Mode::Optimistic => {
+                self.next_optimistic(true);
+                if timewarp::is_rolling_back() {
+                    self.finished = false;
+                }
+            }
+            _ => {
+                for ix in 0..self.in_queues.len() {
+                    self.in_queues[ix].set_done();
+                    self.wake_producer(ix);
+                }
+                self.flush();
+
+                // the receivers have to get to the end without us
+                if let Some(end) = self.end {
+                    self.send_nulls(end);
+                }
+            }
+        }
+        if self.driver.mode() == Mode::Window {
+            window::report(self.backlog_floor(), None);
+        }
+        stats::hand_over(&mut self.stats);
+
+        if self.n_backlogged == 0 {
+            None
+        } else {
+            Some(self.safe_time)
+        }
+    }
+
+    /// `Stalled` at the safe time, the counters go to the worker before we give the hand back
+    fn stall(&mut self) -> Event<T, U> {
+        self.stats.stalls += 1;
+        stats::hand_over(&mut self.stats);
+        watchdog::report(|| self.describe());
+
+        let stalled = Event {
+            time: self.safe_time,
+            src: 0,
+            event_type: EventType::Stalled,
+        };
+        trace!(Stall, self.id, self.id, 0, &stalled);
+
+        stalled
+    }
+
+    /// Whether `time` is past the end of the run
+    #[inline]
+    fn is_over(&self, time: T) -> bool {
+        self.end.is_some_and(|end| time >= end)
+    }
+
+    /// Stops at the end of the run, once nothing before it can arrive anymore
+    ///
+    /// The receivers are told we won't send anything else, and nothing is returned from then on.
+    fn close(&mut self) {
+        let end = self.end.unwrap();
+        self.safe_time = end;
+        self.finished = true;
+        self.send_nulls(end);
+    }
+
+    /// End time of the run, if it has one and we were advanced already
+    pub(crate) fn end(&self) -> Option<T> {
+        self.end
+    }
+
+    /// Where we're at, for the watchdog: safe time, input to pull from next, and the length and
+    /// head of each input
+    fn describe(&mut self) -> String {
+        let mut description = format!(
+            "Merger #{}: safe time {:?}, winner_q {}",
+            self.id, self.safe_time, self.winner_q
+        );
+        for ix in 0..self.in_queues.len() {
+            // an empty input stands in the tree with the time it's known to be quiet until
+            let (pulled, quiet) = match &self.heads[ix] {
+                Some(head) if matches!(head.event_type, EventType::Stalled) => {
+                    (None, Some(head.time))
+                }
+                Some(head) => (Some(head.time), None),
+                None => (None, None),
+            };
+            let restored = self.restored.get(ix).map_or(0, VecDeque::len);
+            let len = self.in_queues[ix].len() + restored + pulled.is_some() as usize;
+
+            let _ = write!(
+                description,
+                "\n  input {} from #{}: {} events",
+                ix, self.ix_to_id[ix], len
+            );
+            let head = pulled.or_else(|| self.peek_input(ix).map(|head| head.time));
+            let _ = match (head, quiet) {
+                (Some(head), _) => write!(description, ", head at {:?}", head),
+                (None, Some(quiet)) => write!(description, ", empty, quiet until {:?}", quiet),
+                (None, None) => write!(description, ", empty"),
+            };
+            if self.spans[ix].retired {
+                description.push_str(", retired");
+            }
+        }
+        if let Some((time, _)) = self.next_timer() {
+            let _ = write!(
+                description,
+                "\n  {} timers, next at {:?}",
+                self.timers.len(),
+                time
+            );
+        }
+
+        description
+    }
+
+    /// Tells the receivers of our outputs that nothing will arrive before `time` + lookahead
+    fn send_nulls(&mut self, time: T) {
+        let mode = self.driver.mode();
+        for (ix, output) in self.outputs.iter_mut().enumerate() {
+            if output.retired || output.queue.is_consumer_done() {
+                continue;
+            }
+
+            let promise = time + output.lookahead;
+            if output.horizon < promise && output.backlog.is_empty() {
+                // a full queue has events for the receiver to process, we'll try again next time
+                let null = Event {
+                    time: promise,
+                    src: self.id,
+                    event_type: EventType::Null,
+                };
+                if output.queue.push(null).is_ok() {
+                    output.horizon = promise;
+                    self.stats.nulls_sent += 1;
+                    wake_consumer(mode, &output.link);
+                    trace!(
+                        Tx,
+                        self.id,
+                        self.id,
+                        ix,
+                        &Event::<T, U> {
+                            time: promise,
+                            src: self.id,
+                            event_type: EventType::Null,
+                        }
+                    );
+                }
+            }
+        }
+    }
+
+    /// Next event of input `ix`, those restored from a checkpoint first
+    #[inline]
+    fn pop_input(&mut self, ix: usize) -> Option<Event<T, U>> {
+        if self.n_restored > 0 {
+            if let Some(event) = self.restored[ix].pop_front() {
+                self.n_restored -= 1;
+                return Some(event);
+            }
+        }
+
+        let event = self.in_queues[ix].pop().ok();
+        if event.is_some() {
+            self.wake_producer(ix);
+        }
+        event
+    }
+
+    /// Peeks at the next event of input `ix`, those restored from a checkpoint first
+    #[inline]
+    fn peek_input(&mut self, ix: usize) -> Option<&Event<T, U>> {
+        if self.n_restored > 0 && !self.restored[ix].is_empty() {
+            return self.restored[ix].front();
+        }
 
-    // visited structure
-    let mut visited: Vec<bool> = Vec::new();
-    for _ in 0..n_nodes + 1 {
-        visited.push(false);
+        self.in_queues[ix].peek()
     }
 
-    let mut cur_index = 2_usize.pow((n_layers - 1) as u32);
-
-    let mut indices = Vec::new();
+    /// Puts `event` back in front of input `ix`
+    fn unpop_input(&mut self, ix: usize, event: Event<T, U>) {
+        if self.restored.is_empty() {
+            self.restored = self.in_queues.iter().map(|_| VecDeque::new()).collect();
+        }
 
-    indices.push(cur_index);
-    visited[cur_index] = true;
-    let mut going_up = true;
+        self.restored[ix].push_front(event);
+        self.n_restored += 1;
+    }
 
-    for _ in 0..n_nodes - 1 {
-        if going_up {
-            while visited[cur_index] {
-                cur_index /= 2;
-            }
-        } else {
-            if cur_index * 2 + 1 >= n_nodes {
-                going_up = true;
-                continue;
-            }
+    /// Whether input `ix` has no event for us, counting those restored from a checkpoint
+    #[inline]
+    fn is_input_empty(&self, ix: usize) -> bool {
+        self.in_queues[ix].is_empty() && (self.n_restored == 0 || self.restored[ix].is_empty())
+    }
 
-            cur_index = cur_index * 2 + 1;
-            while cur_index * 2 < n_nodes {
-                cur_index *= 2;
+    /// Whether the pause is in effect, the senders are told when that changes
+    fn update_pause(&mut self) -> bool {
+        let paused = self.pause.is_some_and(|pause| self.safe_time >= pause);
+        if paused != self.paused {
+            self.paused = paused;
+            for link in &self.in_links {
+                link.set_paused(paused);
             }
         }
 
-        indices.push(cur_index);
-        visited[cur_index] = true;
-
-        going_up = !going_up;
+        paused
     }
 
-    // hacky but w/e
-    if visited[0] {
-        indices.pop();
+    /// Keeps copies of the delivered events so they can be delivered again after a rollback
+    ///
+    /// This is required to be run by the [optimistic executor](fn.start_optimistic.html).
+    pub fn reversible(mut self) -> Self
+    where
+        U: Clone,
+    {
+        self.clone_event = Some(Event::clone);
+        self
     }
 
-    indices
-}
+    /// Unique ID of the actor this merger belongs to
+    pub fn id(&self) -> usize {
+        self.id
+    }
 
-impl<T, U> Merger<T, U>
-where
-    U: std::fmt::Debug,
-    T: Ord + Copy + num::Zero,
-{
-    /// Builds a new merger from a set of input queues
-    pub fn new(
-        in_queues: Vec<spsc::Consumer<Event<T, U>>>,
-        id: usize,
-        ix_to_id: Vec<usize>,
-        //log: slog::Logger,
-        //start: Instant,
-    ) -> Merger<T, U> {
-        let mut loser_e = Vec::new();
-        let winner_q = 0;
-
-        // index 0 never gets used
-        loser_e.push(Event {
-            time: T::zero(),
-            //real_time: 0,
-            event_type: EventType::Null,
-            src: 0,
-        });
+    /// Last event handed to the actor, for
+    /// [`Advancer::last_delivered`](trait.Advancer.html#method.last_delivered)
+    pub fn last_delivered(&self) -> Option<Delivered<T>> {
+        self.last.map(|(time, ix, kind)| Delivered {
+            id: self.id,
+            time,
+            src_id: self.src_id(ix),
+            kind,
+        })
+    }
 
-        // TODO hacky
-        // fill with placeholders
-        for i in 1..in_queues.len() {
-            loser_e.push(Event {
-                time: T::zero(),
-                //real_time: 0,
-                event_type: EventType::Null,
-                src: i,
+    /// Tells the merger how its actor is run, see
+    /// [`Advancer::driven_by`](trait.Advancer.html#tymethod.driven_by)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the merger is to be run by the optimistic executor without being
+    /// [reversible](#method.reversible), or with retired inputs or timers.
+    pub fn driven_by(&mut self, driver: Driver) {
+        self.driver = driver;
+        if driver.mode() == Mode::Optimistic {
+            let clone_event = self.clone_event.unwrap_or_else(|| {
+                panic!(
+                    "Merger #{} must be reversible to run optimistically",
+                    self.id
+                )
             });
+            assert!(
+                self.spans.iter().all(|span| span.until.is_none()),
+                "Merger #{} can't retire inputs while running optimistically",
+                self.id
+            );
+            assert!(
+                self.timers.is_empty(),
+                "Merger #{} can't schedule timers while running optimistically",
+                self.id
+            );
+            self.timewarp = Some(timewarp::Inputs::new(self.in_queues.len(), clone_event));
         }
 
-        // appropriately set the source
-        for (loser, ix) in ltr_walk(in_queues.len()).iter().enumerate() {
-            loser_e[*ix] = Event {
-                time: T::zero(),
-                //real_time: 0,
-                event_type: EventType::Null,
-                src: loser + 1,
-            };
+        // the other ends need to know where to find us
+        for link in &self.in_links {
+            link.set_consumer(self.consumer_slot());
+        }
+        for output in self.outputs.iter().filter(|o| !o.backlog.is_empty()) {
+            output.link.set_waiting(driver.slot());
         }
+    }
 
-        // helfpul number
-        let n_queues = in_queues.len();
-        let n_layers = (n_queues as f32).log2().ceil() as usize;
-        let largest_full_layer = 2_usize.pow((n_queues as f32).log2().floor() as u32);
-        let last_layer_max_i = ((n_queues + largest_full_layer - 1) % largest_full_layer + 1) * 2;
-        let offset = (last_layer_max_i + 1) / 2;
+    /// Slot the senders wake us up at, when run by the sequential executor
+    fn consumer_slot(&self) -> Option<usize> {
+        match self.driver.mode() {
+            Mode::Sequential => self.driver.slot(),
+            _ => None,
+        }
+    }
 
-        let mut paths = Vec::new();
-        for ix in 0..n_queues {
-            let v_ix = if ix > last_layer_max_i {
-                (ix - offset) * 2
-            } else {
-                ix
+    /// Tells the sender on input `ix` that there might be room, when it waits for some in the
+    /// sequential executor
+    #[inline]
+    fn wake_producer(&self, ix: usize) {
+        if self.driver.mode() == Mode::Sequential {
+            if let Some(slot) = self.in_links[ix].waiting_producer() {
+                sequential::wake(slot);
+            }
+        }
+    }
+
+    /// Next event when advanced by the [optimistic executor], none is delivered if `hold` is set
+    ///
+    /// [optimistic executor]: fn.start_optimistic.html
+    fn next_optimistic(&mut self, hold: bool) -> Event<T, U> {
+        let should_wait = timewarp::may_deliver() && self.flush();
+        if should_wait || hold {
+            timewarp::hold();
+        }
+        let event =
+            self.timewarp
+                .as_mut()
+                .unwrap()
+                .next(&self.in_queues, &self.in_links, &self.ix_to_id);
+
+        // the backlog is part of our floor until it's pushed
+        if let Some(time) = self.backlog_floor() {
+            timewarp::lower_floor(time);
+        }
+
+        event
+    }
+
+    /// Next event when advanced by the [sequential executor](fn.start_sequential.html)
+    ///
+    /// The executor guarantees that nothing earlier than our inputs' earliest event can still
+    /// arrive, so there is no need for the loser tree. The earliest event is returned if the
+    /// executor allows it, otherwise its time is reported and the actor gets `Stalled`.
+    fn next_sequential(&mut self) -> Option<Event<T, U>> {
+        // the end of the run comes after everything before it
+        let earliest = self.earliest().filter(|&(time, _, _)| !self.is_over(time));
+        let next_time = earliest.map(|(time, _, _)| time).or(self.end);
+        if next_time.is_some() && sequential::take_delivery() {
+            return match earliest {
+                Some((_, _, ix)) => Some(self.deliver(ix)),
+                None => {
+                    self.close();
+                    None
+                }
             };
+        }
 
-            let mut index = 0;
+        sequential::report_next_time(next_time);
+        Some(self.stall())
+    }
 
-            assert!(n_layers > 0);
-            for level in (0..n_layers).rev() {
-                // compute index
-                // TODO pre-compute?
-                let base_offset = 2_usize.pow(level as u32);
-                index = base_offset + v_ix / 2_usize.pow((n_layers - level) as u32);
+    /// Next event when advanced by the [window executor](fn.start_windowed.html)
+    ///
+    /// Nothing can arrive below the bound of the window that isn't already on our inputs, except
+    /// for the backlogs of senders waiting for room: an empty input of theirs holds everything
+    /// back until they flush. Otherwise we stall, and report our floor and least lookahead for
+    /// the next window.
+    fn next_window(&mut self) -> Option<Event<T, U>> {
+        // before looking at the inputs, for what was flushed to show up on them
+        let held_back = (0..self.in_queues.len()).any(|ix| {
+            !self.spans[ix].retired
+                && self.in_links[ix].is_producer_waiting()
+                && self.is_input_empty(ix)
+        });
 
-                // skip if we're out of bounds
-                if index >= loser_e.len() {
-                    continue;
+        // the end of the run comes after everything before it
+        let earliest = self.earliest().filter(|&(time, _, _)| !self.is_over(time));
+        let next_time = earliest.map(|(time, _, _)| time).or(self.end);
+        match next_time {
+            Some(time) if !held_back && window::may_deliver(time) => match earliest {
+                Some((_, _, ix)) => Some(self.deliver(ix)),
+                None => {
+                    self.close();
+                    None
                 }
-                break;
+            },
+            _ => {
+                let floor = next_time.into_iter().chain(self.backlog_floor()).min();
+                let lookahead = self
+                    .outputs
+                    .iter()
+                    .filter(|output| !output.retired)
+                    .map(|output| output.lookahead)
+                    .min();
+                window::report(floor, lookahead);
+
+                Some(self.stall())
             }
-
-            paths.push(index);
         }
+    }
 
-        Merger {
-            id,
+    /// Key of the earliest event on the inputs, along with its input, nulls are dropped on the
+    /// way
+    fn earliest(&mut self) -> Option<(T, usize, usize)> {
+        let mut earliest: Option<(T, usize, usize)> = None;
+        for ix in 0..self.in_queues.len() {
+            if self.spans[ix].retired {
+                continue;
+            }
 
-            in_queues,
-            n_layers,
+            // nulls carry no information here, unless they take the input past its span
+            let span = self.spans[ix];
+            loop {
+                match self.peek_input(ix) {
+                    Some(event) if span.is_past(event.time) => {
+                        self.close_input(ix);
+                        break;
+                    }
+                    Some(Event {
+                        event_type: EventType::Null,
+                        ..
+                    }) => {
+                        let null = self.pop_input(ix).unwrap();
+                        self.stats.nulls_received += 1;
+                        trace!(Rx, self.id, self.ix_to_id[ix], ix, &null);
+                    }
+                    _ => break,
+                }
+            }
+            if self.spans[ix].retired {
+                continue;
+            }
 
-            winner_q,
-            safe_time: T::zero(),
+            if let Some(event) = self.peek_input(ix) {
+                let key = (event.time, self.ix_to_id[ix], ix);
+                if earliest.is_none_or(|earliest| key < earliest) {
+                    earliest = Some(key);
+                }
+            }
+        }
 
-            paths,
+        // a timer goes before what we sent ourselves at the same time
+        if let Some((time, id)) = self.next_timer() {
+            if earliest.is_none_or(|(e_time, e_id, _)| (time, id) <= (e_time, e_id)) {
+                earliest = Some((time, id, TIMER));
+            }
+        }
 
-            loser_e,
+        earliest
+    }
 
-            //log,
-            ix_to_id,
+    /// Pops the next event of input `ix` for the actor, or the next timer for `TIMER`
+    fn deliver(&mut self, ix: usize) -> Event<T, U> {
+        let mut event = if ix == TIMER {
+            let ((time, _), payload) = self.timers.pop_first().unwrap();
+            Event {
+                time,
+                src: TIMER,
+                event_type: payload.map_or(EventType::Stalled, EventType::ModelEvent),
+            }
+        } else {
+            self.pop_input(ix).unwrap()
+        };
+        event.src = ix;
+        self.safe_time = event.time;
+        if let EventType::ModelEvent(_) = event.event_type {
+            self.stats.events += 1;
+        }
+        self.delivered(&event);
+        trace!(Rx, self.id, self.src_id(ix), ix, &event);
+
+        event
+    }
+
+    /// Id of the actor on the other end of input `ix`, our own for timers
+    #[inline]
+    fn src_id(&self, ix: usize) -> usize {
+        match ix {
+            TIMER => self.id,
+            ix => self.ix_to_id[ix],
         }
     }
 
+    /// Notes what the actor is about to handle, in case it panics, see the `failure` module
+    #[inline]
+    fn delivered(&mut self, event: &Event<T, U>) {
+        let kind = match event.event_type {
+            EventType::ModelEvent(_) => "ModelEvent",
+            EventType::Stalled => "Stalled",
+            EventType::Null => "Null",
+            EventType::Close => "Close",
+        };
+        self.last = Some((event.time, event.src, kind));
+    }
+
+    /// Time of the earliest event still waiting for room on an output
+    fn backlog_floor(&self) -> Option<T> {
+        self.outputs
+            .iter()
+            .flat_map(|output| &output.backlog)
+            .map(|event| event.time)
+            .min()
+    }
+
     /// Non-blocking next event. Used for testing.
     fn _try_pop(&mut self) -> Option<Event<T, U>> {
         if !self.in_queues[self.winner_q].is_empty() {
@@ -286,81 +1333,146 @@ where
 impl<T, U> Iterator for Merger<T, U>
 where
     U: std::fmt::Debug,
-    T: Ord + Copy + num::Zero,
+    T: 'static + Ord + Copy + std::fmt::Debug + num::Zero,
 {
     type Item = Event<T, U>;
 
     // blocks until it has something to return
     fn next(&mut self) -> Option<Self::Item> {
-        // The state of this must be mostly done except for the previous winner
+        if self.finished {
+            return None;
+        }
+
+        // first time around, find out when the run ends
+        if !self.started {
+            self.started = true;
+            self.end = end::get();
+        }
+
+        match self.driver.mode() {
+            // the receivers wake us up when they make room, they can't see the backlog until then
+            Mode::Sequential => {
+                self.flush();
+                return self.next_sequential();
+            }
+            // everyone flushes at every window, the backlogs can't stay for long
+            Mode::Window => {
+                self.flush();
+                return self.next_window();
+            }
+            Mode::Optimistic => return Some(self.next_optimistic(false)),
+            Mode::Conservative => {
+                let should_wait = self.flush();
+                if should_wait || self.update_pause() {
+                    self.send_nulls(self.safe_time);
+                    return Some(self.stall());
+                }
+            }
+        }
+
+        // Everything is in the tree except for the previous winner
         loop {
-            // get the new candidate
-            let mut new_winner_e = match self.in_queues[self.winner_q].pop() {
-                Err(_) => {
-                    Event {
-                        time: self.safe_time,
-                        //real_time: self.start.elapsed().as_nanos(),
-                        src: self.winner_q,
-                        event_type: EventType::Stalled,
-                    }
+            // an empty input has nothing before what it last sent us, unless a timer went before
+            // its head and left it there
+            let ix = self.winner_q;
+            let kept = self.heads[ix].as_ref().is_some_and(|head| {
+                !matches!(head.event_type, EventType::Stalled) || self.is_input_empty(ix)
+            });
+            if !self.spans[ix].retired && !kept {
+                let mut head = self.pop_input(ix).unwrap_or(Event {
+                    time: self.safe_time,
+                    src: ix,
+                    event_type: EventType::Stalled,
+                });
+                head.src = ix;
+
+                if !matches!(head.event_type, EventType::Stalled)
+                    && self.spans[ix].is_past(head.time)
+                {
+                    self.close_input(ix);
+                } else {
+                    self.tree.replace(ix, Some(key(&self.ix_to_id, &head, ix)));
+                    self.heads[ix] = Some(head);
                 }
-                Ok(event) => event,
-            };
+            }
 
-            // change the source id->ix now
-            new_winner_e.src = self.winner_q;
-
-            // get the path up
-            let mut index = self.paths[self.winner_q];
-
-            // go up our path, noting the loser as we go
-            while index != 0 {
-                // get current loser
-                let cur_loser = &mut self.loser_e[index];
-
-                // The current loser wins, swap with our candidate, move up
-                match cur_loser.time.cmp(&new_winner_e.time) {
-                    Ordering::Less => mem::swap(&mut new_winner_e, cur_loser),
-                    Ordering::Equal => {
-                        if let EventType::Stalled = new_winner_e.event_type {
-                            //if let EventType::Stalled = self.loser_e[index].event_type {
-                            //} else {
-                            mem::swap(&mut new_winner_e, cur_loser);
-                            //}
-                        }
+            // nothing can come before the winner, which stays where it is if a timer goes first
+            if let Some(timer) = self.next_timer() {
+                if self.tree.peek().is_none_or(|(_, &winner)| timer <= winner) {
+                    self.safe_time = timer.0;
+                    if self.update_pause() {
+                        self.send_nulls(self.safe_time);
+                        return Some(self.stall());
+                    }
+                    if self.is_over(timer.0) {
+                        self.close();
+                        return None;
                     }
-                    _ => {}
+                    return Some(self.deliver(TIMER));
                 }
-
-                index /= 2;
             }
 
-            // We need this to know what path to go up next time...
-            self.winner_q = new_winner_e.src;
+            // We need this to know what to pull from next time...
+            self.winner_q = match self.tree.winner() {
+                Some(winner) => winner,
+                // every input is retired, nothing will ever come
+                None => {
+                    if let Some(end) = self.end {
+                        self.safe_time = std::cmp::max(self.safe_time, end);
+                        if !self.update_pause() {
+                            self.close();
+                            return None;
+                        }
+                    }
+                    self.send_nulls(self.safe_time);
+                    return Some(self.stall());
+                }
+            };
+            let new_winner_e = match self.heads[self.winner_q].take() {
+                Some(event) => event,
+                // not pulled from yet
+                None => continue,
+            };
 
             // We need this to return events even if we don't have new events coming in...
             self.safe_time = new_winner_e.time;
 
-            /*trace!(
-                self.log,
-                "{},{},{},{:?}",
-                //new_winner_e.real_time,
-                new_winner_e.time,;
-                self.id,
-                self.ix_to_id[new_winner_e.src],
-                new_winner_e.event_type,
-            );*/
+            // nothing else comes before the end of the run, unless it's paused before it
+            if self.is_over(new_winner_e.time) && !self.update_pause() {
+                self.close();
+                return None;
+            }
 
             // Null events are only useful for us
             if let EventType::Null = new_winner_e.event_type {
+                self.stats.nulls_received += 1;
+                let src = new_winner_e.src;
+                trace!(Rx, self.id, self.ix_to_id[src], src, &new_winner_e);
                 continue;
             }
 
             // If we were gonna stall but we can make progress, don't
             if let EventType::Stalled = new_winner_e.event_type {
-                if !self.in_queues[new_winner_e.src].is_empty() {
+                if !self.is_input_empty(new_winner_e.src) {
                     continue;
                 }
+
+                self.update_pause();
+                self.send_nulls(new_winner_e.time);
+                self.stats.stalls += 1;
+                stats::hand_over(&mut self.stats);
+                trace!(Stall, self.id, self.id, 0, &new_winner_e);
+                watchdog::report(|| self.describe());
+            } else if self.update_pause() {
+                // it wins again once the pause is lifted
+                self.send_nulls(self.safe_time);
+                self.unpop_input(new_winner_e.src, new_winner_e);
+                return Some(self.stall());
+            } else {
+                self.stats.events += 1;
+                self.delivered(&new_winner_e);
+                let src = new_winner_e.src;
+                trace!(Rx, self.id, self.ix_to_id[src], src, &new_winner_e);
             }
 
             return Some(new_winner_e);
@@ -368,11 +1480,153 @@ where
     }
 }
 
+/// What a `Merger` saves: safe time, inputs and their spans, outputs, whether it's finished, and
+/// its timers along with how many were ever scheduled
+type Saved<T, U> = (
+    T,
+    Vec<VecDeque<Event<T, U>>>,
+    Vec<Span<T>>,
+    Vec<(T, bool, VecDeque<Event<T, U>>)>,
+    bool,
+    (BTreeMap<(T, u64), Option<U>>, u64),
+);
+
+impl<T, U> Pausable<T> for Merger<T, U>
+where
+    U: std::fmt::Debug + Serialize + DeserializeOwned,
+    T: 'static + Ord + Copy + std::fmt::Debug + num::Zero + Serialize + DeserializeOwned,
+{
+    fn pause_at(&mut self, time: Option<T>) {
+        self.pause = time;
+    }
+
+    fn is_paused(&self) -> bool {
+        // the receivers that aren't paused themselves need everything we owe them
+        let settled = |output: &Output<T, U>| {
+            output.link.is_consumer_paused()
+                || output.queue.is_consumer_done()
+                || (output.backlog.is_empty()
+                    && (output.retired || output.horizon >= self.safe_time + output.lookahead))
+        };
+
+        if self.finished {
+            self.outputs
+                .iter()
+                .all(|output| output.backlog.is_empty() || output.link.is_consumer_paused())
+        } else {
+            self.paused && self.outputs.iter().all(settled)
+        }
+    }
+
+    fn save(&mut self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+        assert_ne!(
+            self.driver.mode(),
+            Mode::Optimistic,
+            "Merger #{} can't be saved while running optimistically",
+            self.id
+        );
+
+        // the events in flight are part of our state
+        if self.restored.is_empty() {
+            self.restored = self.in_queues.iter().map(|_| VecDeque::new()).collect();
+        }
+        for (q, restored) in self.in_queues.iter().zip(&mut self.restored) {
+            while let Ok(event) = q.pop() {
+                restored.push_back(event);
+                self.n_restored += 1;
+            }
+        }
+
+        // so are the heads of the inputs, the tree starts over from the inputs
+        for ix in 0..self.heads.len() {
+            match self.heads[ix].take() {
+                Some(Event {
+                    event_type: EventType::Stalled,
+                    ..
+                })
+                | None => {}
+                Some(event) => self.unpop_input(ix, event),
+            }
+        }
+        self.tree = fresh_tree(&self.ix_to_id, &self.spans);
+        self.winner_q = 0;
+
+        let outputs: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| (output.horizon, output.retired, &output.backlog))
+            .collect();
+        save_state(
+            out,
+            &(
+                self.safe_time,
+                &self.restored,
+                &self.spans,
+                outputs,
+                self.finished,
+                (&self.timers, self.n_scheduled),
+            ),
+        )
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+        let (safe_time, restored, spans, outputs, finished, (timers, n_scheduled)): Saved<T, U> =
+            load_state(input)?;
+        if restored.len() != self.in_queues.len() || outputs.len() != self.outputs.len() {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "Merger #{} isn't connected like the saved one",
+                self.id
+            ))));
+        }
+
+        // whatever was sent to us since we were built was in flight at the checkpoint already
+        for q in &self.in_queues {
+            while q.pop().is_ok() {}
+        }
+
+        self.safe_time = safe_time;
+        self.n_restored = restored.iter().map(VecDeque::len).sum();
+        self.restored = restored;
+        self.finished = finished;
+        self.timers = timers;
+        self.n_scheduled = n_scheduled;
+
+        // retired inputs don't hold anything back anymore
+        for (q, span) in self.in_queues.iter().zip(&spans) {
+            if span.retired {
+                q.set_done();
+            }
+        }
+        self.heads = spans.iter().map(|_| None).collect();
+        self.tree = fresh_tree(&self.ix_to_id, &spans);
+        self.winner_q = 0;
+        self.spans = spans;
+
+        for (output, (horizon, retired, backlog)) in self.outputs.iter_mut().zip(outputs) {
+            output.horizon = horizon;
+            output.retired = retired;
+            output.backlog = backlog;
+        }
+        self.n_backlogged = self
+            .outputs
+            .iter()
+            .filter(|o| !o.backlog.is_empty())
+            .count();
+        for link in &self.in_links {
+            link.set_blocked(self.n_backlogged > 0);
+        }
+
+        Ok(())
+    }
+}
+
 // TODO make test-able again
 #[cfg(test)]
 mod test_merger {
     use crate::engine::*;
+    use crate::fixtures::{burst, timer_ring, BURST, LOOKAHEAD};
     use crate::spsc;
+    use crate::{start, start_sequential, start_windowed, start_with, Partition, Scheduler};
     use std::{thread, time};
 
     #[derive(Debug)]
@@ -381,26 +1635,10 @@ mod test_merger {
     }
 
     #[test]
-    fn test_ltr() {
-        let ix = ltr_walk(2);
-        let expected = vec![1];
-        assert_eq!(ix, expected);
-
-        let ix = ltr_walk(3);
-        let expected = vec![2, 1];
-        assert_eq!(ix, expected);
-
-        let ix = ltr_walk(4);
-        let expected = vec![2, 1, 3];
-        assert_eq!(ix, expected);
-
-        let ix = ltr_walk(5);
-        let expected = vec![4, 2, 1, 3];
-        assert_eq!(ix, expected);
-
-        let ix = ltr_walk(13);
-        let expected = vec![8, 4, 9, 2, 10, 5, 11, 1, 12, 6, 3, 7];
-        assert_eq!(ix, expected);
+    fn test_merge_1() {
+        test_interleave(1, 5);
+        test_pushpop(1, 5);
+        test_ties(1, 5);
     }
 
     #[test]
@@ -455,6 +1693,291 @@ mod test_merger {
         }
     }
 
+    /// Pushes a model event, or another `event_type`, at each time
+    fn push_all(
+        prod: &spsc::Producer<Event<u64, EmptyModel>>,
+        src: usize,
+        events: &[(u64, EventType<EmptyModel>)],
+    ) {
+        for (time, event_type) in events {
+            let event_type = match event_type {
+                EventType::ModelEvent(_) => EventType::ModelEvent(EmptyModel::None),
+                EventType::Stalled => EventType::Stalled,
+                EventType::Null => EventType::Null,
+                EventType::Close => EventType::Close,
+            };
+            prod.push(Event {
+                time: *time,
+                src,
+                event_type,
+            })
+            .unwrap();
+        }
+    }
+
+    /// (time, input) of the events returned until the merger stalls or closes
+    fn drain(merger: &mut Merger<u64, EmptyModel>) -> Vec<(u64, usize)> {
+        let mut seen = Vec::new();
+        for event in merger.by_ref() {
+            match event.event_type {
+                EventType::Stalled => break,
+                EventType::Close => {
+                    seen.push((event.time, event.src));
+                    break;
+                }
+                _ => seen.push((event.time, event.src)),
+            }
+        }
+        seen
+    }
+
+    const EVENT: EventType<EmptyModel> = EventType::ModelEvent(EmptyModel::None);
+
+    #[test]
+    fn test_attach() {
+        let (prod_a, cons_a) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons_a], 0, vec![10]);
+        push_all(
+            &prod_a,
+            10,
+            &[(1, EVENT), (4, EVENT), (6, EVENT), (8, EVENT)],
+        );
+
+        // nothing comes from the new input before 5, it doesn't hold back what does
+        let (prod_b, cons_b) = spsc::new(16);
+        assert_eq!(merger.attach(cons_b, 11, 5), 1);
+        assert_eq!(drain(&mut merger), vec![(1, 0), (4, 0)]);
+
+        push_all(
+            &prod_b,
+            11,
+            &[(5, EVENT), (8, EVENT), (40, EventType::Null)],
+        );
+        push_all(&prod_a, 10, &[(30, EventType::Close)]);
+        assert_eq!(
+            drain(&mut merger),
+            vec![(5, 1), (6, 0), (8, 0), (8, 1), (30, 0)]
+        );
+    }
+
+    #[test]
+    fn test_retire() {
+        let (prod_a, cons_a) = spsc::new(16);
+        let (prod_b, cons_b) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons_a, cons_b], 0, vec![]);
+        merger.retire(1, 5);
+
+        // what comes at or after 5 is dropped, and the input stops holding anything back
+        push_all(&prod_a, 0, &[(1, EVENT), (3, EVENT), (7, EVENT)]);
+        push_all(&prod_b, 1, &[(2, EVENT), (6, EVENT), (8, EVENT)]);
+        assert_eq!(drain(&mut merger), vec![(1, 0), (2, 1), (3, 0), (7, 0)]);
+        assert!(merger.is_retired(1));
+        assert!(!merger.is_retired(0));
+
+        push_all(&prod_b, 1, &[(9, EVENT)]);
+        push_all(&prod_a, 0, &[(10, EVENT), (12, EventType::Close)]);
+        assert_eq!(drain(&mut merger), vec![(10, 0), (12, 0)]);
+    }
+
+    #[test]
+    fn test_retire_all() {
+        let (prod, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 0, vec![]);
+        push_all(&prod, 0, &[(1, EVENT), (3, EVENT)]);
+
+        merger.retire(0, 2);
+        assert_eq!(drain(&mut merger), vec![(1, 0)]);
+        assert!(merger.is_retired(0));
+        assert!(matches!(
+            merger.next().unwrap().event_type,
+            EventType::Stalled
+        ));
+    }
+
+    #[test]
+    fn test_retire_output() {
+        let (_to_sender, from_world) = spsc::new(16);
+        let mut sender = Merger::<u64, EmptyModel>::new(vec![from_world], 0, vec![2]);
+
+        // the receiver also hears from someone else
+        let (to_receiver, from_other) = spsc::new(16);
+        let mut receiver = Merger::<u64, EmptyModel>::new(vec![from_other], 1, vec![2]);
+        push_all(
+            &to_receiver,
+            2,
+            &[(1, EVENT), (12, EVENT), (20, EventType::Close)],
+        );
+
+        // a link that only lives from 3 to 9
+        let (link, from_sender) = spsc::new(16);
+        let ix = sender.add_output(link, 1);
+        let input = receiver.attach(from_sender, 0, 3);
+        receiver.retire(input, 9);
+
+        // too late for the receiver
+        for time in [4, 10] {
+            sender.send(
+                ix,
+                Event {
+                    time,
+                    src: 0,
+                    event_type: EventType::ModelEvent(EmptyModel::None),
+                },
+            );
+        }
+        sender.retire_output(ix, 9);
+
+        assert_eq!(drain(&mut receiver), vec![(1, 0), (4, 1), (12, 0), (20, 0)]);
+        assert!(receiver.is_retired(input));
+    }
+
+    #[test]
+    #[should_panic(expected = "Merger #0 can't send on retired output 0")]
+    fn test_send_retired() {
+        let (prod, _cons) = spsc::new(16);
+        let (_other, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 0, vec![]).output(prod, 1);
+        merger.retire_output(0, 5);
+        merger.send(
+            0,
+            Event {
+                time: 6,
+                src: 0,
+                event_type: EVENT,
+            },
+        );
+    }
+
+    #[test]
+    fn test_timers() {
+        let (prod_a, cons_a) = spsc::new(16);
+        let (prod_b, cons_b) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons_a, cons_b], 5, vec![3, 7]);
+
+        // in any order, they go off as ours in between the two inputs
+        merger.schedule_self(6, EmptyModel::None);
+        merger.schedule_self(4, EmptyModel::None);
+        let cancelled = merger.schedule_self(9, EmptyModel::None);
+        assert_eq!(cancelled.time(), 9);
+        assert!(merger.cancel(cancelled).is_some());
+        assert_eq!(merger.n_timers(), 2);
+
+        push_all(&prod_a, 3, &[(4, EVENT), (8, EventType::Null)]);
+        push_all(&prod_b, 7, &[(4, EVENT), (5, EventType::Null)]);
+        assert_eq!(drain(&mut merger), vec![(4, 0), (4, TIMER), (4, 1)]);
+
+        // the input it went before still holds the rest back
+        push_all(&prod_b, 7, &[(20, EventType::Null)]);
+        assert_eq!(drain(&mut merger), vec![(6, TIMER)]);
+        assert_eq!(merger.next().unwrap().time, 8);
+        assert!(merger.cancel(cancelled).is_none());
+        assert_eq!(merger.n_timers(), 0);
+    }
+
+    #[test]
+    fn test_wake_up() {
+        let (prod, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 5, vec![3]);
+        merger.wake_at(15);
+        merger.wake_at(10);
+        let cancelled = merger.wake_at(12);
+        assert!(merger.cancel(cancelled).is_none());
+        assert_eq!(merger.n_timers(), 2);
+        push_all(&prod, 3, &[(4, EVENT), (20, EventType::Null)]);
+
+        // it wakes up before stalling past it
+        let mut next = || {
+            let event = merger.next().unwrap();
+            let stalled = matches!(event.event_type, EventType::Stalled);
+            (event.time, event.src, stalled)
+        };
+        assert_eq!(next(), (4, 0, false));
+        assert_eq!(next(), (10, TIMER, true));
+        assert_eq!(next(), (15, TIMER, true));
+        assert_eq!(next(), (20, 0, true));
+        assert_eq!(merger.n_timers(), 0);
+    }
+
+    #[test]
+    fn test_timers_retired() {
+        let (prod, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 0, vec![]);
+        merger.retire(0, 0);
+        push_all(&prod, 0, &[(0, EventType::Null)]);
+
+        // nothing else holds them back
+        merger.schedule_self(30, EmptyModel::None);
+        merger.schedule_self(10, EmptyModel::None);
+        assert_eq!(drain(&mut merger), vec![(10, TIMER), (30, TIMER)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't schedule a timer at 3, it is already at 4")]
+    fn test_timer_late() {
+        let (prod, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 0, vec![]);
+        push_all(&prod, 0, &[(4, EVENT)]);
+        drain(&mut merger);
+        merger.schedule_self(3, EmptyModel::None);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't attach an input from 2, it is already at 4")]
+    fn test_attach_late() {
+        let (prod, cons) = spsc::new(16);
+        let mut merger = Merger::<u64, EmptyModel>::new(vec![cons], 0, vec![]);
+        push_all(&prod, 0, &[(3, EVENT), (4, EVENT)]);
+        assert_eq!(drain(&mut merger), vec![(3, 0), (4, 0)]);
+
+        merger.attach(spsc::new(16).1, 1, 2);
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let limit = 200;
+        let expected: u64 = (1..=limit)
+            .step_by(BURST as usize)
+            .flat_map(|tick| (0..BURST).map(move |i| tick + LOOKAHEAD + i))
+            .zip(1..)
+            .map(|(time, n)| n * time)
+            .sum();
+
+        for &capacity in &[2, 1024] {
+            let mut results = vec![
+                start_sequential(burst(limit, capacity), None),
+                start(2, burst(limit, capacity), None).unwrap().0,
+                start_with(Scheduler::Priority, 2, burst(limit, capacity), None)
+                    .unwrap()
+                    .0,
+            ];
+            for result in &mut results {
+                result.sort_unstable();
+                assert_eq!(*result, vec![expected; 2], "capacity {}", capacity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_timers_executors() {
+        let mut sequential = start_sequential(timer_ring(5, 1_000), None);
+        assert!(sequential.iter().all(|&sum| sum > 0));
+        sequential.sort_unstable();
+
+        let mut results = vec![
+            start(2, timer_ring(5, 1_000), None).unwrap().0,
+            start_with(Scheduler::Priority, 2, timer_ring(5, 1_000), None)
+                .unwrap()
+                .0,
+            start_windowed(&Partition::round_robin(5, 2), timer_ring(5, 1_000), None)
+                .unwrap()
+                .0,
+        ];
+        for result in &mut results {
+            result.sort_unstable();
+            assert_eq!(*result, sequential);
+        }
+    }
+
     fn test_interleave(n_queues: usize, n_events: usize) {
         println!("Interleaving");
         // Create our event queues
@@ -475,7 +1998,7 @@ mod test_merger {
         for (src, prod) in prod_qs.iter().enumerate() {
             for i in 1..n_events + 1 {
                 let e = Event {
-                    time: (src * 1 + i) as u64,
+                    time: (src + i) as u64,
                     src,
                     event_type: EventType::ModelEvent(EmptyModel::None),
                 };
@@ -562,11 +2085,7 @@ mod test_merger {
             }
 
             if let Some(event) = merger._try_pop() {
-                assert!(
-                    false,
-                    "Merger should not have any more events, got {:?}",
-                    event
-                );
+                panic!("Merger should not have any more events, got {:?}", event);
             }
 
             assert_eq!(
@@ -579,7 +2098,7 @@ mod test_merger {
         for (src, prod) in prod_qs.iter().enumerate().rev() {
             for i in 1..n_events + 1 {
                 let e = Event {
-                    time: (src * 1 + 4 * i) as u64,
+                    time: (src + 4 * i) as u64,
                     src,
                     event_type: EventType::ModelEvent(EmptyModel::None),
                 };
@@ -612,14 +2131,30 @@ mod test_merger {
             cons_qs.push(cons);
         }
 
-        // Merger
-        let mut merger = Merger::<u64, EmptyModel>::new(cons_qs, 0, vec![]);
+        // Merger, ids are given in reverse to make sure they are used over indices
+        let ix_to_id = (0..n_queues).rev().collect();
+        let mut merger = Merger::<u64, EmptyModel>::new(cons_qs, 0, ix_to_id);
 
-        let mut cur_time = 0;
+        // (time, id) of the last event seen
+        let mut last: Option<(u64, usize)> = None;
+        let mut event_count = 0;
 
-        let mut success = true;
+        let mut check_order = |event: &Event<u64, EmptyModel>| {
+            println!("    => {:?}", event);
+            let cur = (event.time, n_queues - 1 - event.src);
+            if let Some(prev) = last {
+                assert!(
+                    prev < cur,
+                    "Order violated. Previous event was {:?}, current event {:?}",
+                    prev,
+                    cur
+                );
+            }
+            last = Some(cur);
+        };
 
         for time in 0..n_events {
+            // arrival order should not matter
             println!("\nPushing events @{}", time);
             for (ix, prod) in prod_qs.iter().enumerate() {
                 let e = Event {
@@ -631,50 +2166,46 @@ mod test_merger {
                 prod.push(e).unwrap();
             }
 
+            // We can only go as far as an empty queue lets us
             println!("Popping events");
-
-            let mut event_count = 0;
-            while let Some(event) = merger.next() {
-                // break if we're stalled
+            for event in merger.by_ref() {
                 if let EventType::Stalled = event.event_type {
-                    // current time -> we're done
-                    if event.time == time as u64 {
-                        break;
-                    }
-
-                    // past time
-                    //continue;
+                    break;
                 }
 
-                println!("    => {:?}", event);
+                check_order(&event);
+                event_count += 1;
+            }
+        }
 
-                // check time invariant
-                assert!(
-                    cur_time <= event.time,
-                    "Time invariant violated. Previous event was @{}, current event @{}",
-                    cur_time,
-                    event.time
-                );
+        // close sentinels, everything else should come out before them
+        for (src, prod) in prod_qs.iter().enumerate() {
+            let e = Event {
+                time: n_events as u64,
+                src,
+                event_type: EventType::Close,
+            };
+            prod.push(e).unwrap();
+        }
 
-                // update state
-                cur_time = event.time;
-                if let EventType::Stalled = event.event_type {
-                } else {
-                    event_count += 1;
-                }
+        println!("Draining");
+        for event in merger {
+            match event.event_type {
+                EventType::Close => break,
+                EventType::Stalled => panic!("Should not stall with all queues full"),
+                _ => {}
             }
 
-            // check all our messages got out
-            success = success && event_count == n_queues;
-
-            println!(
-                //n_queues, event_count,
-                "Expected {} events, saw {}",
-                n_queues,
-                event_count
-            );
+            check_order(&event);
+            event_count += 1;
         }
 
-        assert!(success);
+        assert_eq!(
+            event_count,
+            n_queues * n_events,
+            "Expected {} events, saw {}",
+            n_queues * n_events,
+            event_count
+        );
     }
 }
@@ -0,0 +1,98 @@
+//! Turns actor panics into errors
+//!
+//! Workers advance the actors under `catch_unwind`. Each `Merger` notes the last event it
+//! delivered as it goes, and once an actor panicked the worker asks it for that event, so that
+//! whatever panicked while handling it can be told apart: which actor it was, when, and what it
+//! was doing. The worker that caught the panic then
+//! has the others stop, and the executor returns an [`ActorPanic`](struct.ActorPanic.html).
+
+use crate::worker::{self, ActorState};
+use crate::{FrozenActor, WorkerStats};
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::panic::{self, AssertUnwindSafe};
+
+/// An actor panicked, the run was cut short
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorPanic {
+    /// Index of the actor, in the order they were given
+    pub ix: usize,
+
+    /// Unique id of the actor, as given to its `Merger`, if it got any event
+    pub id: Option<usize>,
+
+    /// Simulated time the actor was at
+    pub time: String,
+
+    /// Last event the actor got: its time, the id of its source and its type
+    pub last_event: Option<String>,
+
+    /// What it panicked with
+    pub message: String,
+}
+
+impl fmt::Display for ActorPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "Actor #{} (index {})", id, self.ix)?,
+            None => write!(f, "Actor at index {}", self.ix)?,
+        }
+        write!(f, " panicked at time {}", self.time)?;
+        if let Some(event) = &self.last_event {
+            write!(f, " handling {}", event)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for ActorPanic {}
+
+/// Last event a `Merger` handed to its actor, see
+/// [`Advancer::last_delivered`](trait.Advancer.html#method.last_delivered)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delivered<T> {
+    /// Unique id of the actor the `Merger` belongs to
+    pub id: usize,
+
+    /// Time of the event
+    pub time: T,
+
+    /// Id of the actor it came from, the actor's own for timers and stalls
+    pub src_id: usize,
+
+    /// Type of the event
+    pub kind: &'static str,
+}
+
+/// Advances the actor like the worker does, catching what it panics with
+///
+/// Only the panic is caught, it is still reported as usual on the way.
+pub(crate) fn advance<T, R>(
+    frozen_actor: &mut FrozenActor<T, R>,
+    worker: &mut WorkerStats,
+) -> Result<ActorState<T, R>, ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| worker::advance(frozen_actor, worker))).map_err(
+        |payload| {
+            let message = match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "Box<dyn Any>".to_string(),
+                },
+            };
+
+            let last = frozen_actor.actor.last_delivered();
+            ActorPanic {
+                ix: frozen_actor.ix,
+                id: last.as_ref().map(|last| last.id),
+                time: format!("{:?}", last.as_ref().map_or(frozen_actor.time, |l| l.time)),
+                last_event: last
+                    .map(|last| format!("{} at {:?} from #{}", last.kind, last.time, last.src_id)),
+                message,
+            }
+        },
+    )
+}
@@ -0,0 +1,368 @@
+//! Actors shared by the tests of the executors and of the `Merger`
+//!
+//! Each builder returns a fresh set of actors, so a test can run the same model on several
+//! executors and compare what they return.
+
+use crate::spsc;
+use crate::{load_state, save_state, Checkpoint, CheckpointActor, CheckpointError, Pausable};
+use crate::{ActorState, Advancer, Delivered, Driver, Event, EventType, Merger, TIMER};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Lookahead of the links between the actors
+pub(crate) const LOOKAHEAD: u64 = 10;
+
+/// Number of events in a burst
+pub(crate) const BURST: u64 = 16;
+
+/// Actors of a model, as the executors take them
+pub(crate) type Actors = Vec<Box<dyn Advancer<u64, u64> + Send>>;
+
+/// Passes tokens around a ring, returns the sum of the times it saw them at
+///
+/// Queue 0 loops back to the actor itself, queue 1 goes to the next actor. The merger sends
+/// the null-messages.
+#[derive(Debug)]
+struct RingActor {
+    id: usize,
+    limit: u64,
+    merger: Merger<u64, ()>,
+    time_sum: u64,
+}
+
+impl Advancer<u64, u64> for RingActor {
+    fn advance(&mut self) -> ActorState<u64, u64> {
+        while let Some(event) = self.merger.next() {
+            match event.event_type {
+                EventType::ModelEvent(()) if event.time <= self.limit => {
+                    self.time_sum += event.time;
+                    self.merger.send(
+                        1,
+                        Event {
+                            time: event.time + self.id as u64 + 1 + LOOKAHEAD,
+                            src: self.id,
+                            event_type: EventType::ModelEvent(()),
+                        },
+                    );
+                }
+                EventType::ModelEvent(()) | EventType::Close => {
+                    for ix in 0..self.merger.n_outputs() {
+                        self.merger.send(
+                            ix,
+                            Event {
+                                time: event.time + LOOKAHEAD,
+                                src: self.id,
+                                event_type: EventType::Close,
+                            },
+                        );
+                    }
+                    break;
+                }
+                EventType::Stalled => return ActorState::Continue(event.time),
+                EventType::Null => unreachable!(),
+            }
+        }
+
+        // the closes might still be waiting for room
+        match self.merger.finish() {
+            None => ActorState::Done(self.time_sum),
+            Some(time) => ActorState::Continue(time),
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
+}
+
+/// Ring of `n_actors` passing tokens until `limit`, every other one starts with a token
+pub(crate) fn ring(n_actors: usize, limit: u64) -> Actors {
+    let mut ring_ins = Vec::new();
+    let mut ring_outs = Vec::new();
+    for _ in 0..n_actors {
+        let (prod, cons) = spsc::new(1024);
+        ring_outs.push(prod);
+        ring_ins.push(cons);
+    }
+
+    // a few tokens to start
+    for (id, prod) in ring_outs.iter().enumerate().step_by(2) {
+        prod.push(Event {
+            time: 1,
+            src: id,
+            event_type: EventType::ModelEvent(()),
+        })
+        .unwrap();
+    }
+
+    // actor i sends to i+1
+    ring_outs.rotate_left(1);
+    let mut actors = Vec::new();
+    for (id, (ring_out, ring_in)) in ring_outs.drain(..).zip(ring_ins.drain(..)).enumerate() {
+        let (self_out, self_in) = spsc::new(1024);
+        let prev_id = (id + n_actors - 1) % n_actors;
+
+        let merger = Merger::new(vec![self_in, ring_in], id, vec![id, prev_id])
+            .output(self_out, LOOKAHEAD)
+            .output(ring_out, LOOKAHEAD);
+        let actor = RingActor {
+            id,
+            limit,
+            merger,
+            time_sum: 0,
+        };
+        actors.push(Box::new(actor) as Box<dyn Advancer<u64, u64> + Send>);
+    }
+
+    actors
+}
+
+/// Sends bursts of events to its peer, faster than their link can hold them
+///
+/// Queue 0 loops back to the actor itself, queue 1 goes to the peer. Returns the sum of the
+/// times of the events it got from the peer, weighted by their order of arrival.
+#[derive(Debug)]
+struct BurstActor {
+    id: usize,
+    limit: u64,
+    merger: Merger<u64, ()>,
+    n_received: u64,
+    time_sum: u64,
+}
+
+impl Advancer<u64, u64> for BurstActor {
+    fn advance(&mut self) -> ActorState<u64, u64> {
+        while let Some(event) = self.merger.next() {
+            let id = self.id;
+            let event_at = |time| Event {
+                time,
+                src: id,
+                event_type: EventType::ModelEvent(()),
+            };
+            match event.event_type {
+                EventType::ModelEvent(()) if event.src == 1 => {
+                    self.n_received += 1;
+                    self.time_sum += self.n_received * event.time;
+                }
+                EventType::ModelEvent(()) if event.time <= self.limit => {
+                    for i in 0..BURST {
+                        self.merger.send(1, event_at(event.time + LOOKAHEAD + i));
+                    }
+                    self.merger.send(0, event_at(event.time + BURST));
+                }
+                EventType::ModelEvent(()) => {
+                    let close = Event {
+                        event_type: EventType::Close,
+                        ..event_at(event.time + LOOKAHEAD + BURST)
+                    };
+                    self.merger.send(1, close);
+                }
+                EventType::Close => break,
+                EventType::Stalled => return ActorState::Continue(event.time),
+                EventType::Null => unreachable!(),
+            }
+        }
+
+        match self.merger.finish() {
+            None => ActorState::Done(self.time_sum),
+            Some(time) => ActorState::Continue(time),
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
+}
+
+impl Checkpoint<u64, u64> for BurstActor {
+    fn merger(&mut self) -> &mut dyn Pausable<u64> {
+        &mut self.merger
+    }
+
+    fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+        save_state(out, &(self.n_received, self.time_sum))
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+        let (n_received, time_sum) = load_state(input)?;
+        self.n_received = n_received;
+        self.time_sum = time_sum;
+        Ok(())
+    }
+}
+
+/// Pair of actors sending bursts to each other until `limit`, over links holding `capacity`
+pub(crate) fn burst(limit: u64, capacity: usize) -> Actors {
+    upcast(checkpointed_burst(limit, capacity))
+}
+
+/// Same as [`burst`], as actors that can be checkpointed
+pub(crate) fn checkpointed_burst(limit: u64, capacity: usize) -> Vec<CheckpointActor<u64, u64>> {
+    let (a_to_b, b_from_a) = spsc::new(capacity);
+    let (b_to_a, a_from_b) = spsc::new(capacity);
+
+    let mut actors = Vec::new();
+    let links = vec![(a_to_b, a_from_b), (b_to_a, b_from_a)];
+    for (id, (to_peer, from_peer)) in links.into_iter().enumerate() {
+        let (self_out, self_in) = spsc::new(capacity);
+        self_out
+            .push(Event {
+                time: 1,
+                src: id,
+                event_type: EventType::ModelEvent(()),
+            })
+            .unwrap();
+
+        let merger = Merger::new(vec![self_in, from_peer], id, vec![id, 1 - id])
+            .output(self_out, BURST)
+            .output(to_peer, LOOKAHEAD);
+        let actor = BurstActor {
+            id,
+            limit,
+            merger,
+            n_received: 0,
+            time_sum: 0,
+        };
+        actors.push(Box::new(actor) as CheckpointActor<u64, u64>);
+    }
+
+    actors
+}
+
+/// Checkpointed actors, as the executors take them
+pub(crate) fn upcast(actors: Vec<CheckpointActor<u64, u64>>) -> Actors {
+    actors
+        .into_iter()
+        .map(|actor| -> Box<dyn Advancer<u64, u64> + Send> { actor })
+        .collect()
+}
+
+/// Passes tokens around a ring, holding each for a while on a timer, returns the sum of the
+/// times they left at
+///
+/// Every token also schedules a later timer that is cancelled before it goes off.
+#[derive(Debug)]
+struct TimerActor {
+    id: usize,
+    limit: u64,
+    merger: Merger<u64, bool>,
+    time_sum: u64,
+}
+
+impl Advancer<u64, u64> for TimerActor {
+    fn advance(&mut self) -> ActorState<u64, u64> {
+        while let Some(event) = self.merger.next() {
+            let id = self.id;
+            let event_at = |time, event_type| Event {
+                time,
+                src: id,
+                event_type,
+            };
+            match event.event_type {
+                EventType::ModelEvent(cancelled) if event.src == TIMER => {
+                    assert!(!cancelled, "A cancelled timer went off");
+                    self.time_sum += event.time;
+                    let token = event_at(event.time + LOOKAHEAD, EventType::ModelEvent(false));
+                    self.merger.send(0, token);
+                }
+                EventType::ModelEvent(_) if event.time <= self.limit => {
+                    let cancelled = self.merger.schedule_self(event.time + 7, true);
+                    self.merger
+                        .schedule_self(event.time + self.id as u64 + 1, false);
+                    self.merger.cancel(cancelled);
+                }
+                EventType::ModelEvent(_) | EventType::Close => {
+                    let close = event_at(event.time + LOOKAHEAD, EventType::Close);
+                    self.merger.send(0, close);
+                    break;
+                }
+                EventType::Stalled => return ActorState::Continue(event.time),
+                EventType::Null => unreachable!(),
+            }
+        }
+
+        match self.merger.finish() {
+            None => ActorState::Done(self.time_sum),
+            Some(time) => ActorState::Continue(time),
+        }
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.merger.driven_by(driver);
+    }
+}
+
+/// Ring of `n_actors` holding tokens on timers until `limit`, every other one starts with a token
+pub(crate) fn timer_ring(n_actors: usize, limit: u64) -> Actors {
+    let mut ring_ins = Vec::new();
+    let mut ring_outs = Vec::new();
+    for id in 0..n_actors {
+        let (prod, cons) = spsc::new(1024);
+        if id % 2 == 0 {
+            prod.push(Event {
+                time: 1,
+                src: id,
+                event_type: EventType::ModelEvent(false),
+            })
+            .unwrap();
+        }
+        ring_outs.push(prod);
+        ring_ins.push(cons);
+    }
+
+    // actor i sends to i+1
+    ring_outs.rotate_left(1);
+    let mut actors = Vec::new();
+    for (id, (ring_out, ring_in)) in ring_outs.drain(..).zip(ring_ins.drain(..)).enumerate() {
+        let prev_id = (id + n_actors - 1) % n_actors;
+        let merger = Merger::new(vec![ring_in], id, vec![prev_id]).output(ring_out, LOOKAHEAD);
+        let actor = TimerActor {
+            id,
+            limit,
+            merger,
+            time_sum: 0,
+        };
+        actors.push(Box::new(actor) as Box<dyn Advancer<u64, u64> + Send>);
+    }
+
+    actors
+}
+
+/// Counts how many of the actors it wraps were finished
+#[derive(Debug)]
+struct Finishing {
+    actor: Box<dyn Advancer<u64, u64> + Send>,
+    finished: Arc<AtomicUsize>,
+}
+
+impl Advancer<u64, u64> for Finishing {
+    fn advance(&mut self) -> ActorState<u64, u64> {
+        self.actor.advance()
+    }
+
+    fn driven_by(&mut self, driver: Driver) {
+        self.actor.driven_by(driver);
+    }
+
+    fn last_delivered(&self) -> Option<Delivered<u64>> {
+        self.actor.last_delivered()
+    }
+
+    fn finish(&mut self) {
+        self.finished.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Wraps `actors` to count in `finished` how many of them were finished
+pub(crate) fn finishing(actors: Actors, finished: &Arc<AtomicUsize>) -> Actors {
+    actors
+        .into_iter()
+        .map(|actor| {
+            Box::new(Finishing {
+                actor,
+                finished: Arc::clone(finished),
+            }) as Box<dyn Advancer<u64, u64> + Send>
+        })
+        .collect()
+}
@@ -0,0 +1,345 @@
+//! Wires the actors of a simulation together
+//!
+//! Actors are registered by id, and directed links declared between them. Each link is an spsc
+//! queue, the [`GraphBuilder`](struct.GraphBuilder.html) then hands every actor a
+//! [`Node`](struct.Node.html): a `Merger` of its inbound links, and the producers of its outbound
+//! ones keyed by the id of the receiver.
+//!
+//! ```
+//! use rustasim::GraphBuilder;
+//!
+//! let mut graph = GraphBuilder::<u64, ()>::new().capacity(16);
+//! for id in 1..=3 {
+//!     graph.add_actor(id);
+//! }
+//!
+//! // a ring going both ways
+//! for id in 1..=3 {
+//!     graph.link(id, id % 3 + 1);
+//!     graph.link(id % 3 + 1, id);
+//! }
+//!
+//! let nodes = graph.build();
+//! assert_eq!(nodes[0].sources, vec![2, 3]);
+//! assert!(nodes[2].outputs.contains_key(&1));
+//! ```
+
+use crate::engine::{Event, Merger};
+use crate::net::Cluster;
+use crate::spsc::{self, Consumer, Producer};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+
+/// Capacity of the queues unless told otherwise
+const CAPACITY: usize = 128;
+
+/// Inbound links of an actor, along with their source
+type Inbound<T, U> = Vec<(usize, Consumer<Event<T, U>>)>;
+
+/// How the queues of the links are made
+#[derive(Debug, Clone, Copy)]
+enum Queues {
+    Bounded(usize),
+    Segmented(usize),
+}
+
+/// Builds the links between actors, and the `Merger`s on the receiving end
+#[derive(Debug)]
+pub struct GraphBuilder<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    queues: Queues,
+
+    // registration order
+    ids: Vec<usize>,
+
+    // inbound links of each actor, in the order they were declared
+    inputs: HashMap<usize, Inbound<T, U>>,
+    outputs: HashMap<usize, BTreeMap<usize, Producer<Event<T, U>>>>,
+}
+
+/// What an actor gets out of the [`GraphBuilder`](struct.GraphBuilder.html)
+#[derive(Debug)]
+pub struct Node<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// Id of the actor
+    pub id: usize,
+
+    /// Merges the inbound links, in the order they were declared
+    pub merger: Merger<T, U>,
+
+    /// Id of the source of each inbound link, by input index of the `Merger`
+    pub sources: Vec<usize>,
+
+    /// Outbound links, by id of the receiver
+    ///
+    /// They can be handed to the `Merger` as [outputs](struct.Merger.html#method.output) for it to
+    /// send the null-messages.
+    pub outputs: BTreeMap<usize, Producer<Event<T, U>>>,
+}
+
+impl<T, U> Default for GraphBuilder<T, U>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+    U: Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, U> GraphBuilder<T, U>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+    U: Debug,
+{
+    /// Empty graph, linked by bounded queues of a default capacity
+    pub fn new() -> Self {
+        GraphBuilder {
+            queues: Queues::Bounded(CAPACITY),
+            ids: Vec::new(),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+        }
+    }
+
+    /// Links declared from now on are bounded queues of `capacity` events
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.queues = Queues::Bounded(capacity);
+        self
+    }
+
+    /// Links declared from now on are unbounded queues, allocated `segment_len` events at a time
+    pub fn segmented(mut self, segment_len: usize) -> Self {
+        self.queues = Queues::Segmented(segment_len);
+        self
+    }
+
+    /// Registers actor `id`, the nodes are built in the order they were registered
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was already registered.
+    pub fn add_actor(&mut self, id: usize) {
+        assert!(
+            !self.inputs.contains_key(&id),
+            "Actor #{} is already registered",
+            id
+        );
+
+        self.ids.push(id);
+        self.inputs.insert(id, Vec::new());
+        self.outputs.insert(id, BTreeMap::new());
+    }
+
+    /// Declares a link from actor `src` to actor `dst`, they can be the same
+    ///
+    /// # Panics
+    ///
+    /// Panics if either actor isn't registered, or if they are already linked that way.
+    pub fn link(&mut self, src: usize, dst: usize) {
+        let producer = self.external(src, dst);
+        let outputs = self
+            .outputs
+            .get_mut(&src)
+            .unwrap_or_else(|| panic!("Actor #{} isn't registered", src));
+
+        assert!(
+            outputs.insert(dst, producer).is_none(),
+            "Actor #{} is already linked to #{}",
+            src,
+            dst
+        );
+    }
+
+    /// Declares a link to actor `dst` from outside the graph, and returns its producer
+    ///
+    /// `src` is the id the events sent on it are ordered by, it doesn't have to be registered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` isn't registered.
+    pub fn external(&mut self, src: usize, dst: usize) -> Producer<Event<T, U>> {
+        let (producer, consumer) = match self.queues {
+            Queues::Bounded(capacity) => spsc::new(capacity),
+            Queues::Segmented(segment_len) => spsc::segmented(segment_len),
+        };
+
+        self.inputs
+            .get_mut(&dst)
+            .unwrap_or_else(|| panic!("Actor #{} isn't registered", dst))
+            .push((src, consumer));
+
+        producer
+    }
+
+    /// Builds the node of every actor, in the order they were registered
+    ///
+    /// # Panics
+    ///
+    /// Panics if an actor has no inbound links, a `Merger` needs at least one input.
+    pub fn build(self) -> Vec<Node<T, U>> {
+        let ids = self.ids.clone();
+        self.build_nodes(ids)
+    }
+
+    /// Builds the nodes of the actors this process of `cluster` runs, in the order they were
+    /// registered
+    ///
+    /// Every process must declare the same graph, `owner` gives the rank of the process running
+    /// each actor. Links from or to the actors of other processes are handed to the cluster, those
+    /// between other processes are dropped. External links only go anywhere on the process
+    /// running their receiver.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an actor we run has no inbound links, or if `owner` gives a rank that isn't part
+    /// of the cluster.
+    pub fn build_on(
+        mut self,
+        cluster: &mut Cluster<T, U>,
+        owner: impl Fn(usize) -> usize,
+    ) -> Vec<Node<T, U>>
+    where
+        T: Send + Serialize + DeserializeOwned,
+        U: 'static + Send + Serialize + DeserializeOwned,
+    {
+        let rank = cluster.rank();
+        for (&dst, inputs) in &mut self.inputs {
+            let dst_rank = owner(dst);
+            let outputs = &mut self.outputs;
+            *inputs = std::mem::take(inputs)
+                .into_iter()
+                .filter_map(|(src, consumer)| {
+                    // external links are as good as from the receiver's process
+                    let src_rank = if outputs.contains_key(&src) {
+                        owner(src)
+                    } else {
+                        dst_rank
+                    };
+
+                    if src_rank == dst_rank {
+                        return if dst_rank == rank {
+                            Some((src, consumer))
+                        } else {
+                            None
+                        };
+                    }
+
+                    let producer = outputs.get_mut(&src).unwrap().remove(&dst).unwrap();
+                    if dst_rank == rank {
+                        cluster.receive_from(src_rank, (src, dst), producer);
+                        Some((src, consumer))
+                    } else {
+                        if src_rank == rank {
+                            cluster.send_to(dst_rank, (src, dst), consumer);
+                            outputs.get_mut(&src).unwrap().insert(dst, producer);
+                        }
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        let ids = self
+            .ids
+            .iter()
+            .copied()
+            .filter(|&id| owner(id) == rank)
+            .collect();
+        self.build_nodes(ids)
+    }
+
+    /// Builds the nodes of `ids`
+    fn build_nodes(mut self, ids: Vec<usize>) -> Vec<Node<T, U>> {
+        let mut nodes = Vec::new();
+        for id in ids {
+            assert!(
+                !self.inputs[&id].is_empty(),
+                "Actor #{} has no inbound links",
+                id
+            );
+
+            let (sources, in_queues): (Vec<usize>, Vec<_>) =
+                self.inputs.remove(&id).unwrap().into_iter().unzip();
+
+            nodes.push(Node {
+                id,
+                merger: Merger::new(in_queues, id, sources.clone()),
+                sources,
+                outputs: self.outputs.remove(&id).unwrap(),
+            });
+        }
+
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::graph::*;
+    use crate::EventType;
+
+    #[test]
+    fn test_wiring() {
+        let mut graph = GraphBuilder::<u64, usize>::new().capacity(4);
+        for id in (0..4).rev() {
+            graph.add_actor(id);
+        }
+
+        // everyone to everyone, including themselves
+        for src in 0..4 {
+            for dst in 0..4 {
+                graph.link(src, dst);
+            }
+        }
+        let world = graph.external(10, 2);
+
+        let mut nodes = graph.build();
+        let ids: Vec<usize> = nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids, vec![3, 2, 1, 0]);
+        assert_eq!(nodes[1].sources, vec![0, 1, 2, 3, 10]);
+
+        // everyone tells the others who they are, and closes
+        let introduce = |producer: &Producer<Event<u64, usize>>, id| {
+            for (time, event_type) in [(1, EventType::ModelEvent(id)), (2, EventType::Close)] {
+                producer
+                    .push(Event {
+                        time,
+                        src: id,
+                        event_type,
+                    })
+                    .unwrap();
+            }
+        };
+        for node in &nodes {
+            for producer in node.outputs.values() {
+                introduce(producer, node.id);
+            }
+        }
+        introduce(&world, 10);
+
+        // node 2 heard from everyone
+        let node = &mut nodes[1];
+        for (ix, &src) in node.sources.iter().enumerate() {
+            let event = node.merger.next().unwrap();
+            assert_eq!(event.src, ix);
+            assert!(matches!(event.event_type, EventType::ModelEvent(id) if id == src));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Actor #1 is already linked to #0")]
+    fn test_duplicate_link() {
+        let mut graph = GraphBuilder::<u64, ()>::new();
+        graph.add_actor(0);
+        graph.add_actor(1);
+        graph.link(1, 0);
+        graph.link(1, 0);
+    }
+}
@@ -8,25 +8,60 @@
 //! the type of model being run, and should probably eventually be pulled out into its own crate.
 
 use atomic_counter::RelaxedCounter;
+use crossbeam_deque::Worker;
 use parking_lot::Mutex;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use std::thread;
 
-//use slog::*;
-//use slog_async;
-
+mod async_actor;
+mod checkpoint;
+mod end;
 mod engine;
 mod err;
+mod failure;
+#[cfg(test)]
+mod fixtures;
+mod graph;
+mod links;
+pub mod net;
+mod partition;
+mod sequential;
+mod session;
 pub mod spsc;
-mod tree;
+mod stats;
+mod timewarp;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod tree;
+mod watchdog;
+mod window;
 mod worker;
 
-pub use self::engine::{Event, EventType, Merger};
+pub use self::async_actor::{AsyncActor, Ctx};
+pub use self::checkpoint::{
+    checkpoint, load_state, restore, save_state, Checkpoint, CheckpointActor, CheckpointError,
+    Pausable, Resumed,
+};
+pub use self::engine::{Event, EventType, Merger, TimerId, TIMER};
 pub use self::err::{PopError, PushError};
-pub use self::worker::{run, ActorState, Advancer, LockedTaskHeap};
+pub use self::failure::{ActorPanic, Delivered};
+pub use self::graph::{GraphBuilder, Node};
+pub use self::partition::Partition;
+pub use self::sequential::start_sequential;
+pub use self::session::Session;
+pub use self::stats::{Report, Stats, WorkerStats};
+pub use self::timewarp::{start_optimistic, Reversible};
+pub use self::watchdog::Watchdog;
+use self::watchdog::Watching;
+pub use self::window::start_windowed;
+pub use self::worker::{run, ActorState, Advancer, Driver, LockedTaskHeap, Parking, Scheduler};
+use self::worker::{run_priority, Finished, Mode, Pool};
 
 /// Maintains the state of the actor while it's at rest
 #[derive(Debug)]
@@ -36,6 +71,16 @@ where
 {
     time: T,
     actor: Box<dyn Advancer<T, R> + Send>,
+
+    // when it was put back, ties in time are first come first served
+    turn: u64,
+
+    // where it was given to `start`, and what it did so far
+    ix: usize,
+    stats: Stats,
+
+    // group of the worker it belongs to, see `Partition`
+    group: usize,
 }
 
 impl<T, R> Ord for FrozenActor<T, R>
@@ -43,7 +88,7 @@ where
     T: Ord + Copy + num::Zero,
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.time.cmp(&self.time)
+        (other.time, other.turn).cmp(&(self.time, self.turn))
     }
 }
 
@@ -52,7 +97,7 @@ where
     T: Ord + Copy + num::Zero,
 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(other.time.cmp(&self.time))
+        Some(self.cmp(other))
     }
 }
 
@@ -61,57 +106,294 @@ where
     T: Ord + Copy + num::Zero,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.time == other.time
+        (self.time, self.turn) == (other.time, other.turn)
     }
 }
 impl<T, R> Eq for FrozenActor<T, R> where T: Ord + Copy + num::Zero {}
 
-/// Starts the actors on `num_cpus` workers
+/// Random number generator of the actors, see [`actor_rng`](fn.actor_rng.html)
+pub type ActorRng = ChaCha20Rng;
+
+/// Builds the random number generator for actor `id` from the simulation-wide `seed`
+///
+/// Each actor gets its own stream, the values it draws therefore only depend on the events it
+/// processed and not on how the actors were scheduled on the workers. The position in the stream
+/// can be saved with `get_word_pos` for a [checkpoint](fn.checkpoint.html).
+pub fn actor_rng(seed: u64, id: usize) -> ActorRng {
+    ActorRng::seed_from_u64(seed ^ (id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Starts the actors on `num_cpus` workers, until `end` if given
 ///
 /// This function takes care of all the necessary building of the workers and connecting to launch
-/// them
+/// them. With an `end`, no event from then on is delivered: the `Merger` of every actor runs out of
+/// events at that time, and its [`finish`](trait.Advancer.html#method.finish) hook is called once
+/// it is done, see the [engine documentation](engine/index.html#end-of-the-run). Without one, the
+/// actors have to close on their own.
+///
+/// The results are by actor, in the order they were given: `results[ix]` is what actor `ix`
+/// returned, whichever worker finished it. Along with them comes a [`Report`](struct.Report.html)
+/// of what the actors and workers did, in the same order.
+///
+/// If an actor panics, the workers stop and the panic comes back as an
+/// [`ActorPanic`](struct.ActorPanic.html) instead.
 // TODO check if we can remove dynamic dispatch in simple cases
-pub fn start<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send + Copy>(
+pub fn start<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
+    num_cpus: usize,
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    start_with(Scheduler::default(), num_cpus, actors, end)
+}
+
+/// Starts the actors on `num_cpus` workers, scheduled by `scheduler`
+pub fn start_with<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
+    scheduler: Scheduler,
+    num_cpus: usize,
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    match scheduler {
+        Scheduler::Fifo => start_fifo(num_cpus, actors, end, None, None),
+        Scheduler::Priority => {
+            let partition = Partition::round_robin(actors.len(), num_cpus);
+            start_priority(actors, end, &partition, None)
+        }
+    }
+}
+
+/// Starts the actors on a worker per group of `partition`, scheduled by `scheduler`
+///
+/// Each actor starts out with the worker of its group. The workers go back to their own actors
+/// first, and only take on those of the others when they run out. Actors are given in the order
+/// they were numbered by the partition.
+///
+/// # Panics
+///
+/// Panics if the partition isn't of as many actors as were given.
+pub fn start_partitioned<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
+    scheduler: Scheduler,
+    partition: &Partition,
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    assert_eq!(
+        partition.n_actors(),
+        actors.len(),
+        "The partition must be of as many actors as were given"
+    );
+
+    match scheduler {
+        Scheduler::Fifo => start_fifo(partition.n_groups(), actors, end, Some(partition), None),
+        Scheduler::Priority => start_priority(actors, end, partition, None),
+    }
+}
+
+/// Starts the actors like [`start_partitioned`](fn.start_partitioned.html), under `watchdog`
+///
+/// Whenever the least time of the actors doesn't move for a while, the watchdog dumps what their
+/// `Merger`s are waiting on, see [`Watchdog`](struct.Watchdog.html).
+///
+/// # Panics
+///
+/// Panics if the partition isn't of as many actors as were given.
+pub fn start_watched<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
+    watchdog: &Watchdog,
+    scheduler: Scheduler,
+    partition: &Partition,
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    assert_eq!(
+        partition.n_actors(),
+        actors.len(),
+        "The partition must be of as many actors as were given"
+    );
+
+    match scheduler {
+        Scheduler::Fifo => start_fifo(
+            partition.n_groups(),
+            actors,
+            end,
+            Some(partition),
+            Some(watchdog),
+        ),
+        Scheduler::Priority => start_priority(actors, end, partition, Some(watchdog)),
+    }
+}
+
+/// Spawns worker `i`, pinned to its core if the partition says so
+fn spawn<F, R>(i: usize, partition: Option<&Partition>, work: F) -> thread::JoinHandle<R>
+where
+    F: 'static + Send + FnOnce() -> R,
+    R: 'static + Send,
+{
+    let pinned = partition.is_some_and(Partition::is_pinned);
+    thread::spawn(move || {
+        if pinned {
+            partition::pin_to(i);
+        }
+        work()
+    })
+}
+
+fn start_fifo<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
     num_cpus: usize,
     mut actors: Vec<Box<dyn Advancer<T, R> + Send>>,
-) -> Vec<R> {
+    end: Option<T>,
+    partition: Option<&Partition>,
+    watchdog: Option<&Watchdog>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
     // Start the workers
     let n_actors = actors.len();
     let shared_counter = Arc::new(RelaxedCounter::new(0));
 
-    // Initialize the heaps
-    let n_heaps = std::cmp::min(16, n_actors);
+    // Initialize the heaps, one per group when partitioned
+    let n_heaps = match partition {
+        Some(partition) => partition.n_groups(),
+        None => n_actors.clamp(1, 16),
+    };
     let mut heaps = Vec::new();
     for _ in 0..n_heaps {
         let task_heap: LockedTaskHeap<T, R> = Arc::new(Mutex::new(VecDeque::new()));
         heaps.push(task_heap);
     }
 
-    for (i, actor) in actors.drain(..).enumerate() {
-        let heap_ix = i % n_heaps;
+    for (i, mut actor) in actors.drain(..).enumerate() {
+        actor.driven_by(Driver::new(Mode::Conservative, None));
+        let heap_ix = partition.map_or(i % n_heaps, |partition| partition.group(i));
         let frozen = FrozenActor {
             time: T::zero(),
             actor,
+            turn: 0,
+            ix: i,
+            stats: Stats::default(),
+            group: heap_ix,
         };
         heaps[heap_ix].lock().push_back(frozen);
     }
 
+    let watching = watchdog.map(|watchdog| watchdog.watch::<T>(n_actors));
+    let parking = Arc::new(Parking::default());
     let mut handles = Vec::new();
     for i in 0..num_cpus {
-        // start this worker
+        // start this worker, on the heap of its group if there is one
+        let home = partition.map(|_| i);
         handles.push({
-            let cloned_heaps = heaps.iter().map(|x| Arc::clone(&x)).collect();
+            let cloned_heaps = heaps.iter().map(Arc::clone).collect();
             let counter_clone = Arc::clone(&shared_counter);
-            thread::spawn(move || run(i, counter_clone, n_actors, cloned_heaps))
+            let parking = Arc::clone(&parking);
+            let watched = watching.as_ref().map(Watching::worker);
+            spawn(i, partition, move || {
+                if let Some(watched) = watched {
+                    watched();
+                }
+                end::with(end, || {
+                    run(i, counter_clone, n_actors, cloned_heaps, parking, home)
+                })
+            })
+        });
+    }
+
+    let finished = collect(n_actors, handles);
+    if let Some(watching) = watching {
+        watching.stop();
+    }
+    finished
+}
+
+fn start_priority<T: 'static + Ord + Copy + Debug + Send + num::Zero, R: 'static + Send>(
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+    partition: &Partition,
+    watchdog: Option<&Watchdog>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    let num_cpus = partition.n_groups();
+    let n_actors = actors.len();
+
+    // Each worker starts with its group
+    let workers: Vec<Worker<FrozenActor<T, R>>> =
+        (0..num_cpus).map(|_| Worker::new_fifo()).collect();
+    for (i, mut actor) in actors.into_iter().enumerate() {
+        actor.driven_by(Driver::new(Mode::Conservative, None));
+        let group = partition.group(i);
+        workers[group].push(FrozenActor {
+            time: T::zero(),
+            actor,
+            turn: 0,
+            ix: i,
+            stats: Stats::default(),
+            group,
         });
     }
 
-    // Wait for the workers to be done
-    let mut counts = Vec::new();
+    let pool = Arc::new(Pool {
+        stealers: workers.iter().map(Worker::stealer).collect(),
+        n_hungry: AtomicUsize::new(0),
+        n_done: RelaxedCounter::new(0),
+        n_tasks: n_actors,
+
+        parking: Parking::default(),
+    });
+
+    let watching = watchdog.map(|watchdog| watchdog.watch::<T>(n_actors));
+    let handles: Vec<_> = workers
+        .into_iter()
+        .enumerate()
+        .map(|(i, worker)| {
+            let pool = Arc::clone(&pool);
+            let watched = watching.as_ref().map(Watching::worker);
+            spawn(i, Some(partition), move || {
+                if let Some(watched) = watched {
+                    watched();
+                }
+                end::with(end, || run_priority(i, pool, worker))
+            })
+        })
+        .collect();
+
+    let finished = collect(n_actors, handles);
+    if let Some(watching) = watching {
+        watching.stop();
+    }
+    finished
+}
+
+/// Waits for the workers to be done, and puts their results and counters together, by actor
+///
+/// If actors panicked, the first worker to have seen one tells which.
+fn collect<R>(
+    n_actors: usize,
+    handles: Vec<thread::JoinHandle<Result<Finished<R>, ActorPanic>>>,
+) -> Result<(Vec<R>, Report), ActorPanic> {
+    let mut results: Vec<Option<R>> = (0..n_actors).map(|_| None).collect();
+    let mut report = Report {
+        actors: vec![Stats::default(); n_actors],
+        workers: Vec::new(),
+    };
+    let mut panicked = None;
     for h in handles {
-        let local_counts: Vec<R> = h.join().unwrap();
-        counts.extend(local_counts);
+        match h.join().unwrap() {
+            Ok((local_results, actor_stats, worker_stats)) => {
+                for (ix, result) in local_results {
+                    results[ix] = Some(result);
+                }
+                for (ix, stats) in actor_stats {
+                    report.actors[ix] = stats;
+                }
+                report.workers.push(worker_stats);
+            }
+            Err(panic) => {
+                panicked.get_or_insert(panic);
+            }
+        }
     }
 
-    counts
+    match panicked {
+        Some(panic) => Err(panic),
+        None => {
+            let results = results.into_iter().map(Option::unwrap).collect();
+            Ok((results, report))
+        }
+    }
 }
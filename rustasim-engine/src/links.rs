@@ -0,0 +1,149 @@
+//! What the two ends of a link know of each other
+//!
+//! The queues only carry the events. Whatever else the `Merger`s on both ends of a queue tell each
+//! other lives here, behind the queue's id: whether the consumer is blocked on its own outputs or
+//! paused for a checkpoint, the slots of the actors on both ends for the executors that wake them
+//! up, and what the producer cancelled when run optimistically. Each end looks its state up once,
+//! when it gets the queue, and the network bridges do the same for the queues they stand in for.
+
+use crate::timewarp::Voids;
+use parking_lot::{const_mutex, Mutex};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+/// Slot of an actor that isn't known to the executor, or isn't waiting
+const NO_SLOT: usize = usize::MAX;
+
+/// State of the link of each queue still held by someone, by queue id
+static LINKS: Mutex<Option<HashMap<u64, Weak<LinkState>>>> = const_mutex(None);
+
+/// What the ends of a queue tell each other
+#[derive(Debug)]
+pub(crate) struct LinkState {
+    /// Slot of the consuming actor in the sequential executor, `NO_SLOT` otherwise
+    consumer: AtomicUsize,
+
+    /// Slot of the producing actor in the sequential or window executor while it waits for room
+    /// in the queue, `NO_SLOT` otherwise
+    producer: AtomicUsize,
+
+    /// Whether the consumer is itself waiting for room in one of its output queues
+    consumer_blocked: AtomicBool,
+
+    /// Whether the consumer is paused for a checkpoint, it won't pop until it is resumed
+    consumer_paused: AtomicBool,
+
+    /// Elements cancelled by the producer, only used by the optimistic executor
+    voids: Arc<Voids>,
+}
+
+/// State of the link of queue `id`, shared with whoever else holds it
+pub(crate) fn get(id: u64) -> Arc<LinkState> {
+    let mut links = LINKS.lock();
+    let links = links.get_or_insert_with(HashMap::new);
+    if let Some(state) = links.get(&id).and_then(Weak::upgrade) {
+        return state;
+    }
+
+    // the queues nobody holds anymore go before we grow
+    if links.len() == links.capacity() {
+        links.retain(|_, state| state.strong_count() > 0);
+    }
+
+    let state = Arc::new(LinkState {
+        consumer: AtomicUsize::new(NO_SLOT),
+        producer: AtomicUsize::new(NO_SLOT),
+        consumer_blocked: AtomicBool::new(false),
+        consumer_paused: AtomicBool::new(false),
+        voids: Arc::new(Voids::default()),
+    });
+    links.insert(id, Arc::downgrade(&state));
+    state
+}
+
+/// Slot stored for `slot`
+fn to_slot(slot: Option<usize>) -> usize {
+    slot.unwrap_or(NO_SLOT)
+}
+
+/// Slot stored back
+fn from_slot(slot: usize) -> Option<usize> {
+    Some(slot).filter(|&slot| slot != NO_SLOT)
+}
+
+impl LinkState {
+    /// Slot of the consuming actor in the sequential executor
+    pub(crate) fn consumer(&self) -> Option<usize> {
+        from_slot(self.consumer.load(Ordering::Relaxed))
+    }
+
+    /// Sets the slot of the consuming actor in the sequential executor, pushes then wake it up
+    pub(crate) fn set_consumer(&self, slot: Option<usize>) {
+        self.consumer.store(to_slot(slot), Ordering::Relaxed);
+    }
+
+    /// Slot of the producing actor while it waits for room
+    pub(crate) fn waiting_producer(&self) -> Option<usize> {
+        from_slot(self.producer.load(Ordering::Relaxed))
+    }
+
+    /// Marks the producer as waiting for room, as the actor in `slot` of the sequential or window
+    /// executor, until called again with `None`
+    pub(crate) fn set_waiting(&self, slot: Option<usize>) {
+        self.producer.store(to_slot(slot), Ordering::Release);
+    }
+
+    /// Whether the producer holds events back until there is room, only known for producers run
+    /// by the sequential or window executor
+    ///
+    /// Once it says no, whatever the producer flushed from its backlog is in the queue.
+    pub(crate) fn is_producer_waiting(&self) -> bool {
+        self.producer.load(Ordering::Acquire) != NO_SLOT
+    }
+
+    /// Whether the consumer is itself waiting for room in one of its output queues
+    pub(crate) fn is_consumer_blocked(&self) -> bool {
+        self.consumer_blocked.load(Ordering::Relaxed)
+    }
+
+    /// Tells the producer whether the consumer is waiting for room in one of its output queues
+    pub(crate) fn set_blocked(&self, blocked: bool) {
+        self.consumer_blocked.store(blocked, Ordering::Relaxed);
+    }
+
+    /// Whether the consumer is paused for a checkpoint, it won't pop until it is resumed
+    pub(crate) fn is_consumer_paused(&self) -> bool {
+        self.consumer_paused.load(Ordering::Relaxed)
+    }
+
+    /// Tells the producer whether the consumer is paused for a checkpoint
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.consumer_paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Elements of the queue cancelled by its producer
+    pub(crate) fn voids(&self) -> &Arc<Voids> {
+        &self.voids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::links;
+    use crate::spsc;
+
+    #[test]
+    fn test_shared() {
+        let (p, c) = spsc::new::<u64>(1);
+        let producer = links::get(p.id());
+        let consumer = links::get(c.id());
+
+        consumer.set_blocked(true);
+        assert!(producer.is_consumer_blocked());
+
+        // the state goes with the last one holding it
+        drop((producer, consumer));
+        assert!(!links::get(p.id()).is_consumer_blocked());
+    }
+}
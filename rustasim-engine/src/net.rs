@@ -0,0 +1,977 @@
+//! Runs a simulation over several processes, linked by sockets
+//!
+//! Every process builds the same simulation, but only runs its own share of the actors. Links
+//! between actors of different processes are still spsc queues on both ends, so the `Merger`s
+//! don't know the difference: a bridge thread pops what the sender pushes and writes it to the
+//! socket, and another pushes it to the receiver's queue on the other end. What the receiver
+//! tells its senders through its queues, such as being done or waiting for room itself, goes back
+//! the same way.
+//!
+//! Processes are told their rank and the [`Address`](enum.Address.html) of every process, rank
+//! included, e.g. by a [`Launch`](struct.Launch.html). Once the processes are
+//! [connected](struct.Cluster.html#method.connect), the links that cross over are handed to the
+//! [`Cluster`](struct.Cluster.html), usually by
+//! [`GraphBuilder::build_on`](../struct.GraphBuilder.html#method.build_on). It is then started
+//! along with the actors, and finished once they are done.
+//!
+//! A link is named by the ids of the actors on either end, `(src, dst)`, which must be the same
+//! on both processes. Events that arrive while the receiver's queue is full wait in the bridge,
+//! up to as many again as the queue holds. The bridge then stops reading from the socket, and the
+//! sender is held back as if the queue were its own, unless the receiver is itself blocked or
+//! paused: senders on the same process wouldn't wait on it either.
+
+use crate::engine::Event;
+use crate::links::{self, LinkState};
+use crate::spsc::{Consumer, Producer};
+use crossbeam_utils::Backoff;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Ids of the sender and the receiver of a link
+pub type Link = (usize, usize);
+
+// how long we wait for the other processes to show up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// how often the reader looks at the flags of its queues
+const POLL: Duration = Duration::from_millis(1);
+
+// and how often it gets back to what it couldn't push yet
+const RETRY: Duration = Duration::from_micros(20);
+
+// flags of the receiving end of a queue, as they go over the wire
+const DONE: u8 = 1;
+const BLOCKED: u8 = 2;
+const PAUSED: u8 = 4;
+
+/// Where a process can be reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// TCP, written `host:port`
+    Tcp(SocketAddr),
+
+    /// Unix socket, written `unix:path`
+    Unix(PathBuf),
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.strip_prefix("unix:") {
+            Some(path) => Ok(Address::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(Address::Tcp)
+                .map_err(|e| format!("Bad address {}: {}", s, e)),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(addr) => write!(f, "{}", addr),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A connected socket, of either kind
+#[derive(Debug)]
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn connect(addr: &Address) -> io::Result<Stream> {
+        match addr {
+            Address::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                Ok(Stream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => Ok(Stream::Unix(UnixStream::connect(path)?)),
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Unix sockets aren't supported here",
+            )),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Listens for the processes of higher rank
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn bind(addr: &Address) -> io::Result<Listener> {
+        match addr {
+            Address::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr)?)),
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                // a socket left behind by an earlier run, nobody answers on it anymore
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    let stale = metadata.file_type().is_socket()
+                        && matches!(
+                            UnixStream::connect(path),
+                            Err(e) if e.kind() == ErrorKind::ConnectionRefused
+                        );
+                    if !stale {
+                        return Err(io::Error::new(
+                            ErrorKind::AddrInUse,
+                            format!("{} is in use", path.display()),
+                        ));
+                    }
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Unix sockets aren't supported here",
+            )),
+        }
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok(Stream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Stream::Unix(listener.accept()?.0)),
+        }
+    }
+}
+
+/// What goes over the wire
+#[derive(Serialize, Deserialize)]
+enum Frame<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// An event pushed on a link
+    Event(Link, Event<T, U>),
+
+    /// The flags of the receiving end of a link changed
+    Flags(Link, u8),
+
+    /// The sender is done, nothing else will come
+    Bye,
+}
+
+/// What the reader of a socket has for its writer
+enum Note {
+    /// The peer's receiver of our outbound link `ix` has these flags
+    Apply(usize, u8),
+
+    /// Our receiver of `link` has these flags now, the peer must be told
+    Tell(Link, u8),
+}
+
+/// The links to and from another process
+#[derive(Debug)]
+struct Peer<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    stream: Stream,
+
+    // links to the peer, we pop what our actors push
+    outbound: Vec<(Link, Consumer<Event<T, U>>)>,
+
+    // links from the peer, we push what its actors sent
+    inbound: Vec<(Link, Producer<Event<T, U>>)>,
+}
+
+/// The processes of a simulation, connected to one another
+#[derive(Debug)]
+pub struct Cluster<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    rank: usize,
+
+    // by rank, `None` for ourselves
+    peers: Vec<Option<Peer<T, U>>>,
+}
+
+impl<T, U> Cluster<T, U>
+where
+    T: 'static + Ord + Copy + Send + num::Zero + Serialize + DeserializeOwned,
+    U: 'static + Send + Serialize + DeserializeOwned,
+{
+    /// Connects process `rank` to every other process, `addrs` being where each of them listens
+    ///
+    /// Every process must be given the same addresses. Processes that aren't listening yet are
+    /// waited for, up to a minute.
+    pub fn connect(rank: usize, addrs: &[Address]) -> io::Result<Self> {
+        assert!(
+            rank < addrs.len(),
+            "Rank {} is out of {} processes",
+            rank,
+            addrs.len()
+        );
+        let listener = Listener::bind(&addrs[rank])?;
+        let mut peers: Vec<Option<Peer<T, U>>> = addrs.iter().map(|_| None).collect();
+
+        // lower ranks are listening already, or about to
+        for (peer, addr) in addrs.iter().enumerate().take(rank) {
+            let started = Instant::now();
+            let mut stream = loop {
+                match Stream::connect(addr) {
+                    Ok(stream) => break stream,
+                    Err(e) if started.elapsed() > CONNECT_TIMEOUT => return Err(e),
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            stream.write_all(&(rank as u32).to_le_bytes())?;
+            peers[peer] = Some(Peer::new(stream));
+        }
+
+        // higher ranks come to us, in any order
+        for _ in rank + 1..addrs.len() {
+            let mut stream = listener.accept()?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf)?;
+
+            let peer = u32::from_le_bytes(buf) as usize;
+            if peer <= rank || peer >= addrs.len() || peers[peer].is_some() {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Process {} got a connection from rank {}", rank, peer),
+                ));
+            }
+            peers[peer] = Some(Peer::new(stream));
+        }
+
+        Ok(Cluster { rank, peers })
+    }
+
+    /// Rank of this process
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Number of processes, this one included
+    pub fn n_ranks(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Sends what is pushed on `link` to process `rank`, `queue` being the receiving end
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rank` is our own.
+    pub fn send_to(&mut self, rank: usize, link: Link, queue: Consumer<Event<T, U>>) {
+        self.peer(rank).outbound.push((link, queue));
+    }
+
+    /// Pushes what process `rank` sends on `link` to `queue`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rank` is our own.
+    pub fn receive_from(&mut self, rank: usize, link: Link, queue: Producer<Event<T, U>>) {
+        self.peer(rank).inbound.push((link, queue));
+    }
+
+    fn peer(&mut self, rank: usize) -> &mut Peer<T, U> {
+        let own = self.rank;
+        self.peers[rank]
+            .as_mut()
+            .unwrap_or_else(|| panic!("Process {} can't link to itself", own))
+    }
+
+    /// Starts carrying the events over, the links must all have been given by then
+    pub fn start(self) -> io::Result<Bridges> {
+        let closing = Arc::new(AtomicBool::new(false));
+        let mut threads = Vec::new();
+        for peer in self.peers.into_iter().flatten() {
+            let reader = peer.stream.try_clone()?;
+            reader.set_read_timeout(Some(POLL))?;
+
+            let (notes_tx, notes_rx) = mpsc::channel();
+            let outbound_links = peer.outbound.iter().map(|(link, _)| *link).collect();
+            let inbound = peer.inbound;
+            threads.push(thread::spawn(move || {
+                read(reader, inbound, outbound_links, notes_tx)
+            }));
+
+            let writer = BufWriter::new(peer.stream);
+            let outbound = peer.outbound;
+            let closing = Arc::clone(&closing);
+            threads.push(thread::spawn(move || {
+                write(writer, outbound, notes_rx, closing)
+            }));
+        }
+
+        Ok(Bridges { threads, closing })
+    }
+}
+
+impl<T, U> Peer<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    fn new(stream: Stream) -> Self {
+        Peer {
+            stream,
+            outbound: Vec::new(),
+            inbound: Vec::new(),
+        }
+    }
+}
+
+/// The bridges of a started [`Cluster`](struct.Cluster.html)
+#[derive(Debug)]
+pub struct Bridges {
+    threads: Vec<thread::JoinHandle<io::Result<()>>>,
+    closing: Arc<AtomicBool>,
+}
+
+impl Bridges {
+    /// Sends what's left and waits for the other processes to be done
+    ///
+    /// Our actors must all be done by then: nothing pushed afterwards makes it over.
+    pub fn finish(self) -> io::Result<()> {
+        self.closing.store(true, Ordering::Release);
+
+        let mut result = Ok(());
+        for thread in self.threads {
+            let done = thread.join().unwrap();
+            if result.is_ok() {
+                result = done;
+            }
+        }
+        result
+    }
+}
+
+/// Writes `frame` to `out`, behind its length
+fn write_frame<T, U>(out: &mut impl Write, buf: &mut Vec<u8>, frame: &Frame<T, U>) -> io::Result<()>
+where
+    T: Ord + Copy + num::Zero + Serialize,
+    U: Serialize,
+{
+    buf.clear();
+    bincode::serialize_into(&mut *buf, frame).map_err(io::Error::other)?;
+    out.write_all(&(buf.len() as u32).to_le_bytes())?;
+    out.write_all(buf)
+}
+
+/// Flags of the receiving end of `queue`, whose link is in `state`
+fn flags<V>(queue: &Producer<V>, state: &LinkState) -> u8 {
+    let mut flags = 0;
+    if queue.is_consumer_done() {
+        flags |= DONE;
+    }
+    if state.is_consumer_blocked() {
+        flags |= BLOCKED;
+    }
+    if state.is_consumer_paused() {
+        flags |= PAUSED;
+    }
+    flags
+}
+
+/// Whether as many events wait for `queue` in the bridge as it holds, `pending` of them, while its
+/// receiver, whose link is in `state`, is bound to make room
+fn is_backed_up<V>(queue: &Producer<V>, state: &LinkState, pending: usize) -> bool {
+    pending >= queue.capacity() && !state.is_consumer_blocked() && !state.is_consumer_paused()
+}
+
+/// Forwards what our actors push to the peer, and how our receivers are doing
+///
+/// Flags sent by the peer come from the reader, and are applied to the queues they are about.
+/// This is the only thread writing to the socket.
+fn write<T, U>(
+    mut out: BufWriter<Stream>,
+    outbound: Vec<(Link, Consumer<Event<T, U>>)>,
+    notes: mpsc::Receiver<Note>,
+    closing: Arc<AtomicBool>,
+) -> io::Result<()>
+where
+    T: Ord + Copy + num::Zero + Serialize,
+    U: Serialize,
+{
+    // we stand in for the receivers on the peer
+    let states: Vec<_> = outbound
+        .iter()
+        .map(|(_, queue)| links::get(queue.id()))
+        .collect();
+
+    let mut buf = Vec::new();
+    let backoff = Backoff::new();
+    loop {
+        let closing = closing.load(Ordering::Acquire);
+        let mut busy = false;
+
+        for note in notes.try_iter() {
+            match note {
+                Note::Apply(ix, flags) => {
+                    if flags & DONE != 0 {
+                        outbound[ix].1.set_done();
+                    }
+                    states[ix].set_blocked(flags & BLOCKED != 0);
+                    states[ix].set_paused(flags & PAUSED != 0);
+                }
+                Note::Tell(link, flags) => {
+                    write_frame::<T, U>(&mut out, &mut buf, &Frame::Flags(link, flags))?;
+                    busy = true;
+                }
+            }
+        }
+
+        for (link, queue) in &outbound {
+            while let Ok(event) = queue.pop() {
+                write_frame(&mut out, &mut buf, &Frame::Event(*link, event))?;
+                busy = true;
+            }
+        }
+
+        // everything our actors pushed went out, they won't push anything else
+        if closing {
+            write_frame::<T, U>(&mut out, &mut buf, &Frame::Bye)?;
+            return out.flush();
+        }
+
+        if busy {
+            out.flush()?;
+            backoff.reset();
+        } else if backoff.is_completed() {
+            thread::sleep(Duration::from_micros(50));
+        } else {
+            backoff.snooze();
+        }
+    }
+}
+
+/// Pushes what the peer sends to our actors, and tells the writer about the receivers on both
+/// ends
+fn read<T, U>(
+    mut stream: Stream,
+    inbound: Vec<(Link, Producer<Event<T, U>>)>,
+    outbound_links: Vec<Link>,
+    notes: mpsc::Sender<Note>,
+) -> io::Result<()>
+where
+    T: Ord + Copy + num::Zero + Serialize + DeserializeOwned,
+    U: Serialize + DeserializeOwned,
+{
+    let inbound_ix: HashMap<Link, usize> = inbound
+        .iter()
+        .enumerate()
+        .map(|(ix, (link, _))| (*link, ix))
+        .collect();
+    let outbound_ix: HashMap<Link, usize> = outbound_links
+        .iter()
+        .enumerate()
+        .map(|(ix, link)| (*link, ix))
+        .collect();
+
+    // we stand in for the senders on the peer
+    let states: Vec<_> = inbound
+        .iter()
+        .map(|(_, queue)| links::get(queue.id()))
+        .collect();
+
+    // what didn't fit in the queues yet, at most as much again as they hold unless their receiver
+    // is blocked or paused, and the flags the peer knows of
+    let mut pending: Vec<VecDeque<Event<T, U>>> = inbound.iter().map(|_| VecDeque::new()).collect();
+    let mut sent_flags: Vec<u8> = inbound.iter().map(|_| 0).collect();
+
+    let mut received = Vec::new();
+    let mut chunk = vec![0; 1 << 16];
+    let mut bye = false;
+    let mut retrying = false;
+
+    loop {
+        for (ix, (link, queue)) in inbound.iter().enumerate() {
+            let pending = &mut pending[ix];
+            if queue.is_consumer_done() {
+                pending.clear();
+            }
+            while let Some(event) = pending.pop_front() {
+                if let Err(crate::PushError(event)) = queue.push(event) {
+                    pending.push_front(event);
+                    break;
+                }
+            }
+
+            // the writer is gone once we're closing, nobody cares anymore
+            let flags = flags(queue, &states[ix]);
+            if flags != sent_flags[ix] {
+                sent_flags[ix] = flags;
+                let _ = notes.send(Note::Tell(*link, flags));
+            }
+        }
+
+        // whole frames only, the rest comes with the next read, and only while there's room
+        let backed_up = |pending: &[VecDeque<Event<T, U>>]| {
+            (0..inbound.len())
+                .any(|ix| is_backed_up(&inbound[ix].1, &states[ix], pending[ix].len()))
+        };
+        let mut start = 0;
+        while received.len() - start >= 4 && !backed_up(&pending) {
+            let mut len = [0; 4];
+            len.copy_from_slice(&received[start..start + 4]);
+            let end = start + 4 + u32::from_le_bytes(len) as usize;
+            if received.len() < end {
+                break;
+            }
+
+            let frame: Frame<T, U> = bincode::deserialize(&received[start + 4..end])
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            start = end;
+
+            match frame {
+                Frame::Event(link, event) => {
+                    let ix = *inbound_ix.get(&link).ok_or_else(|| unknown(link))?;
+                    let (_, queue) = &inbound[ix];
+                    if queue.is_consumer_done() {
+                        continue;
+                    }
+                    if !pending[ix].is_empty() {
+                        pending[ix].push_back(event);
+                    } else if let Err(crate::PushError(event)) = queue.push(event) {
+                        pending[ix].push_back(event);
+                    }
+                }
+                Frame::Flags(link, flags) => {
+                    let ix = *outbound_ix.get(&link).ok_or_else(|| unknown(link))?;
+                    let _ = notes.send(Note::Apply(ix, flags));
+                }
+                Frame::Bye => bye = true,
+            }
+        }
+        received.drain(..start);
+
+        let waiting = pending.iter().any(|pending| !pending.is_empty());
+        if bye && !waiting {
+            return Ok(());
+        }
+
+        // the peer waits on the socket until the receiver makes room
+        if backed_up(&pending) {
+            thread::sleep(RETRY);
+            continue;
+        }
+        if waiting != retrying {
+            retrying = waiting;
+            stream.set_read_timeout(Some(if waiting { RETRY } else { POLL }))?;
+        }
+
+        let n = match stream.read(&mut chunk) {
+            Ok(0) if bye => {
+                thread::sleep(POLL);
+                continue;
+            }
+            Ok(0) => {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "A process hung up before it was done",
+                ))
+            }
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        received.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn unknown(link: Link) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("No link from #{} to #{} here", link.0, link.1),
+    )
+}
+
+/// Rank of a process and where every process listens, as given to the processes of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Launch {
+    /// Rank of this process
+    pub rank: usize,
+
+    /// Address of each process, by rank
+    pub addrs: Vec<Address>,
+}
+
+impl Launch {
+    /// Environment variable with the rank of the process
+    pub const RANK_VAR: &'static str = "RUSTASIM_RANK";
+
+    /// Environment variable with the address of each process, separated by commas
+    pub const ADDRS_VAR: &'static str = "RUSTASIM_ADDRS";
+
+    /// What [`spawn`](#method.spawn) told this process, `None` if it wasn't spawned that way
+    pub fn from_env() -> Result<Option<Launch>, String> {
+        let (rank, addrs) = match (
+            std::env::var(Self::RANK_VAR),
+            std::env::var(Self::ADDRS_VAR),
+        ) {
+            (Ok(rank), Ok(addrs)) => (rank, addrs),
+            _ => return Ok(None),
+        };
+
+        let rank = rank
+            .parse()
+            .map_err(|e| format!("Bad {}: {}", Self::RANK_VAR, e))?;
+        let addrs = addrs
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Address>, String>>()?;
+        if rank >= addrs.len() {
+            return Err(format!("Rank {} is out of {} processes", rank, addrs.len()));
+        }
+
+        Ok(Some(Launch { rank, addrs }))
+    }
+
+    /// Spawns `n` processes talking over Unix sockets in `dir`, `command` builds each of them
+    ///
+    /// Each gets its rank and the addresses through the environment, for
+    /// [`from_env`](#method.from_env).
+    pub fn spawn(
+        n: usize,
+        dir: &Path,
+        mut command: impl FnMut(usize) -> Command,
+    ) -> io::Result<Vec<Child>> {
+        let addrs: Vec<String> = (0..n)
+            .map(|rank| Address::Unix(dir.join(format!("rank-{}.sock", rank))).to_string())
+            .collect();
+        let addrs = addrs.join(",");
+
+        (0..n)
+            .map(|rank| {
+                command(rank)
+                    .env(Self::RANK_VAR, rank.to_string())
+                    .env(Self::ADDRS_VAR, &addrs)
+                    .spawn()
+            })
+            .collect()
+    }
+
+    /// Connects to the other processes
+    pub fn connect<T, U>(&self) -> io::Result<Cluster<T, U>>
+    where
+        T: 'static + Ord + Copy + Send + num::Zero + Serialize + DeserializeOwned,
+        U: 'static + Send + Serialize + DeserializeOwned,
+    {
+        Cluster::connect(self.rank, &self.addrs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::*;
+    use crate::{spsc, EventType};
+
+    /// Passes the tokens it gets on to the next actor of the ring, until `limit`
+    #[derive(Debug)]
+    struct Ring {
+        merger: crate::Merger<u64, ()>,
+        limit: u64,
+        count: u64,
+    }
+
+    impl crate::Advancer<u64, u64> for Ring {
+        fn advance(&mut self) -> crate::ActorState<u64, u64> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::Stalled => return crate::ActorState::Continue(event.time),
+                    EventType::ModelEvent(()) => {
+                        self.count += 1;
+                        if event.time + 1 < self.limit {
+                            let token = Event {
+                                time: event.time + 1,
+                                src: self.merger.id(),
+                                event_type: EventType::ModelEvent(()),
+                            };
+                            self.merger.send(0, token);
+                        }
+                    }
+                    _ => {
+                        // so that the next one knows nothing else comes before its own end
+                        let close = Event {
+                            time: event.time + 1,
+                            src: self.merger.id(),
+                            event_type: EventType::Close,
+                        };
+                        self.merger.send(0, close);
+                        break;
+                    }
+                }
+            }
+
+            match self.merger.finish() {
+                None => crate::ActorState::Done(self.count),
+                Some(time) => crate::ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: crate::Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    const N_ACTORS: usize = 6;
+    const WORLD: usize = 100;
+    const LIMIT: u64 = 200;
+
+    /// Runs our share of the ring, and returns how many tokens our actors got
+    fn run_rank(launch: &Launch) -> u64 {
+        let mut graph = crate::GraphBuilder::<u64, ()>::new().capacity(4);
+        for id in 0..N_ACTORS {
+            graph.add_actor(id);
+        }
+        let mut world = Vec::new();
+        for id in 0..N_ACTORS {
+            graph.link(id, (id + 1) % N_ACTORS);
+            world.push(graph.external(WORLD, id));
+        }
+
+        let mut cluster = launch.connect().unwrap();
+        let owner = |id| id % launch.addrs.len();
+        let nodes = graph.build_on(&mut cluster, owner);
+
+        let mut actors: Vec<Box<dyn crate::Advancer<u64, u64> + Send>> = Vec::new();
+        for node in nodes {
+            // a token each to start with, the world tells everyone when it's over
+            for (time, event_type) in [(0, EventType::ModelEvent(())), (LIMIT, EventType::Close)] {
+                world[node.id]
+                    .push(Event {
+                        time,
+                        src: WORLD,
+                        event_type,
+                    })
+                    .unwrap();
+            }
+
+            let (_, next) = node.outputs.into_iter().next().unwrap();
+            actors.push(Box::new(Ring {
+                merger: node.merger.output(next, 1),
+                limit: LIMIT,
+                count: 0,
+            }));
+        }
+
+        let bridges = cluster.start().unwrap();
+        let (counts, _) = crate::start(2, actors, None).unwrap();
+        bridges.finish().unwrap();
+
+        counts.iter().sum()
+    }
+
+    /// Three processes running a ring of actors, the test binary runs them again
+    #[test]
+    fn test_processes() {
+        if let Some(launch) = Launch::from_env().unwrap() {
+            println!("tokens: {}", run_rank(&launch));
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("rustasim-ranks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exe = std::env::current_exe().unwrap();
+        let children = Launch::spawn(3, &dir, |_| {
+            let mut command = Command::new(&exe);
+            command
+                .args(["--exact", "net::test::test_processes", "--nocapture"])
+                .stdout(std::process::Stdio::piped());
+            command
+        })
+        .unwrap();
+
+        let mut tokens = 0;
+        for child in children {
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            tokens += stdout
+                .lines()
+                .find_map(|line| line.split("tokens: ").nth(1))
+                .unwrap()
+                .parse::<u64>()
+                .unwrap();
+        }
+
+        // every actor got a token at each tick
+        assert_eq!(tokens, N_ACTORS as u64 * LIMIT);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            "unix:/tmp/a.sock".parse(),
+            Ok(Address::Unix(PathBuf::from("/tmp/a.sock")))
+        );
+        let addr: Address = "127.0.0.1:4000".parse().unwrap();
+        assert_eq!(addr.to_string(), "127.0.0.1:4000");
+        assert!("nowhere".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn test_bind() {
+        let dir = std::env::temp_dir().join(format!("rustasim-bind-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.sock");
+        let addr = Address::Unix(path.clone());
+
+        // left behind, taken over
+        drop(UnixListener::bind(&path).unwrap());
+        let listener = Listener::bind(&addr).unwrap();
+
+        // still answered
+        let in_use = Listener::bind(&addr).err().unwrap();
+        assert_eq!(in_use.kind(), ErrorKind::AddrInUse);
+        drop(listener);
+
+        // not ours to remove
+        let other = dir.join("other");
+        std::fs::write(&other, "not a socket").unwrap();
+        let in_use = Listener::bind(&Address::Unix(other.clone())).err().unwrap();
+        assert_eq!(in_use.kind(), ErrorKind::AddrInUse);
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "not a socket");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Ranks on threads rather than processes, sending `n` events each way on a small queue
+    #[test]
+    fn test_bridge() {
+        let dir = std::env::temp_dir().join(format!("rustasim-net-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let addrs: Vec<Address> = (0..2)
+            .map(|rank| Address::Unix(dir.join(format!("{}.sock", rank))))
+            .collect();
+
+        let n = 1000;
+        let ranks: Vec<_> = (0..2)
+            .map(|rank| {
+                let addrs = addrs.clone();
+                thread::spawn(move || {
+                    let mut cluster = Cluster::<u64, String>::connect(rank, &addrs).unwrap();
+                    let peer = 1 - rank;
+
+                    // to the peer, and back from it
+                    let (to_peer, outbound) = spsc::new(4);
+                    let (inbound, from_peer) = spsc::new(4);
+                    cluster.send_to(peer, (rank, peer), outbound);
+                    cluster.receive_from(peer, (peer, rank), inbound);
+                    let bridges = cluster.start().unwrap();
+
+                    let mut received = Vec::new();
+                    let mut sent = 0;
+                    while received.len() < n || sent < n {
+                        if sent < n {
+                            let event = Event {
+                                time: sent as u64,
+                                src: rank,
+                                event_type: EventType::ModelEvent(format!("{}", sent)),
+                            };
+                            if to_peer.push(event).is_ok() {
+                                sent += 1;
+                            }
+                        }
+                        match from_peer.pop() {
+                            Ok(event) => received.push(event),
+                            // the bridges need the core more than we do
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+
+                    from_peer.set_done();
+                    bridges.finish().unwrap();
+                    received
+                })
+            })
+            .collect();
+
+        for rank in ranks {
+            let received = rank.join().unwrap();
+            for (i, event) in received.iter().enumerate() {
+                assert_eq!(event.time, i as u64);
+                assert!(
+                    matches!(&event.event_type, EventType::ModelEvent(s) if *s == i.to_string())
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
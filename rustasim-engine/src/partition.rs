@@ -0,0 +1,192 @@
+//! Places actors on workers so that neighbours share a core
+//!
+//! By default actors are dealt to the workers in turn, and whoever talks to whom ends up anywhere.
+//! Every event and null-message between actors on different cores then has its cache lines
+//! bounce from one core to the other through the spsc queue. A [`Partition`](struct.Partition.html)
+//! built from the links between actors instead puts actors that are linked together in the same
+//! group, one per worker, which [`start_partitioned`](../fn.start_partitioned.html) then runs
+//! on, optionally pinning each worker to its own core.
+//!
+//! ```
+//! use rustasim::Partition;
+//!
+//! // two triangles, joined by a single link
+//! let links = vec![(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3), (2, 3)];
+//! let partition = Partition::from_links(6, 2, links.iter().copied());
+//! assert_eq!(partition.cut(links), 1);
+//! ```
+
+use std::collections::VecDeque;
+
+/// Group of each actor, each group being run by a worker of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    groups: Vec<usize>,
+    n_groups: usize,
+    pinned: bool,
+}
+
+impl Partition {
+    /// Deals `n_actors` to `n_groups` in turn, which is what [`start`](../fn.start.html) does
+    pub fn round_robin(n_actors: usize, n_groups: usize) -> Self {
+        let n_groups = std::cmp::max(1, n_groups);
+        Partition {
+            groups: (0..n_actors).map(|ix| ix % n_groups).collect(),
+            n_groups,
+            pinned: false,
+        }
+    }
+
+    /// Splits `n_actors` in `n_groups` of the same size, keeping linked actors together
+    ///
+    /// Actors are numbered in the order they are given to the executor, `links` pairs up those
+    /// that talk to each other, both ways. Each group is grown breadth first from the first actor
+    /// left, so that whole neighbourhoods end up together, e.g. a rack and its switch.
+    pub fn from_links(
+        n_actors: usize,
+        n_groups: usize,
+        links: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Self {
+        let n_groups = std::cmp::max(1, n_groups);
+        let mut neighbours = vec![Vec::new(); n_actors];
+        for (a, b) in links {
+            if a != b {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        for list in &mut neighbours {
+            list.sort_unstable();
+            list.dedup();
+        }
+
+        let mut groups = vec![usize::MAX; n_actors];
+        let mut seeds = 0..n_actors;
+        for group in 0..n_groups {
+            let mut size = n_actors / n_groups + usize::from(group < n_actors % n_groups);
+            let mut frontier = VecDeque::new();
+            while size > 0 {
+                let ix = match frontier.pop_front() {
+                    Some(ix) => ix,
+                    // what we've reached so far is all there is, start over somewhere else
+                    None => match seeds.find(|&ix| groups[ix] == usize::MAX) {
+                        Some(ix) => ix,
+                        None => break,
+                    },
+                };
+                if groups[ix] != usize::MAX {
+                    continue;
+                }
+
+                groups[ix] = group;
+                size -= 1;
+                frontier.extend(
+                    neighbours[ix]
+                        .iter()
+                        .filter(|&&other| groups[other] == usize::MAX),
+                );
+            }
+        }
+
+        Partition {
+            groups,
+            n_groups,
+            pinned: false,
+        }
+    }
+
+    /// Pins each worker to its own core, the workers of the `n`th group to core `n`
+    ///
+    /// This only does something on Linux.
+    pub fn pinned(mut self, pinned: bool) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Whether the workers are pinned to their core
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Number of groups, and therefore of workers
+    pub fn n_groups(&self) -> usize {
+        self.n_groups
+    }
+
+    /// Number of actors
+    pub fn n_actors(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Group of actor `ix`
+    pub fn group(&self, ix: usize) -> usize {
+        self.groups[ix]
+    }
+
+    /// Number of `links` between actors of different groups
+    pub fn cut(&self, links: impl IntoIterator<Item = (usize, usize)>) -> usize {
+        links
+            .into_iter()
+            .filter(|&(a, b)| self.groups[a] != self.groups[b])
+            .count()
+    }
+}
+
+/// Pins the calling thread to `core`, if there is such a core
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_to(core: usize) {
+    // SAFETY: the set is zeroed memory, which is a valid empty set, and only touched through libc
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core % num_cpus::get(), &mut set);
+        // not being pinned only costs performance
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+/// Pins the calling thread to `core`, if there is such a core
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_to(_core: usize) {}
+
+#[cfg(test)]
+mod test {
+    use crate::partition::*;
+
+    /// Racks of a server switch and its servers, the switches in a ring
+    fn racks(n_racks: usize, per_rack: usize) -> Vec<(usize, usize)> {
+        let mut links = Vec::new();
+        for rack in 0..n_racks {
+            let switch = n_racks * per_rack + rack;
+            for server in rack * per_rack..(rack + 1) * per_rack {
+                links.push((server, switch));
+            }
+            links.push((switch, n_racks * per_rack + (rack + 1) % n_racks));
+        }
+        links
+    }
+
+    #[test]
+    fn test_balanced() {
+        for n_groups in 1..8 {
+            let partition = Partition::from_links(23, n_groups, racks(4, 4));
+            let mut sizes = vec![0; n_groups];
+            for ix in 0..23 {
+                sizes[partition.group(ix)] += 1;
+            }
+
+            let (min, max) = (sizes.iter().min(), sizes.iter().max());
+            assert!(max.unwrap() - min.unwrap() <= 1, "{:?}", sizes);
+        }
+    }
+
+    #[test]
+    fn test_racks_together() {
+        // a group per rack, only the ring between switches is cut
+        let links = racks(4, 4);
+        let partition = Partition::from_links(20, 4, links.iter().copied());
+        assert_eq!(partition.cut(links.iter().copied()), 4);
+
+        let dealt = Partition::round_robin(20, 4);
+        assert!(dealt.cut(links.iter().copied()) > 4);
+    }
+}
@@ -0,0 +1,219 @@
+//! Single-threaded reference executor
+//!
+//! This runs the exact same actors as [`start`](../fn.start.html), but from a single global event
+//! list instead of conservative null-message synchronization. It is meant to be the ground truth
+//! to compare a parallel run against when a model misbehaves, and can be faster for small
+//! simulations.
+//!
+//! The executor keeps, for every actor, the time of the earliest event waiting on its inputs. It
+//! always advances the actor with the globally earliest event, and lets it process exactly that
+//! one event. Each actor's [`Merger`](../struct.Merger.html) is told its slot, and cooperates
+//! through a thread-local `Turn`: it delivers the event if allowed to, and otherwise reports its
+//! next event time and hands a `Stalled` back to the actor. Sending on a link wakes up the actor
+//! consuming it so that its next event time can be updated.
+//!
+//! Null-messages are never needed: everything that can happen before the earliest event has
+//! already happened. Actors may still send them when they see `Stalled`, they are dropped on
+//! arrival.
+
+use crate::end;
+use crate::worker::{ActorState, Advancer, Driver, Mode};
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+
+/// What the executor and the `Merger` of the actor being advanced tell each other
+struct Turn {
+    /// Whether the actor may process its next event
+    deliver: bool,
+
+    /// Time of the actor's next event, as reported by its `Merger`
+    next_time: Option<Box<dyn Any>>,
+
+    /// Slots of the actors whose input queues were pushed to
+    woken: Vec<usize>,
+}
+
+thread_local! {
+    static TURN: RefCell<Option<Turn>> = const { RefCell::new(None) };
+}
+
+/// Whether the actor being advanced may process an event, only returns `true` once per turn
+pub(crate) fn take_delivery() -> bool {
+    TURN.with(|turn| match turn.borrow_mut().as_mut() {
+        Some(turn) => std::mem::replace(&mut turn.deliver, false),
+        None => false,
+    })
+}
+
+/// Reports the time of the next event of the actor being advanced, `None` if it has none
+pub(crate) fn report_next_time<T: 'static>(next_time: Option<T>) {
+    TURN.with(|turn| {
+        if let Some(turn) = turn.borrow_mut().as_mut() {
+            turn.next_time = next_time.map(|time| Box::new(time) as Box<dyn Any>);
+        }
+    })
+}
+
+/// Notes that the actor in `slot` got something new on one of its inputs
+pub(crate) fn wake(slot: usize) {
+    TURN.with(|turn| {
+        if let Some(turn) = turn.borrow_mut().as_mut() {
+            turn.woken.push(slot);
+        }
+    })
+}
+
+/// State of a sequential run
+struct Executor<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// The actors, `None` once they are done
+    actors: Vec<Option<Box<dyn Advancer<T, R> + Send>>>,
+
+    /// Time of each actor's next event, `None` if it has nothing to do for now
+    next_times: Vec<Option<T>>,
+
+    /// The global event list, entries that don't match `next_times` are stale
+    events: BinaryHeap<Reverse<(T, usize)>>,
+
+    /// Actors whose next event time needs to be asked for again
+    to_probe: Vec<usize>,
+    needs_probe: Vec<bool>,
+
+    // by slot
+    results: Vec<Option<R>>,
+}
+
+impl<T, R> Executor<T, R>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    /// Advances the actor in `slot`, letting it process its next event if `deliver` is set
+    fn advance(&mut self, slot: usize, deliver: bool) {
+        let actor = match self.actors[slot].as_mut() {
+            Some(actor) => actor,
+            None => return,
+        };
+
+        TURN.with(|turn| {
+            *turn.borrow_mut() = Some(Turn {
+                deliver,
+                next_time: None,
+                woken: Vec::new(),
+            })
+        });
+        let state = actor.advance();
+        let turn = TURN.with(|turn| turn.borrow_mut().take()).unwrap();
+
+        self.next_times[slot] = None;
+        match state {
+            ActorState::Continue(_) => {
+                if let Some(next_time) = turn.next_time {
+                    let next_time = *next_time
+                        .downcast::<T>()
+                        .expect("Merger and actor disagree on the time type");
+                    self.next_times[slot] = Some(next_time);
+                    self.events.push(Reverse((next_time, slot)));
+                }
+            }
+            ActorState::Done(result) => {
+                actor.finish();
+                self.results[slot] = Some(result);
+                self.actors[slot] = None;
+            }
+        }
+
+        for woken in turn.woken {
+            if self.actors[woken].is_some() && !self.needs_probe[woken] {
+                self.needs_probe[woken] = true;
+                self.next_times[woken] = None;
+                self.to_probe.push(woken);
+            }
+        }
+    }
+}
+
+/// Runs the actors to completion on the current thread, or until `end` if given
+///
+/// The results are returned in the order the actors were given. Actors must give the hand back
+/// when they get a `Stalled` event, as they do for [`start`](fn.start.html).
+///
+/// # Panics
+///
+/// Panics if some actors never finish because none of them has anything left to do.
+pub fn start_sequential<T, R>(
+    mut actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Vec<R>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    for (slot, actor) in actors.iter_mut().enumerate() {
+        actor.driven_by(Driver::new(Mode::Sequential, Some(slot)));
+    }
+
+    let n_actors = actors.len();
+    let mut executor = Executor {
+        actors: actors.into_iter().map(Some).collect(),
+        next_times: vec![None; n_actors],
+        events: BinaryHeap::new(),
+        to_probe: (0..n_actors).rev().collect(),
+        needs_probe: vec![true; n_actors],
+        results: (0..n_actors).map(|_| None).collect(),
+    };
+
+    end::with(end, || loop {
+        // find out when the actors that got new inputs have something to do
+        while let Some(slot) = executor.to_probe.pop() {
+            executor.needs_probe[slot] = false;
+            executor.advance(slot, false);
+        }
+
+        // process the globally earliest event
+        let next = loop {
+            match executor.events.pop() {
+                Some(Reverse((time, slot))) if executor.next_times[slot] == Some(time) => {
+                    break Some(slot)
+                }
+                Some(_) => continue,
+                None => break None,
+            }
+        };
+
+        match next {
+            Some(slot) => executor.advance(slot, true),
+            None => break,
+        }
+    });
+
+    let n_running = executor.actors.iter().flatten().count();
+    assert_eq!(
+        n_running, 0,
+        "{} actors have nothing left to do but never finished",
+        n_running
+    );
+
+    executor.results.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixtures::ring;
+    use crate::sequential::*;
+    use crate::start;
+
+    #[test]
+    fn test_matches_parallel() {
+        let (mut parallel, _) = start(2, ring(7, 1_000), None).unwrap();
+        let mut sequential = start_sequential(ring(7, 1_000), None);
+
+        parallel.sort_unstable();
+        sequential.sort_unstable();
+        assert_eq!(sequential, parallel);
+        assert!(sequential.iter().all(|&sum| sum > 0));
+    }
+}
@@ -0,0 +1,337 @@
+//! Runs a simulation bit by bit
+//!
+//! A [`Session`](struct.Session.html) holds on to the actors in between runs. Each run takes them
+//! up to a simulated time and hands them back paused there, the same way a
+//! [`checkpoint`](fn.checkpoint.html) does: everything before that time has been processed and
+//! nothing after it has. They can then be looked at, or be sent new events, before carrying on.
+//!
+//! The session doesn't know about what feeds the actors from outside the simulation. Whoever
+//! pushes on those inputs must tell the actors how far it got before each run, with a
+//! null-message at the time the run goes up to: they can't get past their inputs otherwise. Events
+//! pushed in between two runs must not be earlier than the time the first one went up to.
+
+use crate::checkpoint::{self, Checkpoint};
+use crate::{ActorPanic, Report, Scheduler};
+use std::fmt::{self, Debug};
+
+/// A simulation run bit by bit, see the module documentation
+///
+/// Actors are `A`, e.g. an enum of the model's actors for them to be looked at in between runs,
+/// or `dyn Checkpoint<T, R> + Send` when that isn't needed.
+pub struct Session<T, R, A: ?Sized> {
+    scheduler: Scheduler,
+    num_cpus: usize,
+    end: Option<T>,
+
+    // by index, `None` once done
+    actors: Vec<Option<Box<A>>>,
+    results: Vec<Option<R>>,
+
+    // the actors are paused there
+    time: T,
+}
+
+impl<T: Debug, R, A: ?Sized> Debug for Session<T, R, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("time", &self.time)
+            .field("n_running", &self.actors.iter().flatten().count())
+            .finish()
+    }
+}
+
+impl<T, R, A> Session<T, R, A>
+where
+    T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    R: 'static + Send,
+    A: 'static + ?Sized + Checkpoint<T, R> + Send,
+{
+    /// Takes over the actors, to be run on `num_cpus` workers scheduled by `scheduler`, until
+    /// `end` if given
+    ///
+    /// Nothing runs until [`run_until`](#method.run_until) or [`finish`](#method.finish).
+    pub fn new(scheduler: Scheduler, num_cpus: usize, actors: Vec<Box<A>>, end: Option<T>) -> Self {
+        Session {
+            scheduler,
+            num_cpus,
+            end,
+            results: actors.iter().map(|_| None).collect(),
+            actors: actors.into_iter().map(Some).collect(),
+            time: T::zero(),
+        }
+    }
+
+    /// Runs the actors up to `time`, where they are paused until the next run
+    ///
+    /// Along with what the actors and workers did comes whether they are all done. If an actor
+    /// panics, the workers stop and the panic comes back instead, the session can't carry on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is earlier than the previous run went up to.
+    pub fn run_until(&mut self, time: T) -> Result<(bool, Report), ActorPanic> {
+        assert!(
+            time >= self.time,
+            "The session can't run until {:?}, it is already at {:?}",
+            time,
+            self.time
+        );
+
+        let report = self.run(Some(time))?;
+        self.time = time;
+        Ok((self.is_done(), report))
+    }
+
+    /// Runs the actors to the end, and returns their results in the order they were given
+    pub fn finish(mut self) -> Result<(Vec<R>, Report), ActorPanic> {
+        let report = self.run(None)?;
+        let results = self.results.into_iter().flatten().collect();
+        Ok((results, report))
+    }
+
+    /// Time the actors are paused at
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    /// Whether all the actors are done
+    pub fn is_done(&self) -> bool {
+        self.actors.iter().all(Option::is_none)
+    }
+
+    /// The actors still running, along with their index
+    pub fn actors(&self) -> impl Iterator<Item = (usize, &A)> {
+        self.actors
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, actor)| actor.as_deref().map(|actor| (ix, actor)))
+    }
+
+    /// The actors still running, along with their index, to change their state in between runs
+    pub fn actors_mut(&mut self) -> impl Iterator<Item = (usize, &mut A)> {
+        self.actors
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(ix, actor)| actor.as_deref_mut().map(|actor| (ix, actor)))
+    }
+
+    /// Results of the actors that are done, along with their index
+    pub fn results(&self) -> impl Iterator<Item = (usize, &R)> + '_ {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, result)| result.as_ref().map(|result| (ix, result)))
+    }
+
+    /// Runs whoever is still running up to `time`, or to the end without one
+    fn run(&mut self, time: Option<T>) -> Result<Report, ActorPanic> {
+        let n_actors = self.actors.len();
+        let running = self
+            .actors
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(ix, actor)| actor.take().map(|actor| (ix, actor)))
+            .collect();
+
+        let (paused, done, report) = checkpoint::pause(
+            self.scheduler,
+            self.num_cpus,
+            running,
+            n_actors,
+            time,
+            self.end,
+        )?;
+        self.actors = paused;
+        for (ix, result) in done {
+            self.results[ix] = Some(result);
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::session::*;
+    use crate::{
+        load_state, save_state, spsc, ActorState, Advancer, CheckpointError, Driver, Event,
+        EventType, Merger, Pausable,
+    };
+    use std::io::{Read, Write};
+
+    const LOOKAHEAD: u64 = 10;
+
+    /// Sends what it gets from the world to its peer, which sends it back
+    ///
+    /// Input 0 is from the world for the first actor, input 1 from its peer. Returns the number
+    /// of events it got back.
+    #[derive(Debug)]
+    struct Echo {
+        id: usize,
+        merger: Merger<u64, ()>,
+        n_echoes: u64,
+    }
+
+    impl Advancer<u64, u64> for Echo {
+        fn advance(&mut self) -> ActorState<u64, u64> {
+            while let Some(event) = self.merger.next() {
+                let forward = Event {
+                    time: event.time + LOOKAHEAD,
+                    src: self.id,
+                    event_type: event.event_type,
+                };
+                match forward.event_type {
+                    EventType::ModelEvent(()) if self.id == 0 && event.src == 1 => {
+                        self.n_echoes += 1
+                    }
+                    EventType::ModelEvent(()) => self.merger.send(0, forward),
+                    EventType::Close => {
+                        if self.id == 0 {
+                            self.merger.send(0, forward);
+                        }
+                        break;
+                    }
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    EventType::Null => unreachable!(),
+                }
+            }
+
+            match self.merger.finish() {
+                None => ActorState::Done(self.n_echoes),
+                Some(time) => ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    impl Checkpoint<u64, u64> for Echo {
+        fn merger(&mut self) -> &mut dyn Pausable<u64> {
+            &mut self.merger
+        }
+
+        fn save(&self, out: &mut dyn Write) -> Result<(), CheckpointError> {
+            save_state(out, &self.n_echoes)
+        }
+
+        fn load(&mut self, input: &mut dyn Read) -> Result<(), CheckpointError> {
+            self.n_echoes = load_state(input)?;
+            Ok(())
+        }
+    }
+
+    /// A session of the two actors until `end`, and the input of the first one from the world
+    fn echoes(
+        scheduler: Scheduler,
+        end: Option<u64>,
+    ) -> (Session<u64, u64, Echo>, spsc::Producer<Event<u64, ()>>) {
+        let (world, from_world) = spsc::new(16);
+        let (to_peer, from_first) = spsc::new(16);
+        let (to_first, from_peer) = spsc::new(16);
+
+        let first =
+            Merger::new(vec![from_world, from_peer], 0, vec![2, 1]).output(to_peer, LOOKAHEAD);
+        let peer = Merger::new(vec![from_first], 1, vec![0]).output(to_first, LOOKAHEAD);
+        let actors = vec![
+            Echo {
+                id: 0,
+                merger: first,
+                n_echoes: 0,
+            },
+            Echo {
+                id: 1,
+                merger: peer,
+                n_echoes: 0,
+            },
+        ];
+
+        let actors = actors.into_iter().map(Box::new).collect();
+        (Session::new(scheduler, 2, actors, end), world)
+    }
+
+    fn push(world: &spsc::Producer<Event<u64, ()>>, time: u64, event_type: EventType<()>) {
+        world
+            .push(Event {
+                time,
+                src: 2,
+                event_type,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_run_until() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            let (mut session, world) = echoes(scheduler, None);
+            let n_echoes =
+                |session: &Session<_, _, Echo>| session.actors().next().unwrap().1.n_echoes;
+
+            // one makes it back in time, the other doesn't
+            push(&world, 5, EventType::ModelEvent(()));
+            push(&world, 90, EventType::ModelEvent(()));
+            push(&world, 100, EventType::Null);
+            let (done, _) = session.run_until(100).unwrap();
+            assert!(!done);
+            assert_eq!(session.time(), 100);
+            assert_eq!(n_echoes(&session), 1);
+
+            // new events from where it stopped
+            push(&world, 100, EventType::ModelEvent(()));
+            push(&world, 150, EventType::ModelEvent(()));
+            push(&world, 200, EventType::Null);
+            session.run_until(200).unwrap();
+            assert_eq!(n_echoes(&session), 4);
+
+            push(&world, 300, EventType::Close);
+            let (results, _) = session.finish().unwrap();
+            assert_eq!(results, vec![4, 0]);
+        }
+    }
+
+    #[test]
+    fn test_end() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            let (mut session, world) = echoes(scheduler, Some(300));
+
+            push(&world, 5, EventType::ModelEvent(()));
+            push(&world, 100, EventType::Null);
+            session.run_until(100).unwrap();
+
+            // the second one doesn't make it back before the end, the world never closes
+            push(&world, 250, EventType::ModelEvent(()));
+            push(&world, 290, EventType::ModelEvent(()));
+            push(&world, 300, EventType::Null);
+            let (done, _) = session.run_until(300).unwrap();
+            assert!(!done);
+
+            let (results, _) = session.finish().unwrap();
+            assert_eq!(results, vec![2, 0]);
+        }
+    }
+
+    #[test]
+    fn test_done_early() {
+        let (mut session, world) = echoes(Scheduler::Fifo, None);
+
+        push(&world, 50, EventType::Close);
+        let (done, _) = session.run_until(100).unwrap();
+        assert!(done);
+        assert_eq!(
+            session.results().collect::<Vec<_>>(),
+            vec![(0, &0), (1, &0)]
+        );
+        assert_eq!(session.finish().unwrap().0, vec![0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't run until 50, it is already at 100")]
+    fn test_run_back() {
+        let (mut session, world) = echoes(Scheduler::Fifo, None);
+
+        push(&world, 100, EventType::Null);
+        session.run_until(100).unwrap();
+        let _ = session.run_until(50);
+    }
+}
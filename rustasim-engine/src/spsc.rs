@@ -2,6 +2,10 @@
 //!
 //! Lifted from an [unpublished branch of crossbeam](https://github.com/stjepang/crossbeam/tree/spsc/crossbeam-queue/src)
 //!
+//! Queues made with [`segmented`](fn.segmented.html) are unbounded instead: their elements live in
+//! fixed-size segments that are allocated as the queue fills up and released as it drains, so the
+//! memory they take follows what they hold rather than what they might hold.
+//!
 //! # Examples
 //!
 //! ```
@@ -22,7 +26,8 @@ use std::cell::Cell;
 use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 //use std::thread;
 
@@ -31,62 +36,144 @@ use crossbeam_utils::CachePadded;
 
 use crate::err::{PopError, PushError};
 
+/// Id of the next queue to be created.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A chunk of slots of a segmented queue.
+struct Segment<T> {
+    /// The buffer holding slots.
+    buffer: *mut T,
+
+    /// The number of slots.
+    len: usize,
+
+    /// The segment after this one, linked by the producer before it moves on to it.
+    next: AtomicPtr<Segment<T>>,
+}
+
+impl<T> Segment<T> {
+    /// Allocates a segment of `len` slots.
+    fn alloc(len: usize) -> *mut Segment<T> {
+        Box::into_raw(Box::new(Segment {
+            buffer: alloc_buffer(len),
+            len,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> Drop for Segment<T> {
+    fn drop(&mut self) {
+        // Deallocate the buffer, but don't run any destructors.
+        unsafe {
+            Vec::from_raw_parts(self.buffer, 0, self.len);
+        }
+    }
+}
+
+/// Allocates a buffer of length `cap`.
+fn alloc_buffer<T>(cap: usize) -> *mut T {
+    let mut v = Vec::<T>::with_capacity(cap);
+    let ptr = v.as_mut_ptr();
+    mem::forget(v);
+    ptr
+}
+
+/// Where the elements of a queue live.
+enum Storage<T> {
+    /// A single buffer of `cap` slots.
+    ///
+    /// Positions are in range `0 .. 2 * cap`.
+    Bounded {
+        /// The buffer holding slots.
+        buffer: *mut T,
+
+        /// The queue capacity.
+        cap: usize,
+    },
+
+    /// Segments of `len` slots, linked one after the other.
+    ///
+    /// Positions only ever grow, the slot at position `pos` is at `pos % len` in its segment.
+    Segmented {
+        /// The number of slots per segment.
+        len: usize,
+
+        /// The segment holding the head, only read when dropping the queue.
+        first: AtomicPtr<Segment<T>>,
+
+        /// A drained segment kept around for the producer to reuse, or null.
+        spare: AtomicPtr<Segment<T>>,
+    },
+}
+
 /// The inner representation of a single-producer single-consumer queue.
 struct Inner<T> {
     /// The head of the queue.
     ///
-    /// This integer is in range `0 .. 2 * cap`.
+    /// This integer is in range `0 .. 2 * cap` for bounded queues.
     head: CachePadded<AtomicUsize>,
 
     /// The tail of the queue.
     ///
-    /// This integer is in range `0 .. 2 * cap`.
+    /// This integer is in range `0 .. 2 * cap` for bounded queues.
     tail: CachePadded<AtomicUsize>,
 
-    /// The buffer holding slots.
-    buffer: *mut T,
+    /// The slots.
+    storage: Storage<T>,
 
-    /// The queue capacity.
+    /// The queue capacity, `usize::MAX` for segmented queues.
     cap: usize,
 
+    /// Unique id of the queue.
+    id: u64,
+
+    /// Whether the consumer stopped popping for good.
+    consumer_done: AtomicBool,
+
     /// Indicates that dropping a `Buffer<T>` may drop elements of type `T`.
     _marker: PhantomData<T>,
 }
 
 impl<T> Inner<T> {
-    /// Returns a pointer to the slot at position `pos`.
+    /// Returns a pointer to the slot at position `pos`, `segment` being the one holding it.
     ///
-    /// The position must be in range `0 .. 2 * cap`.
+    /// The position must be in range `0 .. 2 * cap` for bounded queues.
     #[inline]
-    unsafe fn slot(&self, pos: usize) -> *mut T {
-        if pos < self.cap {
-            self.buffer.add(pos)
-        } else {
-            self.buffer.add(pos - self.cap)
+    unsafe fn slot(&self, pos: usize, segment: *mut Segment<T>) -> *mut T {
+        match &self.storage {
+            Storage::Bounded { buffer, cap } => {
+                if pos < *cap {
+                    buffer.add(pos)
+                } else {
+                    buffer.add(pos - cap)
+                }
+            }
+            Storage::Segmented { len, .. } => (*segment).buffer.add(pos % len),
         }
     }
 
     /// Increments a position by going one slot forward.
     ///
-    /// The position must be in range `0 .. 2 * cap`.
+    /// The position must be in range `0 .. 2 * cap` for bounded queues.
     #[inline]
     fn increment(&self, pos: usize) -> usize {
-        if pos < 2 * self.cap - 1 {
-            pos + 1
-        } else {
-            0
+        match &self.storage {
+            Storage::Bounded { cap, .. } if pos < 2 * cap - 1 => pos + 1,
+            Storage::Bounded { .. } => 0,
+            Storage::Segmented { .. } => pos.wrapping_add(1),
         }
     }
 
     /// Returns the distance between two positions.
     ///
-    /// Positions must be in range `0 .. 2 * cap`.
+    /// Positions must be in range `0 .. 2 * cap` for bounded queues.
     #[inline]
     fn distance(&self, a: usize, b: usize) -> usize {
-        if a <= b {
-            b - a
-        } else {
-            2 * self.cap - a + b
+        match &self.storage {
+            Storage::Bounded { .. } if a <= b => b - a,
+            Storage::Bounded { cap, .. } => 2 * cap - a + b,
+            Storage::Segmented { .. } => b.wrapping_sub(a),
         }
     }
 }
@@ -96,17 +183,44 @@ impl<T> Drop for Inner<T> {
         let mut head = self.head.load(Ordering::Relaxed);
         let tail = self.tail.load(Ordering::Relaxed);
 
-        // Loop over all slots that hold a value and drop them.
-        while head != tail {
-            unsafe {
-                self.slot(head).drop_in_place();
+        match &self.storage {
+            Storage::Bounded { buffer, cap } => {
+                // Loop over all slots that hold a value and drop them.
+                while head != tail {
+                    unsafe {
+                        self.slot(head, ptr::null_mut()).drop_in_place();
+                    }
+                    head = self.increment(head);
+                }
+
+                // Finally, deallocate the buffer, but don't run any destructors.
+                unsafe {
+                    Vec::from_raw_parts(*buffer, 0, *cap);
+                }
+            }
+            Storage::Segmented { len, first, spare } => {
+                let mut segment = first.load(Ordering::Relaxed);
+                while head != tail {
+                    unsafe {
+                        self.slot(head, segment).drop_in_place();
+                    }
+                    head = self.increment(head);
+
+                    // Done with this one.
+                    if head.is_multiple_of(*len) {
+                        let next = unsafe { (*segment).next.load(Ordering::Relaxed) };
+                        unsafe { drop(Box::from_raw(segment)) };
+                        segment = next;
+                    }
+                }
+
+                // The segment the producer was filling, and the spare one.
+                for segment in &[segment, spare.load(Ordering::Relaxed)] {
+                    if !segment.is_null() {
+                        unsafe { drop(Box::from_raw(*segment)) };
+                    }
+                }
             }
-            head = self.increment(head);
-        }
-
-        // Finally, deallocate the buffer, but don't run any destructors.
-        unsafe {
-            Vec::from_raw_parts(self.buffer, 0, self.cap);
         }
     }
 }
@@ -129,19 +243,60 @@ impl<T> Drop for Inner<T> {
 pub fn new<T>(cap: usize) -> (Producer<T>, Consumer<T>) {
     assert!(cap > 0, "capacity must be non-zero");
 
-    // Allocate a buffer of length `cap`.
-    let buffer = {
-        let mut v = Vec::<T>::with_capacity(cap);
-        let ptr = v.as_mut_ptr();
-        mem::forget(v);
-        ptr
+    let buffer = alloc_buffer(cap);
+    with_storage(Storage::Bounded { buffer, cap }, cap, ptr::null_mut())
+}
+
+/// Creates an unbounded single-producer single-consumer queue, stored in segments of `len` slots.
+///
+/// Returns the producer and the consumer side for the queue. Pushing never fails, and segments
+/// are only allocated as the queue fills up. A drained segment is kept around for reuse, so a
+/// queue holding `n` elements takes at most `n / len + 3` segments.
+///
+/// # Panics
+///
+/// Panics if the segment length is zero.
+///
+/// # Examples
+///
+/// ```
+/// use rustasim::spsc;
+///
+/// let (p, c) = spsc::segmented(2);
+///
+/// for i in 0..5 {
+///     assert!(p.push(i).is_ok());
+/// }
+/// assert_eq!(c.len(), 5);
+/// assert!(!c.is_full());
+///
+/// assert_eq!(c.pop(), Ok(0));
+/// ```
+pub fn segmented<T>(len: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(len > 0, "segment length must be non-zero");
+
+    let segment = Segment::alloc(len);
+    let storage = Storage::Segmented {
+        len,
+        first: AtomicPtr::new(segment),
+        spare: AtomicPtr::new(ptr::null_mut()),
     };
+    with_storage(storage, usize::MAX, segment)
+}
 
+/// Creates a queue from its slots, `segment` being the first one for segmented queues.
+fn with_storage<T>(
+    storage: Storage<T>,
+    cap: usize,
+    segment: *mut Segment<T>,
+) -> (Producer<T>, Consumer<T>) {
     let inner = Arc::new(Inner {
         head: CachePadded::new(AtomicUsize::new(0)),
         tail: CachePadded::new(AtomicUsize::new(0)),
-        buffer,
+        storage,
         cap,
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        consumer_done: AtomicBool::new(false),
         _marker: PhantomData,
     });
 
@@ -149,12 +304,16 @@ pub fn new<T>(cap: usize) -> (Producer<T>, Consumer<T>) {
         inner: inner.clone(),
         head: Cell::new(0),
         tail: Cell::new(0),
+        segment: Cell::new(segment),
+        pushed: Cell::new(0),
     };
 
     let c = Consumer {
         inner,
         head: Cell::new(0),
         tail: Cell::new(0),
+        segment: Cell::new(segment),
+        popped: Cell::new(0),
     };
 
     (p, c)
@@ -188,6 +347,12 @@ pub struct Producer<T> {
     ///
     /// This value is always in sync with `inner.tail`.
     tail: Cell<usize>,
+
+    /// The segment holding the tail, null for bounded queues.
+    segment: Cell<*mut Segment<T>>,
+
+    /// Number of elements pushed so far, the next element's sequence number.
+    pushed: Cell<u64>,
 }
 
 unsafe impl<T: Send> Send for Producer<T> {}
@@ -225,18 +390,49 @@ impl<T> Producer<T> {
 
         // Write the value into the tail slot.
         unsafe {
-            self.inner.slot(tail).write(value);
+            self.inner.slot(tail, self.segment.get()).write(value);
         }
 
         // Move the tail one slot forward.
         tail = self.inner.increment(tail);
+
+        // Link the next segment before the consumer can get to it.
+        if let Storage::Segmented { len, spare, .. } = &self.inner.storage {
+            if tail.is_multiple_of(*len) {
+                let mut next = spare.swap(ptr::null_mut(), Ordering::Acquire);
+                if next.is_null() {
+                    next = Segment::alloc(*len);
+                }
+                unsafe {
+                    (*self.segment.get()).next.store(next, Ordering::Relaxed);
+                }
+                self.segment.set(next);
+            }
+        }
+
         self.inner.tail.store(tail, Ordering::Release);
         self.tail.set(tail);
+        self.pushed.set(self.pushed.get() + 1);
 
         Ok(())
     }
 
-    /// Returns the capacity of the queue.
+    /// Sequence number of the next element to be pushed, elements are numbered from 0 on.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.pushed.get()
+    }
+
+    /// Unique id of the queue, the same on both sides.
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Whether the consumer stopped popping for good, nothing pushed will ever be seen.
+    pub(crate) fn is_consumer_done(&self) -> bool {
+        self.inner.consumer_done.load(Ordering::Relaxed)
+    }
+
+    /// Returns the capacity of the queue, `usize::MAX` for segmented queues.
     ///
     /// # Examples
     ///
@@ -244,8 +440,10 @@ impl<T> Producer<T> {
     /// use rustasim::spsc;
     ///
     /// let (p, c) = spsc::new::<i32>(100);
-    ///
     /// assert_eq!(p.capacity(), 100);
+    ///
+    /// let (p, c) = spsc::segmented::<i32>(100);
+    /// assert_eq!(p.capacity(), usize::MAX);
     /// ```
     pub fn capacity(&self) -> usize {
         self.inner.cap
@@ -344,10 +542,22 @@ pub struct Consumer<T> {
     ///
     /// This value can be stale and sometimes needs to be resynchronized with `inner.tail`.
     tail: Cell<usize>,
+
+    /// The segment holding the head, null for bounded queues.
+    segment: Cell<*mut Segment<T>>,
+
+    /// Number of elements popped so far, the next element's sequence number.
+    popped: Cell<u64>,
 }
 
 unsafe impl<T: Send> Send for Consumer<T> {}
 
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.set_done();
+    }
+}
+
 impl<T> Consumer<T> {
     /// Attempts to pop an element from the queue.
     ///
@@ -381,26 +591,97 @@ impl<T> Consumer<T> {
         }
 
         // Read the value from the head slot.
-        let value = unsafe { self.inner.slot(head).read() };
+        let value = unsafe { self.inner.slot(head, self.segment.get()).read() };
 
         // Move the head one slot forward.
         head = self.inner.increment(head);
+
+        // Move on to the next segment, the producer linked it before moving the tail past this one.
+        if let Storage::Segmented { len, first, spare } = &self.inner.storage {
+            if head.is_multiple_of(*len) {
+                let drained = self.segment.get();
+                let next = unsafe { (*drained).next.swap(ptr::null_mut(), Ordering::Relaxed) };
+                self.segment.set(next);
+                first.store(next, Ordering::Relaxed);
+
+                // Keep it for the producer, unless it already has one.
+                let spare = spare.swap(drained, Ordering::Release);
+                if !spare.is_null() {
+                    unsafe { drop(Box::from_raw(spare)) };
+                }
+            }
+        }
+
         self.inner.head.store(head, Ordering::Release);
         self.head.set(head);
+        self.popped.set(self.popped.get() + 1);
 
         Ok(value)
     }
 
-    /// Returns the capacity of the queue.
+    /// Returns a reference to the next element without removing it from the queue.
+    ///
+    /// If the queue is empty, `None` is returned.
     ///
     /// # Examples
     ///
     /// ```
     /// use rustasim::spsc;
     ///
-    /// let (p, c) = spsc::new::<i32>(100);
+    /// let (p, mut c) = spsc::new(2);
+    /// assert_eq!(c.peek(), None);
+    ///
+    /// p.push(10).unwrap();
+    /// assert_eq!(c.peek(), Some(&10));
+    /// assert_eq!(c.pop(), Ok(10));
+    /// ```
+    pub fn peek(&mut self) -> Option<&T> {
+        let head = self.head.get();
+        let mut tail = self.tail.get();
+
+        // Check if the queue is *possibly* empty.
+        if head == tail {
+            // We need to refresh the tail and check again if the queue is *really* empty.
+            tail = self.inner.tail.load(Ordering::Acquire);
+            self.tail.set(tail);
+
+            // Is the queue *really* empty?
+            if head == tail {
+                return None;
+            }
+        }
+
+        // The producer can't touch this slot until we pop it, which can't happen while borrowed.
+        unsafe { Some(&*self.inner.slot(head, self.segment.get())) }
+    }
+
+    /// Sequence number of the next element to be popped, elements are numbered from 0 on.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.popped.get()
+    }
+
+    /// Unique id of the queue, the same on both sides.
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Tells the producer we will never pop again.
+    pub(crate) fn set_done(&self) {
+        self.inner.consumer_done.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the capacity of the queue, `usize::MAX` for segmented queues.
+    ///
+    /// # Examples
     ///
+    /// ```
+    /// use rustasim::spsc;
+    ///
+    /// let (p, c) = spsc::new::<i32>(100);
     /// assert_eq!(c.capacity(), 100);
+    ///
+    /// let (p, c) = spsc::segmented::<i32>(100);
+    /// assert_eq!(c.capacity(), usize::MAX);
     /// ```
     pub fn capacity(&self) -> usize {
         self.inner.cap
@@ -491,3 +772,57 @@ impl<T> fmt::Debug for Consumer<T> {
         f.pad("Consumer { .. }")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::spsc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_segmented_threads() {
+        let (p, c) = spsc::segmented(7);
+        let n = 100_000;
+
+        let producer = thread::spawn(move || {
+            for i in 0..n {
+                p.push(i).unwrap();
+            }
+        });
+
+        let mut expected = 0;
+        while expected < n {
+            if let Ok(i) = c.pop() {
+                assert_eq!(i, expected);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn test_segmented_drop() {
+        #[derive(Debug)]
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let (p, c) = spsc::segmented(4);
+        for _ in 0..10 {
+            p.push(Counted(dropped.clone())).unwrap();
+        }
+        for _ in 0..5 {
+            c.pop().unwrap();
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 5);
+
+        drop((p, c));
+        assert_eq!(dropped.load(Ordering::Relaxed), 10);
+    }
+}
@@ -0,0 +1,151 @@
+//! Counts what the actors and workers spend their time on
+//!
+//! Each `Merger` counts the events it delivers and sends, the null-messages it sends and
+//! receives, and the times it stalls. It hands its counters over whenever the actor is about to give the hand back,
+//! and the worker adds them to those of the actor it is advancing, along with the time spent in
+//! `advance`. The totals come back from [`start`](../fn.start.html) as a [`Report`].
+
+use std::cell::Cell;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Counters of an actor, or of all the actors advanced by a worker
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Events delivered to the actor, `Stalled` and null-messages aside
+    pub events: u64,
+
+    /// Events pushed on the outputs of the `Merger`, null-messages aside
+    pub sent: u64,
+
+    /// Null-messages sent on the outputs of the `Merger`
+    pub nulls_sent: u64,
+
+    /// Null-messages received on the inputs of the `Merger`
+    pub nulls_received: u64,
+
+    /// `Stalled` events delivered to the actor
+    pub stalls: u64,
+
+    /// Calls to `advance`
+    pub advances: u64,
+
+    /// Time spent in `advance`
+    pub busy: Duration,
+}
+
+impl Stats {
+    const ZERO: Stats = Stats {
+        events: 0,
+        sent: 0,
+        nulls_sent: 0,
+        nulls_received: 0,
+        stalls: 0,
+        advances: 0,
+        busy: Duration::from_secs(0),
+    };
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.events += other.events;
+        self.sent += other.sent;
+        self.nulls_sent += other.nulls_sent;
+        self.nulls_received += other.nulls_received;
+        self.stalls += other.stalls;
+        self.advances += other.advances;
+        self.busy += other.busy;
+    }
+}
+
+/// Counters of a worker
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Counters of the actors it advanced, while it advanced them
+    pub stats: Stats,
+
+    /// Time spent looking for an actor to advance, or sleeping
+    pub idle: Duration,
+}
+
+/// What the engine counted over a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Counters of each actor, in the order they were given
+    pub actors: Vec<Stats>,
+
+    /// Counters of each worker
+    pub workers: Vec<WorkerStats>,
+}
+
+impl Report {
+    /// Counters of all the actors together
+    pub fn total(&self) -> Stats {
+        let mut total = Stats::default();
+        for &stats in &self.actors {
+            total += stats;
+        }
+        total
+    }
+
+    /// Time the workers spent idle, as a fraction of the time they ran
+    pub fn idle_fraction(&self) -> f64 {
+        let idle: Duration = self.workers.iter().map(|w| w.idle).sum();
+        let busy: Duration = self.workers.iter().map(|w| w.stats.busy).sum();
+        if idle + busy > Duration::from_secs(0) {
+            idle.as_secs_f64() / (idle + busy).as_secs_f64()
+        } else {
+            0.
+        }
+    }
+}
+
+thread_local! {
+    /// Counters handed over by the `Merger`s since the worker last took them
+    static HANDED_OVER: Cell<Stats> = const { Cell::new(Stats::ZERO) };
+}
+
+/// Hands `stats` over to the worker, and resets them
+pub(crate) fn hand_over(stats: &mut Stats) {
+    HANDED_OVER.with(|handed_over| {
+        let mut total = handed_over.get();
+        total += std::mem::take(stats);
+        handed_over.set(total);
+    });
+}
+
+/// Takes the counters handed over since the last call
+pub(crate) fn take() -> Stats {
+    HANDED_OVER.with(|handed_over| handed_over.replace(Stats::ZERO))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixtures::ring;
+    use crate::stats::*;
+    use crate::{start_with, Scheduler};
+
+    #[test]
+    fn test_report() {
+        for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+            let (_, report) = start_with(scheduler, 3, ring(7, 1_000), None).unwrap();
+            assert_eq!(report.actors.len(), 7);
+            assert_eq!(report.workers.len(), 3);
+            for stats in &report.actors {
+                assert!(stats.events > 0 && stats.advances > 0 && stats.stalls > 0);
+                assert!(stats.nulls_sent > 0 && stats.nulls_received > 0);
+            }
+
+            let total = report.total();
+            let workers = report
+                .workers
+                .iter()
+                .fold(Stats::default(), |mut total, w| {
+                    total += w.stats;
+                    total
+                });
+            assert_eq!(total, workers);
+            assert!((0. ..=1.).contains(&report.idle_fraction()));
+        }
+    }
+}
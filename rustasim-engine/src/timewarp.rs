@@ -0,0 +1,943 @@
+//! Optimistic (Time Warp) executor
+//!
+//! Conservative synchronization only lets an actor process an event once nothing earlier can
+//! arrive anymore. With little lookahead, actors spend most of their time `Stalled`. This executor
+//! lets actors process whatever they have instead, and undoes the work that turns out to be wrong:
+//!
+//! - the actor's state is saved with [`Reversible::snapshot`] before each event it processes,
+//! - an event arriving in the past of its receiver (a straggler) makes the receiver go back to the
+//!   state it had before the first event that should have come after it, and those events are
+//!   delivered again,
+//! - whatever the actor sent since is cancelled with anti-messages: the sender voids a range of
+//!   sequence numbers of the queue, the receiver drops them or rolls back if it already used them,
+//! - every so often the workers agree on the global virtual time (GVT), the earliest time a
+//!   rollback can still go back to. Older snapshots and events can't be needed anymore and are
+//!   fossil collected.
+//!
+//! Actors opt in by implementing [`Reversible`] and building their [`Merger`] with
+//! [`Merger::reversible`]. They are otherwise written exactly as for the conservative engine: the
+//! `Merger` hands out at most one event per call to `advance`, followed by a `Stalled` event at
+//! which point the actor must give the hand back. Null-messages are not needed, they are dropped on
+//! arrival.
+//!
+//! An actor may be rolled back after it is `Done`, its result is only final at the end of the run.
+//! Anything an actor does outside of its own state and of what it sends through its `Merger` can't
+//! be undone.
+//!
+//! [`Merger`]: struct.Merger.html
+//! [`Merger::reversible`]: struct.Merger.html#method.reversible
+
+use crate::engine::{Event, EventType};
+use crate::links::LinkState;
+use crate::spsc::Consumer;
+use crate::worker::{ActorState, Advancer, Driver, Mode};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+/// Busy passes over its actors after which a worker asks for the GVT
+const GVT_INTERVAL: usize = 256;
+
+/// Passes without anything to do after which a worker asks for the GVT
+const IDLE_GVT_INTERVAL: usize = 16;
+
+/// Events an actor may process past the GVT before waiting for it to advance
+///
+/// This also bounds how much an actor can push on a queue between two GVT computations.
+const MAX_UNCOMMITTED: usize = 128;
+
+/// Actors that can be run by the [optimistic executor](fn.start_optimistic.html)
+pub trait Reversible<T, R>: Advancer<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// Saves the state of the actor
+    ///
+    /// This is everything `advance` changes, except for the actor's `Merger` and output queues
+    /// which the engine takes care of.
+    fn snapshot(&self) -> Box<dyn Any + Send>;
+
+    /// Goes back to a state previously returned by `snapshot`
+    fn restore(&mut self, snapshot: Box<dyn Any + Send>);
+}
+
+/// Ranges of sequence numbers cancelled by the producer of a queue
+///
+/// Ranges are counted from the first one ever cancelled, those the consumer is done with are
+/// trimmed from the front.
+#[derive(Debug, Default)]
+pub(crate) struct Voids {
+    /// Number of ranges cancelled so far, can be checked without locking
+    n_ranges: AtomicUsize,
+    ranges: Mutex<Ranges>,
+}
+
+#[derive(Debug, Default)]
+struct Ranges {
+    /// Number of ranges trimmed, the first one left is range `n_trimmed`
+    n_trimmed: usize,
+    ranges: VecDeque<Range<u64>>,
+}
+
+impl Voids {
+    fn push(&self, range: Range<u64>) {
+        let mut ranges = self.ranges.lock();
+        ranges.ranges.push_back(range);
+        self.n_ranges
+            .store(ranges.n_trimmed + ranges.ranges.len(), Ordering::Release);
+    }
+
+    /// Ranges cancelled after the first `n_seen`
+    fn since(&self, n_seen: usize) -> Vec<Range<u64>> {
+        if self.n_ranges.load(Ordering::Acquire) == n_seen {
+            return Vec::new();
+        }
+
+        let ranges = self.ranges.lock();
+        ranges
+            .ranges
+            .range(n_seen - ranges.n_trimmed..)
+            .cloned()
+            .collect()
+    }
+
+    /// Drops the ranges among the first `n_seen` that are all before `next_seq`, the next element
+    /// the consumer will pop
+    fn trim(&self, n_seen: usize, next_seq: u64) {
+        let mut ranges = self.ranges.lock();
+        while ranges.n_trimmed < n_seen && ranges.ranges.front().is_some_and(|r| r.end <= next_seq)
+        {
+            ranges.ranges.pop_front();
+            ranges.n_trimmed += 1;
+        }
+    }
+}
+
+/// What a worker and the `Merger` of the actor being advanced tell each other
+struct Turn {
+    /// Whether the actor may process its next event
+    deliver: bool,
+
+    /// Delivered events before this index will never be replayed
+    collect_before: u64,
+
+    /// Index and time of the event delivered this turn
+    delivered: Option<(u64, Box<dyn Any>)>,
+
+    /// Index of the first delivered event undone this turn
+    rolled_back: Option<u64>,
+
+    /// Time of the earliest event left to process
+    floor: Option<Box<dyn Any>>,
+
+    /// Queues pushed to this turn, and the sequence number of the element pushed
+    sent: Vec<(Arc<Voids>, u64)>,
+}
+
+thread_local! {
+    static TURN: RefCell<Option<Turn>> = const { RefCell::new(None) };
+}
+
+/// Notes that the actor being advanced pushed element `seq` of the queue cancelled by `voids`
+pub(crate) fn sent(voids: &Arc<Voids>, seq: u64) {
+    TURN.with(|turn| {
+        if let Some(turn) = turn.borrow_mut().as_mut() {
+            turn.sent.push((Arc::clone(voids), seq));
+        }
+    })
+}
+
+/// Whether the actor being advanced may process an event this turn
+///
+/// Nothing may be sent during turns that don't deliver: the executor might be probing for the
+/// GVT, and it would miss events pushed to queues it already went through.
+pub(crate) fn may_deliver() -> bool {
+    with_turn(|turn| turn.deliver)
+}
+
+/// Keeps the actor being advanced from processing an event this turn
+pub(crate) fn hold() {
+    with_turn(|turn| turn.deliver = false)
+}
+
+/// Whether the actor being advanced has to roll back at the end of this turn
+pub(crate) fn is_rolling_back() -> bool {
+    with_turn(|turn| turn.rolled_back.is_some())
+}
+
+/// Notes that the actor being advanced still has an event at `time` to send
+pub(crate) fn lower_floor<T: 'static + Ord + Copy>(time: T) {
+    with_turn(|turn| {
+        let floor = match turn.floor.take() {
+            Some(floor) => std::cmp::min(*floor.downcast::<T>().unwrap(), time),
+            None => time,
+        };
+        turn.floor = Some(Box::new(floor));
+    })
+}
+
+/// Runs `f` on the current turn, panics outside of one
+fn with_turn<F, V>(f: F) -> V
+where
+    F: FnOnce(&mut Turn) -> V,
+{
+    TURN.with(|turn| {
+        f(turn
+            .borrow_mut()
+            .as_mut()
+            .expect("not in an optimistic turn"))
+    })
+}
+
+/// Copies an event, to deliver it again after a rollback
+pub(crate) type CloneEvent<T, U> = fn(&Event<T, U>) -> Event<T, U>;
+
+/// Order in which an actor processes its events: time, source id, queue index, sequence number
+///
+/// This is the order described in the [engine documentation](index.html#event-ordering).
+type Key<T> = (T, usize, usize, u64);
+
+/// An event that is yet to be processed
+#[derive(Debug)]
+struct Pending<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    key: Key<T>,
+    event: Event<T, U>,
+}
+
+// reversed, the heap gives the earliest event
+impl<T, U> Ord for Pending<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.key.cmp(&self.key)
+    }
+}
+
+impl<T, U> PartialOrd for Pending<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, U> PartialEq for Pending<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T, U> Eq for Pending<T, U> where T: Ord + Copy + num::Zero {}
+
+/// The `Merger`'s inputs when run optimistically
+#[derive(Debug)]
+pub(crate) struct Inputs<T, U>
+where
+    T: Ord + Copy + num::Zero,
+{
+    clone_event: CloneEvent<T, U>,
+
+    /// Events received and not processed yet
+    pending: BinaryHeap<Pending<T, U>>,
+
+    /// Copies of the processed events that might have to be delivered again, the first one has
+    /// index `base`
+    processed: VecDeque<(Key<T>, Event<T, U>)>,
+    base: u64,
+
+    /// Time of the last event that can't be undone anymore
+    committed: T,
+
+    /// Time of the last event processed
+    lvt: T,
+
+    /// Cancelled ranges of each input that might still be popped, and how many ranges we've seen
+    /// since the first one ever cancelled
+    voids: Vec<Vec<Range<u64>>>,
+    n_voids: Vec<usize>,
+}
+
+impl<T, U> Inputs<T, U>
+where
+    T: 'static + Ord + Copy + num::Zero,
+{
+    pub(crate) fn new(n_inputs: usize, clone_event: CloneEvent<T, U>) -> Self {
+        Inputs {
+            clone_event,
+            pending: BinaryHeap::new(),
+            processed: VecDeque::new(),
+            base: 0,
+            committed: T::zero(),
+            lvt: T::zero(),
+            voids: vec![Vec::new(); n_inputs],
+            n_voids: vec![0; n_inputs],
+        }
+    }
+
+    /// Next event, `Stalled` if the executor doesn't allow one or if the actor has to roll back
+    pub(crate) fn next(
+        &mut self,
+        in_queues: &[Consumer<Event<T, U>>],
+        in_links: &[Arc<LinkState>],
+        ix_to_id: &[usize],
+    ) -> Event<T, U> {
+        // forget what the executor won't roll back to
+        let collect_before = with_turn(|turn| turn.collect_before);
+        if self.base < collect_before {
+            while self.base < collect_before {
+                let (key, _) = self
+                    .processed
+                    .pop_front()
+                    .expect("collecting unprocessed events");
+                self.committed = key.0;
+                self.base += 1;
+            }
+
+            // the cancelled ranges we've seen and popped past are of no use anymore either
+            for (ix, (q, link)) in in_queues.iter().zip(in_links).enumerate() {
+                link.voids().trim(self.n_voids[ix], q.next_seq());
+            }
+        }
+
+        // position of the first processed event to undo
+        let mut undo: Option<usize> = None;
+        let mut cancelled = Vec::new();
+
+        // anti-messages
+        for (ix, link) in in_links.iter().enumerate() {
+            let ranges = link.voids().since(self.n_voids[ix]);
+            if ranges.is_empty() {
+                continue;
+            }
+            self.n_voids[ix] += ranges.len();
+
+            let is_void = |key: &Key<T>| key.2 == ix && ranges.iter().any(|r| r.contains(&key.3));
+            let pending = std::mem::take(&mut self.pending);
+            self.pending = pending.into_iter().filter(|p| !is_void(&p.key)).collect();
+            for (pos, (key, _)) in self.processed.iter().enumerate() {
+                if is_void(key) {
+                    cancelled.push(pos);
+                    undo = Some(undo.map_or(pos, |undo| undo.min(pos)));
+                }
+            }
+
+            // some of them might still be in the queue
+            self.voids[ix].extend(ranges);
+        }
+
+        // new events, stragglers undo what came after them
+        for (ix, q) in in_queues.iter().enumerate() {
+            loop {
+                let seq = q.next_seq();
+                let mut event = match q.pop() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                };
+
+                if let EventType::Null = event.event_type {
+                    continue;
+                }
+                if self.voids[ix].iter().any(|r| r.contains(&seq)) {
+                    continue;
+                }
+
+                event.src = ix;
+                let key = (event.time, ix_to_id[ix], ix, seq);
+                if self.processed.back().is_some_and(|(last, _)| key < *last) {
+                    let pos = self
+                        .processed
+                        .partition_point(|(processed, _)| *processed < key);
+                    undo = Some(undo.map_or(pos, |undo| undo.min(pos)));
+                }
+
+                self.pending.push(Pending { key, event });
+            }
+
+            let next_seq = q.next_seq();
+            self.voids[ix].retain(|r| r.end > next_seq);
+        }
+
+        if let Some(pos) = undo {
+            for (offset, (key, event)) in self.processed.drain(pos..).enumerate() {
+                if !cancelled.contains(&(pos + offset)) {
+                    self.pending.push(Pending { key, event });
+                }
+            }
+            self.lvt = self
+                .processed
+                .back()
+                .map_or(self.committed, |(key, _)| key.0);
+
+            let index = self.base + pos as u64;
+            with_turn(|turn| {
+                turn.rolled_back = Some(turn.rolled_back.map_or(index, |prev| prev.min(index)))
+            });
+        } else if with_turn(|turn| std::mem::replace(&mut turn.deliver, false)) {
+            if let Some(Pending { key, event }) = self.pending.pop() {
+                let index = self.base + self.processed.len() as u64;
+                self.processed.push_back((key, (self.clone_event)(&event)));
+                self.lvt = key.0;
+
+                let floor = self
+                    .pending
+                    .peek()
+                    .map(|p| Box::new(p.key.0) as Box<dyn Any>);
+                with_turn(|turn| {
+                    turn.delivered = Some((index, Box::new(key.0)));
+                    turn.floor = floor;
+                });
+
+                return event;
+            }
+        }
+
+        let floor = self
+            .pending
+            .peek()
+            .map(|p| Box::new(p.key.0) as Box<dyn Any>);
+        with_turn(|turn| turn.floor = floor);
+
+        Event {
+            time: self.lvt,
+            src: 0,
+            event_type: EventType::Stalled,
+        }
+    }
+}
+
+/// What happened during an actor's turn
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Delivered,
+    RolledBack,
+    Idle,
+}
+
+/// An actor and everything needed to roll it back
+struct Lp<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    actor: Box<dyn Reversible<T, R> + Send>,
+
+    /// State before each event that might still be undone: index, event time, state
+    snapshots: VecDeque<(u64, T, Box<dyn Any + Send>)>,
+
+    /// Everything sent that might still be cancelled: index of the event being processed, queue,
+    /// sequence number
+    sent: VecDeque<(u64, Arc<Voids>, u64)>,
+
+    /// Index of the next event to process
+    next_index: u64,
+
+    /// Events before this index will never be replayed
+    collect_before: u64,
+
+    /// Time of the earliest event left to process
+    floor: Option<T>,
+
+    /// Result, once done
+    done: Option<R>,
+}
+
+impl<T, R> Lp<T, R>
+where
+    T: 'static + Ord + Copy + num::Zero,
+{
+    fn new(actor: Box<dyn Reversible<T, R> + Send>) -> Self {
+        Lp {
+            actor,
+            snapshots: VecDeque::new(),
+            sent: VecDeque::new(),
+            next_index: 0,
+            collect_before: 0,
+            floor: None,
+            done: None,
+        }
+    }
+
+    /// Whether the actor may process events past the GVT
+    fn may_run(&self, gvt: T) -> bool {
+        self.done.is_none()
+            && (self.snapshots.len() < MAX_UNCOMMITTED || self.floor.is_some_and(|t| t <= gvt))
+    }
+
+    /// Advances the actor, letting it process one event if `deliver` is set
+    fn turn(&mut self, deliver: bool) -> Outcome {
+        let snapshot = if deliver {
+            Some(self.actor.snapshot())
+        } else {
+            None
+        };
+
+        TURN.with(|turn| {
+            *turn.borrow_mut() = Some(Turn {
+                deliver,
+                collect_before: self.collect_before,
+                delivered: None,
+                rolled_back: None,
+                floor: None,
+                sent: Vec::new(),
+            })
+        });
+        let state = self.actor.advance();
+        let turn = TURN.with(|turn| turn.borrow_mut().take()).unwrap();
+
+        let delivered = turn.delivered.is_some();
+        let index = match turn.delivered {
+            Some((index, time)) => {
+                let time = *time
+                    .downcast::<T>()
+                    .expect("Merger and actor disagree on the time type");
+                self.snapshots.push_back((index, time, snapshot.unwrap()));
+                self.next_index = index + 1;
+                index
+            }
+            None => self.next_index,
+        };
+        for (voids, seq) in turn.sent {
+            self.sent.push_back((index, voids, seq));
+        }
+
+        if let ActorState::Done(result) = state {
+            self.done = Some(result);
+        }
+        self.floor = turn.floor.map(|floor| *floor.downcast::<T>().unwrap());
+
+        match turn.rolled_back {
+            Some(index) => {
+                self.roll_back(index);
+                Outcome::RolledBack
+            }
+            None if delivered => Outcome::Delivered,
+            None => Outcome::Idle,
+        }
+    }
+
+    /// Goes back to the state before event `index`, and cancels what was sent since
+    fn roll_back(&mut self, index: u64) {
+        let pos = self
+            .snapshots
+            .iter()
+            .position(|(i, _, _)| *i == index)
+            .expect("rolling back past the GVT");
+        let (_, _, snapshot) = self.snapshots.drain(pos..).next().unwrap();
+        self.actor.restore(snapshot);
+        self.next_index = index;
+        self.done = None;
+
+        let mut cancels: Vec<(Arc<Voids>, Range<u64>)> = Vec::new();
+        while self.sent.back().is_some_and(|(i, _, _)| *i >= index) {
+            let (_, voids, seq) = self.sent.pop_back().unwrap();
+            match cancels.iter_mut().find(|(v, _)| Arc::ptr_eq(v, &voids)) {
+                Some((_, range)) => {
+                    range.start = range.start.min(seq);
+                    range.end = range.end.max(seq + 1);
+                }
+                None => cancels.push((voids, seq..seq + 1)),
+            }
+        }
+
+        for (voids, range) in cancels {
+            voids.push(range);
+        }
+    }
+
+    /// Forgets what can't be rolled back anymore, `None` meaning everything
+    fn collect(&mut self, gvt: Option<T>) {
+        // a straggler at the GVT still needs the state before the events at the GVT
+        let keep = self
+            .snapshots
+            .iter()
+            .position(|(_, time, _)| gvt.is_some_and(|gvt| *time >= gvt))
+            .unwrap_or(self.snapshots.len());
+        self.snapshots.drain(..keep);
+
+        self.collect_before = self
+            .snapshots
+            .front()
+            .map_or(self.next_index, |(index, _, _)| *index);
+        while self
+            .sent
+            .front()
+            .is_some_and(|(index, _, _)| *index < self.collect_before)
+        {
+            self.sent.pop_front();
+        }
+    }
+}
+
+/// What the workers share to compute the GVT
+struct Shared<T> {
+    gvt_requested: AtomicBool,
+    barrier: Barrier,
+
+    /// Per worker: earliest event left, actors not done, rollbacks while probing
+    reports: Mutex<Vec<(Option<T>, usize, usize)>>,
+}
+
+/// Computes the GVT with the other workers, returns `None` once the simulation is over
+fn gvt_round<T, R>(id: usize, shared: &Shared<T>, lps: &mut [Lp<T, R>]) -> Option<T>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    if shared.barrier.wait().is_leader() {
+        shared.gvt_requested.store(false, Ordering::Relaxed);
+    }
+
+    // nobody processes events anymore, everything in transit ends up on someone's floor
+    let mut floor: Option<T> = None;
+    let mut n_running = 0;
+    let mut n_rollbacks = 0;
+    for lp in lps.iter_mut() {
+        if lp.turn(false) == Outcome::RolledBack {
+            n_rollbacks += 1;
+        }
+        if lp.done.is_none() {
+            n_running += 1;
+            if let Some(lp_floor) = lp.floor {
+                floor = Some(floor.map_or(lp_floor, |floor| floor.min(lp_floor)));
+            }
+        }
+    }
+    shared.reports.lock()[id] = (floor, n_running, n_rollbacks);
+    shared.barrier.wait();
+
+    let (gvt, n_running, n_rollbacks) = shared.reports.lock().iter().fold(
+        (None, 0, 0),
+        |(gvt, n_running, n_rollbacks): (Option<T>, usize, usize), &(floor, running, rollbacks)| {
+            let gvt = match (gvt, floor) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            (gvt, n_running + running, n_rollbacks + rollbacks)
+        },
+    );
+
+    match gvt {
+        // anti-messages sent while probing might not have been seen yet
+        None if n_rollbacks > 0 => Some(T::zero()),
+        None => {
+            assert_eq!(
+                n_running, 0,
+                "{} actors have nothing left to do but never finished",
+                n_running
+            );
+            for lp in lps.iter_mut() {
+                lp.collect(None);
+            }
+            None
+        }
+        Some(gvt) => {
+            for lp in lps.iter_mut() {
+                lp.collect(Some(gvt));
+            }
+            Some(gvt)
+        }
+    }
+}
+
+/// Runs a worker's actors until the simulation is over
+fn run<T, R>(id: usize, shared: Arc<Shared<T>>, mut lps: Vec<Lp<T, R>>) -> Vec<R>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    let mut gvt = T::zero();
+    let mut n_busy = 0;
+    let mut n_idle = 0;
+
+    loop {
+        if shared.gvt_requested.load(Ordering::Relaxed) {
+            match gvt_round(id, &shared, &mut lps) {
+                Some(new_gvt) => gvt = std::cmp::max(gvt, new_gvt),
+                None => break,
+            }
+            continue;
+        }
+
+        let mut busy = false;
+        for lp in lps.iter_mut().filter(|lp| lp.may_run(gvt)) {
+            busy |= lp.turn(true) != Outcome::Idle;
+        }
+
+        let (count, interval) = if busy {
+            (&mut n_busy, GVT_INTERVAL)
+        } else {
+            (&mut n_idle, IDLE_GVT_INTERVAL)
+        };
+        *count += 1;
+        if *count % interval == 0 {
+            shared.gvt_requested.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // only now can't they be rolled back anymore
+    lps.into_iter()
+        .map(|mut lp| {
+            lp.actor.finish();
+            lp.done.unwrap()
+        })
+        .collect()
+}
+
+/// Runs the actors optimistically on `num_cpus` workers
+///
+/// Actors are spread evenly over the workers and stay on them. The results are returned in the
+/// order the actors were given, the actors' `Merger`s must be
+/// [reversible](struct.Merger.html#method.reversible).
+///
+/// Unlike the other executors, this one takes no end time: getting there would be as optimistic
+/// as any event, and ending an actor can't be rolled back. The actors have to close on their own.
+/// Their [`finish`](trait.Advancer.html#method.finish) hooks are only called once they are all
+/// done, when nothing can be rolled back anymore.
+///
+/// # Panics
+///
+/// Panics if some actors never finish because none of them has anything left to do.
+pub fn start_optimistic<T, R>(
+    num_cpus: usize,
+    actors: Vec<Box<dyn Reversible<T, R> + Send>>,
+) -> Vec<R>
+where
+    T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    R: 'static + Send,
+{
+    let n_actors = actors.len();
+    let num_cpus = std::cmp::max(1, std::cmp::min(num_cpus, n_actors));
+    let shared = Arc::new(Shared {
+        gvt_requested: AtomicBool::new(false),
+        barrier: Barrier::new(num_cpus),
+        reports: Mutex::new(vec![(None, 0, 0); num_cpus]),
+    });
+
+    let mut partitions: Vec<Vec<Lp<T, R>>> = (0..num_cpus).map(|_| Vec::new()).collect();
+    for (i, mut actor) in actors.into_iter().enumerate() {
+        actor.driven_by(Driver::new(Mode::Optimistic, None));
+        partitions[i % num_cpus].push(Lp::new(actor));
+    }
+
+    let handles: Vec<_> = partitions
+        .into_iter()
+        .enumerate()
+        .map(|(id, lps)| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(id, shared, lps))
+        })
+        .collect();
+
+    // worker `i` had actors `i`, `i + num_cpus` and so on
+    let mut results: Vec<Option<R>> = (0..n_actors).map(|_| None).collect();
+    for (i, h) in handles.into_iter().enumerate() {
+        for (k, result) in h.join().unwrap().into_iter().enumerate() {
+            results[i + k * num_cpus] = Some(result);
+        }
+    }
+
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spsc;
+    use crate::timewarp::*;
+    use crate::{actor_rng, start_sequential, Merger};
+    use rand::Rng;
+    use rand_chacha::ChaCha20Rng;
+
+    const LOOKAHEAD: u64 = 1;
+
+    /// PHOLD-like actor, returns a checksum of the events it processed, in order
+    #[derive(Debug)]
+    struct Phold {
+        id: usize,
+        limit: u64,
+        merger: Merger<u64, u64>,
+        out_times: Vec<u64>,
+
+        rng: ChaCha20Rng,
+        checksum: u64,
+    }
+
+    impl Advancer<u64, u64> for Phold {
+        fn advance(&mut self) -> ActorState<u64, u64> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::ModelEvent(hops) if event.time <= self.limit => {
+                        self.checksum = self
+                            .checksum
+                            .wrapping_mul(31)
+                            .wrapping_add(event.time * 7 + hops);
+
+                        let dst = self.rng.gen_range(0, self.out_times.len());
+                        // links are FIFO, the sends on each of them must be in order
+                        self.out_times[dst] = std::cmp::max(self.out_times[dst], event.time)
+                            + self.rng.gen_range(0, 20);
+                        let time = self.out_times[dst] + LOOKAHEAD;
+                        self.merger.send(
+                            dst,
+                            Event {
+                                time,
+                                src: self.id,
+                                event_type: EventType::ModelEvent(hops + 1),
+                            },
+                        );
+                    }
+                    EventType::ModelEvent(_) | EventType::Close => {
+                        for dst in 0..self.out_times.len() {
+                            self.merger.send(
+                                dst,
+                                Event {
+                                    time: event.time + LOOKAHEAD,
+                                    src: self.id,
+                                    event_type: EventType::Close,
+                                },
+                            );
+                        }
+                        break;
+                    }
+                    EventType::Stalled => {
+                        for dst in 0..self.out_times.len() {
+                            if self.out_times[dst] < event.time {
+                                self.out_times[dst] = event.time;
+                                self.merger.send(
+                                    dst,
+                                    Event {
+                                        time: event.time + LOOKAHEAD,
+                                        src: self.id,
+                                        event_type: EventType::Null,
+                                    },
+                                );
+                            }
+                        }
+                        return ActorState::Continue(event.time);
+                    }
+                    EventType::Null => unreachable!(),
+                }
+            }
+
+            ActorState::Done(self.checksum)
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    impl Reversible<u64, u64> for Phold {
+        fn snapshot(&self) -> Box<dyn Any + Send> {
+            Box::new((self.out_times.clone(), self.rng.clone(), self.checksum))
+        }
+
+        fn restore(&mut self, snapshot: Box<dyn Any + Send>) {
+            let (out_times, rng, checksum) = *snapshot.downcast().unwrap();
+            self.out_times = out_times;
+            self.rng = rng;
+            self.checksum = checksum;
+        }
+    }
+
+    fn phold(n_actors: usize, limit: u64) -> Vec<Phold> {
+        let mut outs: Vec<Vec<_>> = (0..n_actors).map(|_| Vec::new()).collect();
+        let mut ins: Vec<Vec<_>> = (0..n_actors).map(|_| Vec::new()).collect();
+        for (src, src_outs) in outs.iter_mut().enumerate() {
+            for dst_ins in ins.iter_mut() {
+                let (prod, cons) = spsc::new(4096);
+                prod.push(Event {
+                    time: LOOKAHEAD,
+                    src,
+                    event_type: EventType::Null,
+                })
+                .unwrap();
+                src_outs.push(prod);
+                dst_ins.push(cons);
+            }
+        }
+
+        // a few tokens to start
+        for (id, src_outs) in outs.iter().enumerate() {
+            src_outs[id]
+                .push(Event {
+                    time: 1 + id as u64 % 3,
+                    src: id,
+                    event_type: EventType::ModelEvent(0),
+                })
+                .unwrap();
+        }
+
+        outs.into_iter()
+            .zip(ins)
+            .enumerate()
+            .map(|(id, (out_queues, in_queues))| Phold {
+                id,
+                limit,
+                merger: out_queues.into_iter().fold(
+                    Merger::new(in_queues, id, (0..n_actors).collect()).reversible(),
+                    |merger, q| merger.output(q, LOOKAHEAD),
+                ),
+                out_times: vec![0; n_actors],
+                rng: actor_rng(0, id),
+                checksum: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_voids() {
+        let voids = Voids::default();
+        voids.push(0..2);
+        voids.push(5..6);
+        assert_eq!(voids.since(0), vec![0..2, 5..6]);
+
+        // only what was seen goes, and nothing past what was popped
+        voids.trim(1, 10);
+        voids.push(3..4);
+        voids.trim(3, 5);
+        assert_eq!(voids.since(1), vec![5..6, 3..4]);
+        assert_eq!(voids.since(3), vec![]);
+
+        voids.trim(3, 6);
+        assert_eq!(voids.since(3), vec![]);
+        voids.push(7..8);
+        assert_eq!(voids.since(3), vec![7..8]);
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let actors = phold(9, 2_000);
+        let optimistic = start_optimistic(
+            3,
+            actors
+                .into_iter()
+                .map(|a| Box::new(a) as Box<dyn Reversible<u64, u64> + Send>)
+                .collect(),
+        );
+
+        let actors = phold(9, 2_000);
+        let sequential = start_sequential(
+            actors
+                .into_iter()
+                .map(|a| Box::new(a) as Box<dyn Advancer<u64, u64> + Send>)
+                .collect(),
+            None,
+        );
+
+        // each actor processed the same events in the same order
+        assert_eq!(optimistic, sequential);
+        assert!(sequential.iter().all(|&checksum| checksum > 0));
+    }
+}
@@ -0,0 +1,346 @@
+//! Traces what the `Merger`s do, for `log-analysis.R`
+//!
+//! Only built with the `trace` feature, the `Merger`s don't record anything otherwise. Once
+//! [`start`](fn.start.html)ed, every event a `Merger` receives, delivers or pushes, and every time
+//! it stalls, is written as a line of CSV:
+//!
+//! ```text
+//! real_time,sim_time,id,src,link,action,type
+//! ```
+//!
+//! - `real_time` is in nanoseconds since tracing was first started,
+//! - `id` is the actor the `Merger` belongs to,
+//! - `src` is the actor that sent the event, `id` itself for stalls,
+//! - `link` is the index of the input the event came from, or of the output it went to,
+//! - `action` is `rx` for events received, `tx` for those pushed on an output, `full` for those
+//!   held back because the output was full, and pushed later on, and `stall` when the actor is
+//!   told to give the hand back,
+//! - `type` is the kind of event: `ModelEvent`, `Null`, `Stalled` or `Close`.
+//!
+//! Events received are recorded when they are delivered to the actor, null-messages when they are
+//! dropped. Those of the optimistic executor aren't recorded.
+//!
+//! Lines are kept in a buffer of the thread that recorded them, and written out when it fills up,
+//! when the thread exits, and for the calling thread, on [`finish`](fn.finish.html).
+
+use crate::engine::{Event, EventType};
+use parking_lot::{const_mutex, Mutex};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// First line of the trace
+pub const CSV_HEADER: &str = "real_time,sim_time,id,src,link,action,type";
+
+// thread buffers are written out past this size
+const BUFFER_SIZE: usize = 1 << 16;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+static OUT: Mutex<Option<BufWriter<File>>> = const_mutex(None);
+
+thread_local! {
+    static BUFFER: RefCell<Buffer> = const { RefCell::new(Buffer(Vec::new())) };
+}
+
+/// Lines recorded by this thread, written out when it exits
+struct Buffer(Vec<u8>);
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // nobody to tell anyway
+        let _ = write_out(&mut self.0);
+    }
+}
+
+/// What the `Merger` did with an event
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Action {
+    Rx,
+    Tx,
+    Full,
+    Stall,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Rx => "rx",
+            Action::Tx => "tx",
+            Action::Full => "full",
+            Action::Stall => "stall",
+        }
+    }
+}
+
+/// Starts tracing to `path`, replacing whatever it held
+///
+/// Tracing already started carries on to the new file, lines not yet written out included.
+pub fn start(path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "{}", CSV_HEADER)?;
+
+    EPOCH.get_or_init(Instant::now);
+    *OUT.lock() = Some(out);
+    ENABLED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Stops tracing, and writes out the lines recorded by the calling thread
+///
+/// Threads that recorded lines must have exited by then, for theirs to be written out too.
+pub fn finish() -> io::Result<()> {
+    ENABLED.store(false, Ordering::Relaxed);
+    BUFFER.with(|buffer| write_out(&mut buffer.borrow_mut().0))?;
+
+    match OUT.lock().take() {
+        Some(mut out) => out.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Records `action` on `event` by the `Merger` of actor `id`
+pub(crate) fn record<T, U>(action: Action, id: usize, src: usize, link: usize, event: &Event<T, U>)
+where
+    T: Ord + Copy + Debug + num::Zero,
+{
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let real_time = EPOCH.get().map_or(0, |epoch| epoch.elapsed().as_nanos());
+    let kind = match event.event_type {
+        EventType::ModelEvent(_) => "ModelEvent",
+        EventType::Stalled => "Stalled",
+        EventType::Null => "Null",
+        EventType::Close => "Close",
+    };
+
+    BUFFER.with(|buffer| {
+        let buffer = &mut buffer.borrow_mut().0;
+        // writing to a `Vec` can't fail
+        let _ = writeln!(
+            buffer,
+            "{},{:?},{},{},{},{},{}",
+            real_time,
+            event.time,
+            id,
+            src,
+            link,
+            action.as_str(),
+            kind
+        );
+
+        if buffer.len() >= BUFFER_SIZE {
+            let _ = write_out(buffer);
+        }
+    });
+}
+
+/// Writes `lines` to the trace and clears them, they are dropped if tracing is over
+fn write_out(lines: &mut Vec<u8>) -> io::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let result = match OUT.lock().as_mut() {
+        Some(out) => out.write_all(lines),
+        None => Ok(()),
+    };
+    lines.clear();
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::trace::*;
+    use crate::{spsc, ActorState, Advancer, Driver, Merger};
+
+    /// Sends back whatever it gets a tick later, until `limit`
+    ///
+    /// Queue 0 loops back to the actor itself, queue 1 goes to the peer.
+    #[derive(Debug)]
+    struct Ping {
+        limit: u64,
+        merger: Merger<u64, ()>,
+    }
+
+    impl Advancer<u64, ()> for Ping {
+        fn advance(&mut self) -> ActorState<u64, ()> {
+            while let Some(event) = self.merger.next() {
+                let event_type = match event.event_type {
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    EventType::ModelEvent(()) if event.time < self.limit => {
+                        EventType::ModelEvent(())
+                    }
+                    _ => EventType::Close,
+                };
+                let done = matches!(event_type, EventType::Close);
+                self.merger.send(
+                    1,
+                    Event {
+                        time: event.time + 1,
+                        src: self.merger.id(),
+                        event_type,
+                    },
+                );
+                if done {
+                    break;
+                }
+            }
+
+            match self.merger.finish() {
+                None => ActorState::Done(()),
+                Some(time) => ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    /// Sends a burst of `size` events on its first one, then closes
+    ///
+    /// With no `size`, it has no output and only closes once told to.
+    #[derive(Debug)]
+    struct Burst {
+        size: u64,
+        merger: Merger<u64, ()>,
+    }
+
+    impl Advancer<u64, ()> for Burst {
+        fn advance(&mut self) -> ActorState<u64, ()> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    EventType::ModelEvent(()) if self.size > 0 => {
+                        for i in 1..=self.size + 1 {
+                            let event_type = if i <= self.size {
+                                EventType::ModelEvent(())
+                            } else {
+                                EventType::Close
+                            };
+                            self.merger.send(
+                                0,
+                                Event {
+                                    time: event.time + i,
+                                    src: self.merger.id(),
+                                    event_type,
+                                },
+                            );
+                        }
+                        break;
+                    }
+                    EventType::ModelEvent(()) => {}
+                    _ => break,
+                }
+            }
+
+            match self.merger.finish() {
+                None => ActorState::Done(()),
+                Some(time) => ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let path = std::env::temp_dir().join(format!("rustasim-trace-{}.csv", std::process::id()));
+
+        let mut actors: Vec<Box<dyn Advancer<u64, ()> + Send>> = Vec::new();
+        let (to_b, from_a) = spsc::new(16);
+        let (to_a, from_b) = spsc::new(16);
+        to_a.push(Event {
+            time: 1,
+            src: 1,
+            event_type: EventType::ModelEvent(()),
+        })
+        .unwrap();
+        for (id, to_peer, from_peer) in [(0, to_b, from_b), (1, to_a, from_a)] {
+            let (to_self, from_self) = spsc::new(16);
+            let merger = Merger::new(vec![from_self, from_peer], id, vec![id, 1 - id])
+                .output(to_self, 1)
+                .output(to_peer, 1);
+            actors.push(Box::new(Ping { limit: 50, merger }));
+        }
+
+        // the burst only fits in its queue two at a time, the other input of each side closes it
+        // once the burst is over
+        let start_time = 1_000_000;
+        let closing = || {
+            let (to, from) = spsc::new(1);
+            to.push(Event {
+                time: start_time + 100,
+                src: 1,
+                event_type: EventType::Close,
+            })
+            .unwrap();
+            from
+        };
+        let mut burst: Vec<Box<dyn Advancer<u64, ()> + Send>> = Vec::new();
+        let (kick, from_kick) = spsc::new(1);
+        kick.push(Event {
+            time: start_time,
+            src: 0,
+            event_type: EventType::ModelEvent(()),
+        })
+        .unwrap();
+        let (to_b, from_a) = spsc::new(2);
+        let merger = Merger::new(vec![from_kick, closing()], 0, vec![0, 1]).output(to_b, 1);
+        burst.push(Box::new(Burst { size: 10, merger }));
+        let merger = Merger::new(vec![from_a, closing()], 1, vec![0, 1]);
+        burst.push(Box::new(Burst { size: 0, merger }));
+
+        start(&path).unwrap();
+        crate::start(2, actors, None).unwrap();
+        crate::start(1, burst, None).unwrap();
+        finish().unwrap();
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        let mut lines = trace.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert!(lines.all(|line| line.split(',').count() == 7));
+        for action in &["rx", "tx", "stall"] {
+            let field = format!(",{},", action);
+            assert!(
+                trace.lines().any(|line| line.contains(&field)),
+                "no {} in the trace",
+                action
+            );
+        }
+
+        // each pushed once, after being held back if it was
+        let actions = |time: u64| -> Vec<&str> {
+            let prefix = format!(",{},0,0,0,", time);
+            trace
+                .lines()
+                .filter(|line| line.contains(&prefix) && line.ends_with(",ModelEvent"))
+                .filter_map(|line| line.split(',').nth(5))
+                .collect()
+        };
+        assert_eq!(actions(start_time + 1), ["tx"]);
+        assert_eq!(actions(start_time + 10), ["full", "tx"]);
+        for time in start_time + 1..=start_time + 10 {
+            let actions = actions(time);
+            assert!(
+                actions == ["tx"] || actions == ["full", "tx"],
+                "{:?}",
+                actions
+            );
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+//! Tournament tree finding the least of many keys
+//!
+//! A [`LoserTree`](struct.LoserTree.html) keeps one key per leaf, and knows which leaf holds the
+//! least of them. Replacing the key of that winning leaf only replays the matches on its path to
+//! the root, which makes it the tool of choice for merging many sorted sequences: the winner is
+//! taken out, and replaced by the next element of its sequence. The `Merger` is built on it, and
+//! [`merge`](fn.merge.html) does the same for any sorted iterators.
+//!
+//! Leaves can be added and removed after the tree is built. Removed leaves are left vacant, the
+//! other ones keep their index.
+//!
+//! ```
+//! use rustasim::tree::merge;
+//!
+//! let merged: Vec<u32> = merge(vec![vec![1, 4, 9], vec![2, 3], vec![], vec![5]]).collect();
+//! assert_eq!(merged, vec![1, 2, 3, 4, 5, 9]);
+//! ```
+
+use std::mem;
+
+/// Keeps track of the leaf with the least key
+///
+/// Equal keys are won by the leaf with the lowest index, vacant leaves lose to everyone.
+#[derive(Debug, Clone)]
+pub struct LoserTree<K> {
+    // by leaf, `None` for vacant ones
+    keys: Vec<Option<K>>,
+
+    // the winner, then the loser of each match. Leaf `i` sits below match `(n + i) / 2`
+    nodes: Vec<usize>,
+}
+
+impl<K: Ord> Default for LoserTree<K> {
+    fn default() -> Self {
+        LoserTree::new(Vec::new())
+    }
+}
+
+impl<K: Ord> LoserTree<K> {
+    /// Builds a tree with one leaf per key, in order
+    pub fn new(keys: Vec<K>) -> Self {
+        let mut tree = LoserTree {
+            keys: keys.into_iter().map(Some).collect(),
+            nodes: Vec::new(),
+        };
+        tree.rebuild();
+        tree
+    }
+
+    /// Number of leaves, vacant ones included
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether the tree has no leaves at all
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Leaf with the least key, `None` if they are all vacant
+    pub fn winner(&self) -> Option<usize> {
+        self.nodes
+            .first()
+            .copied()
+            .filter(|&leaf| self.keys[leaf].is_some())
+    }
+
+    /// Leaf with the least key along with its key, `None` if they are all vacant
+    pub fn peek(&self) -> Option<(usize, &K)> {
+        let leaf = self.winner()?;
+        self.keys[leaf].as_ref().map(|key| (leaf, key))
+    }
+
+    /// Key of `leaf`, `None` if it is vacant
+    pub fn key(&self, leaf: usize) -> Option<&K> {
+        self.keys[leaf].as_ref()
+    }
+
+    /// Sets the key of `leaf`, `None` makes it vacant, and returns its previous key
+    ///
+    /// This takes `O(log n)` if `leaf` is the winner, `O(n)` otherwise.
+    pub fn replace(&mut self, leaf: usize, key: Option<K>) -> Option<K> {
+        let previous = mem::replace(&mut self.keys[leaf], key);
+        if self.nodes.first() == Some(&leaf) {
+            self.replay(leaf);
+        } else {
+            self.rebuild();
+        }
+
+        previous
+    }
+
+    /// Adds a leaf with `key`, and returns its index
+    ///
+    /// This takes `O(n)`.
+    pub fn push(&mut self, key: Option<K>) -> usize {
+        self.keys.push(key);
+        self.rebuild();
+
+        self.keys.len() - 1
+    }
+
+    /// Makes `leaf` vacant for good, and returns its key
+    ///
+    /// The other leaves keep their index. This takes `O(log n)` if `leaf` is the winner, `O(n)`
+    /// otherwise.
+    pub fn remove(&mut self, leaf: usize) -> Option<K> {
+        self.replace(leaf, None)
+    }
+
+    /// Whether leaf `a` wins its match against leaf `b`
+    fn beats(&self, a: usize, b: usize) -> bool {
+        match (&self.keys[a], &self.keys[b]) {
+            (Some(key_a), Some(key_b)) => (key_a, a) < (key_b, b),
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => a < b,
+        }
+    }
+
+    /// Replays the matches of `leaf` up to the root, it must have been the winner
+    fn replay(&mut self, leaf: usize) {
+        let mut winner = leaf;
+        let mut node = (self.keys.len() + leaf) / 2;
+        while node > 0 {
+            if self.beats(self.nodes[node], winner) {
+                mem::swap(&mut self.nodes[node], &mut winner);
+            }
+            node /= 2;
+        }
+
+        self.nodes[0] = winner;
+    }
+
+    /// Replays every match
+    fn rebuild(&mut self) {
+        let n = self.keys.len();
+        if n == 0 {
+            self.nodes.clear();
+            return;
+        }
+
+        // winner of each match, bottom up
+        let mut winners = vec![0; n];
+        self.nodes = vec![0; n];
+        for node in (1..n).rev() {
+            let [left, right] = [2 * node, 2 * node + 1].map(|child| {
+                if child >= n {
+                    child - n
+                } else {
+                    winners[child]
+                }
+            });
+
+            let (winner, loser) = if self.beats(left, right) {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            winners[node] = winner;
+            self.nodes[node] = loser;
+        }
+
+        self.nodes[0] = if n == 1 { 0 } else { winners[1] };
+    }
+}
+
+/// Merges sorted iterators into one sorted iterator
+///
+/// Equal items are taken from the iterators in the order they were given.
+pub fn merge<I>(iters: impl IntoIterator<Item = I>) -> Merge<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Ord,
+{
+    let mut iters: Vec<_> = iters.into_iter().map(IntoIterator::into_iter).collect();
+    let mut tree = LoserTree {
+        keys: iters.iter_mut().map(Iterator::next).collect(),
+        nodes: Vec::new(),
+    };
+    tree.rebuild();
+
+    Merge { iters, tree }
+}
+
+/// Iterator returned by [`merge`](fn.merge.html)
+#[derive(Debug, Clone)]
+pub struct Merge<I>
+where
+    I: Iterator,
+{
+    iters: Vec<I>,
+    tree: LoserTree<I::Item>,
+}
+
+impl<I> Iterator for Merge<I>
+where
+    I: Iterator,
+    I::Item: Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let leaf = self.tree.winner()?;
+        let next = self.iters[leaf].next();
+        self.tree.replace(leaf, next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree::*;
+
+    /// Least key along with its leaf, the hard way
+    fn least(keys: &[Option<u32>]) -> Option<(usize, u32)> {
+        keys.iter()
+            .enumerate()
+            .filter_map(|(leaf, key)| key.map(|key| (key, leaf)))
+            .min()
+            .map(|(key, leaf)| (leaf, key))
+    }
+
+    #[test]
+    fn test_winner() {
+        for n in 0..20 {
+            // scrambled keys with lots of ties
+            let mut keys: Vec<Option<u32>> = (0..n).map(|i| Some((i * 7 % 5) as u32)).collect();
+            let mut tree = LoserTree::new(keys.iter().map(|key| key.unwrap()).collect());
+            assert_eq!(tree.len(), n);
+
+            for round in 0..3 * n as u32 {
+                assert_eq!(
+                    tree.peek().map(|(l, &k)| (l, k)),
+                    least(&keys),
+                    "{} leaves",
+                    n
+                );
+
+                // the winner moves on, another leaf gets vacated or changes
+                let (leaf, key) = least(&keys).unwrap();
+                keys[leaf] = Some(key + 3);
+                tree.replace(leaf, keys[leaf]);
+
+                let other = (round as usize * 3) % n;
+                keys[other] = if round % 4 == 0 { None } else { Some(round) };
+                tree.replace(other, keys[other]);
+
+                if keys.iter().all(Option::is_none) {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_remove() {
+        let mut tree = LoserTree::new(vec![5, 3]);
+        assert_eq!(tree.winner(), Some(1));
+
+        assert_eq!(tree.push(Some(1)), 2);
+        assert_eq!(tree.winner(), Some(2));
+
+        assert_eq!(tree.remove(2), Some(1));
+        assert_eq!(tree.winner(), Some(1));
+        assert_eq!(tree.len(), 3);
+
+        // ties go to the lowest leaf
+        assert_eq!(tree.push(Some(3)), 3);
+        assert_eq!(tree.winner(), Some(1));
+
+        tree.remove(0);
+        tree.remove(1);
+        tree.remove(3);
+        assert_eq!(tree.winner(), None);
+        assert_eq!(tree.peek(), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let iters = vec![vec![(1, 'a'), (3, 'a')], vec![], vec![(1, 'c'), (2, 'c')]];
+        let merged: Vec<_> = merge(iters).collect();
+        assert_eq!(merged, vec![(1, 'a'), (1, 'c'), (2, 'c'), (3, 'a')]);
+
+        let merged: Vec<u8> = merge(Vec::<Vec<u8>>::new()).collect();
+        assert!(merged.is_empty());
+    }
+}
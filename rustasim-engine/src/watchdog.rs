@@ -0,0 +1,382 @@
+//! Notices when a run stops making progress, and tells what the `Merger`s are waiting on
+//!
+//! A forgotten null-message doesn't crash anything: the actors waiting on it stall at the same
+//! time over and over, and the run goes on forever without a word. Under a
+//! [`Watchdog`](struct.Watchdog.html), the workers note the time of each actor whenever they put
+//! it back, and a thread of its own looks at the least of them. If it didn't move for the given
+//! wall-clock time, every `Merger` describes itself the next time it stalls: its safe time, the
+//! input it pulls from next (`winner_q`), and the length and head of each of its inputs. The dump
+//! is then handed over, to stderr by default, and the watchdog keeps watching.
+
+use crate::failure::{self, ActorPanic};
+use crate::stats::WorkerStats;
+use crate::worker::ActorState;
+use crate::FrozenActor;
+use parking_lot::{Condvar, Mutex};
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the dumps are handed to
+type Sink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Watches a run for the least time of its actors to stop moving, see the module documentation
+#[derive(Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    sink: Sink,
+}
+
+impl Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Watchdog {
+    /// Dumps the state of the `Merger`s to stderr whenever no progress was made for `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        Watchdog {
+            timeout,
+            sink: Arc::new(|dump| eprintln!("{}", dump)),
+        }
+    }
+
+    /// Hands the dumps over to `sink` instead
+    pub fn sink(mut self, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    /// Starts watching a run of `n_actors`, on a thread of its own
+    pub(crate) fn watch<T>(&self, n_actors: usize) -> Watching<T>
+    where
+        T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    {
+        let watch = Arc::new(Watch {
+            times: (0..n_actors).map(|_| Mutex::new(Some(T::zero()))).collect(),
+            reports: (0..n_actors).map(|_| Mutex::new((0, Vec::new()))).collect(),
+            request: AtomicU64::new(0),
+            done: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+
+        let handle = {
+            let watch = Arc::clone(&watch);
+            let watchdog = self.clone();
+            thread::spawn(move || watchdog.run(&watch))
+        };
+
+        Watching { watch, handle }
+    }
+
+    /// Looks at the least time of the actors until the run is done, dumps whenever it's stuck
+    fn run<T>(&self, watch: &Watch<T>)
+    where
+        T: Ord + Copy + Debug,
+    {
+        let poll = (self.timeout / 10).min(Duration::from_millis(100));
+        let mut least = watch.least();
+        let mut since = Instant::now();
+        while !watch.sleep(poll) {
+            let now = watch.least();
+            if now != least {
+                least = now;
+                since = Instant::now();
+                continue;
+            }
+
+            let least = match least {
+                Some(least) if since.elapsed() >= self.timeout => least,
+                _ => continue,
+            };
+
+            // the actors report whenever they next stall, those stuck for good won't
+            let request = watch.request.fetch_add(1, Ordering::Relaxed) + 1;
+            let asked = Instant::now();
+            while !watch.has_reported(request) && asked.elapsed() < self.timeout {
+                if watch.sleep(poll) {
+                    return;
+                }
+            }
+
+            (self.sink)(&watch.dump(least, since.elapsed(), request));
+            since = Instant::now();
+        }
+    }
+}
+
+/// What the workers and the watchdog of a run share
+pub(crate) struct Watch<T> {
+    /// Time of each actor when it was last put back, `None` once it's done
+    times: Vec<Mutex<Option<T>>>,
+
+    /// What the `Merger`s of each actor said, and for which request
+    reports: Vec<Mutex<(u64, Vec<String>)>>,
+
+    /// Bumped for the actors to report again
+    request: AtomicU64,
+
+    /// The run is over, the watchdog gets woken up to return
+    done: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl<T: Ord + Copy + Debug> Watch<T> {
+    /// Least time of the actors still running
+    fn least(&self) -> Option<T> {
+        self.times.iter().filter_map(|time| *time.lock()).min()
+    }
+
+    /// Whether all the actors still running reported since `request`
+    fn has_reported(&self, request: u64) -> bool {
+        self.times
+            .iter()
+            .zip(&self.reports)
+            .all(|(time, report)| time.lock().is_none() || report.lock().0 >= request)
+    }
+
+    /// Sleeps for `duration`, returns whether the run is over
+    fn sleep(&self, duration: Duration) -> bool {
+        let mut done = self.done.lock();
+        if !*done {
+            self.wake.wait_for(&mut done, duration);
+        }
+        *done
+    }
+
+    /// Where each actor still running is at, and what its `Merger`s said since `request`
+    fn dump(&self, least: T, stuck: Duration, request: u64) -> String {
+        let mut dump = format!("No progress past time {:?} for {:?}", least, stuck);
+        for (ix, (time, report)) in self.times.iter().zip(&self.reports).enumerate() {
+            let time = match *time.lock() {
+                Some(time) => time,
+                None => continue,
+            };
+
+            let report = report.lock();
+            write!(dump, "\nActor at index {}, time {:?}", ix, time).unwrap();
+            if report.0 < request {
+                dump.push_str(", hasn't stalled since asked");
+            }
+            for line in &report.1 {
+                write!(dump, "\n  {}", line.replace('\n', "\n  ")).unwrap();
+            }
+        }
+
+        dump
+    }
+}
+
+/// A run being watched
+pub(crate) struct Watching<T> {
+    watch: Arc<Watch<T>>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl<T: 'static + Ord + Copy + Debug + Send> Watching<T> {
+    /// Has the workers of this thread note what their actors are doing, see `advance`
+    pub(crate) fn worker(&self) -> impl FnOnce() {
+        let watch = Arc::clone(&self.watch);
+        move || WATCH.with(|current| *current.borrow_mut() = Some(Box::new(watch)))
+    }
+
+    /// The run is over, waits for the watchdog to return
+    pub(crate) fn stop(self) {
+        *self.watch.done.lock() = true;
+        self.watch.wake.notify_all();
+        self.handle.join().unwrap();
+    }
+}
+
+thread_local! {
+    /// `Watch` of the run this worker is part of, if any
+    static WATCH: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+
+    /// What the `Merger`s of the actor being advanced said so far, when they were asked to
+    static REPORT: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Has the `Merger` describe itself, if the watchdog asked for it
+pub(crate) fn report(describe: impl FnOnce() -> String) {
+    REPORT.with(|report| {
+        if let Some(lines) = report.borrow_mut().as_mut() {
+            lines.push(describe());
+        }
+    })
+}
+
+/// Advances the actor like the worker does, noting its time and having its `Merger`s report when
+/// the run is watched
+pub(crate) fn advance<T, R>(
+    frozen_actor: &mut FrozenActor<T, R>,
+    worker: &mut WorkerStats,
+) -> Result<ActorState<T, R>, ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    let watch = WATCH.with(|watch| {
+        watch
+            .borrow()
+            .as_ref()
+            .and_then(|watch| watch.downcast_ref::<Arc<Watch<T>>>())
+            .cloned()
+    });
+    let watch = match watch {
+        Some(watch) => watch,
+        None => return failure::advance(frozen_actor, worker),
+    };
+
+    let ix = frozen_actor.ix;
+    let request = watch.request.load(Ordering::Relaxed);
+    let asked = watch.reports[ix].lock().0 < request;
+    if asked {
+        REPORT.with(|report| *report.borrow_mut() = Some(Vec::new()));
+    }
+
+    let state = failure::advance(frozen_actor, worker);
+    if asked {
+        let lines = REPORT.with(|report| report.borrow_mut().take()).unwrap();
+        *watch.reports[ix].lock() = (request, lines);
+    }
+    match state {
+        Ok(ActorState::Continue(time)) => *watch.times[ix].lock() = Some(time),
+        Ok(ActorState::Done(_)) => *watch.times[ix].lock() = None,
+        Err(_) => {}
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod test {
+    use crate::watchdog::*;
+    use crate::{start_watched, Advancer, Driver, Event, EventType, Merger, Partition, Scheduler};
+
+    /// Sends itself a tick every unit of time until `limit`, takes a nap at `nap_at`
+    ///
+    /// Ticks are also sent on its other outputs, and those it gets from the others are dropped.
+    #[derive(Debug)]
+    struct Ticker {
+        merger: Merger<u64, ()>,
+        limit: u64,
+        nap_at: Option<u64>,
+    }
+
+    impl Advancer<u64, ()> for Ticker {
+        fn advance(&mut self) -> ActorState<u64, ()> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::ModelEvent(()) if event.src != 0 => {}
+                    EventType::ModelEvent(()) if event.time < self.limit => {
+                        if self.nap_at == Some(event.time) {
+                            thread::sleep(Duration::from_millis(300));
+                        }
+                        for ix in 0..self.merger.n_outputs() {
+                            self.merger.send(
+                                ix,
+                                Event {
+                                    time: event.time + 1,
+                                    src: self.merger.id(),
+                                    event_type: EventType::ModelEvent(()),
+                                },
+                            );
+                        }
+                    }
+                    EventType::ModelEvent(()) => break,
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    _ => unreachable!(),
+                }
+            }
+
+            match self.merger.finish() {
+                None => ActorState::Done(()),
+                Some(time) => ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    /// Two tickers, the second also waiting on the first which naps at `nap_at`
+    ///
+    /// The second stops first, nothing tells it how far the first went once that is done.
+    fn actors(nap_at: Option<u64>) -> Vec<Box<dyn Advancer<u64, ()> + Send>> {
+        let (tx0, rx0) = crate::spsc::new(16);
+        let (tx1, rx1) = crate::spsc::new(16);
+        let (tx01, rx01) = crate::spsc::new(16);
+        for (tx, src) in [(&tx0, 0), (&tx1, 1)] {
+            tx.push(Event {
+                time: 1,
+                src,
+                event_type: EventType::ModelEvent(()),
+            })
+            .unwrap();
+        }
+
+        vec![
+            Box::new(Ticker {
+                merger: Merger::new(vec![rx0], 0, vec![0])
+                    .output(tx0, 1)
+                    .output(tx01, 1),
+                limit: 100,
+                nap_at,
+            }),
+            Box::new(Ticker {
+                merger: Merger::new(vec![rx1, rx01], 1, vec![1, 0]).output(tx1, 1),
+                limit: 90,
+                nap_at: None,
+            }),
+        ]
+    }
+
+    /// Runs the actors under a watchdog with `timeout`, returns the dumps
+    fn watched(scheduler: Scheduler, timeout: Duration, nap_at: Option<u64>) -> Vec<String> {
+        let dumps = Arc::new(Mutex::new(Vec::new()));
+        let watchdog = {
+            let dumps = Arc::clone(&dumps);
+            Watchdog::new(timeout).sink(move |dump| dumps.lock().push(dump.to_string()))
+        };
+
+        let partition = Partition::round_robin(2, 2);
+        let (results, _) =
+            start_watched(&watchdog, scheduler, &partition, actors(nap_at), None).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let dumps = dumps.lock().clone();
+        dumps
+    }
+
+    #[test]
+    fn test_dump() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            let dumps = watched(scheduler, Duration::from_millis(50), Some(50));
+            let dump = dumps.first().expect("Nothing was dumped");
+            assert!(dump.starts_with("No progress past time "), "{}", dump);
+            assert!(
+                dump.contains("Actor at index 0, time ")
+                    && dump.contains(", hasn't stalled since asked"),
+                "{}",
+                dump
+            );
+            assert!(dump.contains("Actor at index 1, time "), "{}", dump);
+            assert!(dump.contains("  Merger #1: safe time "), "{}", dump);
+            assert!(dump.contains("    input 1 from #0: 0 events"), "{}", dump);
+        }
+    }
+
+    #[test]
+    fn test_quiet() {
+        for scheduler in [Scheduler::Fifo, Scheduler::Priority] {
+            assert!(watched(scheduler, Duration::from_secs(10), None).is_empty());
+        }
+    }
+}
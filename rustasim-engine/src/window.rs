@@ -0,0 +1,464 @@
+//! Barrier-synchronized conservative executor
+//!
+//! This runs the exact same actors as [`start`](../fn.start.html), in windows of simulated time
+//! rather than with null-messages, the way YAWNS does. Between two windows every worker advances
+//! its actors once without letting them process anything, and their `Merger`s report the time of
+//! their earliest event, their floor, along with the least lookahead of their outputs. Whatever
+//! happens from now on happens at least the least lookahead after the earliest floor, so the next
+//! window goes up to that bound: every worker advances its actors through all their events below
+//! it, and waits for the others at a barrier.
+//!
+//! No null-message is ever needed, which pays off when there are many links with little
+//! lookahead, e.g. fully connected racks. It costs two barriers per window, and sends everything
+//! through the lowest lookahead of the whole simulation, so it doesn't do as well when that is
+//! much lower than the others. Every output must therefore be declared to the `Merger`, with a
+//! lookahead above zero.
+
+use crate::end;
+use crate::failure::{self, ActorPanic};
+use crate::stats::{Report, Stats, WorkerStats};
+use crate::worker::{ActorState, Advancer, Driver, Finished, Mode};
+use crate::{FrozenActor, Partition};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::{Arc, Barrier};
+use std::time::Instant;
+
+/// What the executor and the `Merger` of the actor being advanced tell each other
+struct Turn {
+    /// Whether the actor may process anything at all, the window is being set otherwise
+    deliver: bool,
+
+    /// Events from this time on wait for the next window, `None` for no bound
+    bound: Option<Box<dyn Any>>,
+
+    /// Time of the actor's earliest event, waiting on its inputs or for room on its outputs
+    floor: Option<Box<dyn Any>>,
+
+    /// Least lookahead of the actor's outputs
+    lookahead: Option<Box<dyn Any>>,
+}
+
+thread_local! {
+    static TURN: RefCell<Option<Turn>> = const { RefCell::new(None) };
+}
+
+/// Whether the actor being advanced may process an event at `time`
+pub(crate) fn may_deliver<T: 'static + Ord>(time: T) -> bool {
+    TURN.with(|turn| match turn.borrow().as_ref() {
+        Some(turn) if turn.deliver => turn.bound.as_ref().is_none_or(|bound| {
+            time < *bound
+                .downcast_ref::<T>()
+                .expect("Merger and actor disagree on the time type")
+        }),
+        _ => false,
+    })
+}
+
+/// Reports the floor and the least lookahead of the actor being advanced, `None` if it has none
+pub(crate) fn report<T: 'static>(floor: Option<T>, lookahead: Option<T>) {
+    TURN.with(|turn| {
+        if let Some(turn) = turn.borrow_mut().as_mut() {
+            turn.floor = floor.map(|time| Box::new(time) as Box<dyn Any>);
+            turn.lookahead = lookahead.map(|time| Box::new(time) as Box<dyn Any>);
+        }
+    })
+}
+
+/// Earliest floor and least lookahead, of an actor or of a few of them
+type Reported<T> = (Option<T>, Option<T>);
+
+/// What a worker found out about its actors between two windows: their earliest floor, least
+/// lookahead, how many of them are still running, and whether one of them panicked
+type Probe<T> = (Option<T>, Option<T>, usize, bool);
+
+/// Results and counters of the actors a worker finished, along with their index
+type Done<R> = (Vec<(usize, R)>, Vec<(usize, Stats)>);
+
+/// What the workers share
+struct Shared<T> {
+    barrier: Barrier,
+    probes: Mutex<Vec<Probe<T>>>,
+}
+
+/// Lowest of `a` and `b`, when there is any
+fn lowest<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    a.into_iter().chain(b).min()
+}
+
+/// Advances `frozen_actor` with the given window, returns its state and what its `Merger` reported
+fn advance<T, R>(
+    frozen_actor: &mut FrozenActor<T, R>,
+    window: Option<Option<T>>,
+    worker: &mut WorkerStats,
+) -> Result<(ActorState<T, R>, Reported<T>), ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    TURN.with(|turn| {
+        *turn.borrow_mut() = Some(Turn {
+            deliver: window.is_some(),
+            bound: window
+                .flatten()
+                .map(|bound| Box::new(bound) as Box<dyn Any>),
+            floor: None,
+            lookahead: None,
+        })
+    });
+    let state = failure::advance(frozen_actor, worker);
+    let turn = TURN.with(|turn| turn.borrow_mut().take()).unwrap();
+
+    let unbox = |time: Option<Box<dyn Any>>| {
+        time.map(|time| {
+            *time
+                .downcast::<T>()
+                .expect("Merger and actor disagree on the time type")
+        })
+    };
+    Ok((state?, (unbox(turn.floor), unbox(turn.lookahead))))
+}
+
+/// Advances each of the `running` actors once with the given window, keeps those that aren't
+/// done and returns their earliest floor and least lookahead
+fn advance_all<T, R>(
+    running: &mut Vec<FrozenActor<T, R>>,
+    window: Option<Option<T>>,
+    worker: &mut WorkerStats,
+    finished: &mut Done<R>,
+) -> Result<Reported<T>, ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    let mut still_running = Vec::with_capacity(running.len());
+    let (mut floor, mut lookahead) = (None, None);
+    for mut frozen_actor in running.drain(..) {
+        let (state, (actor_floor, actor_lookahead)) = advance(&mut frozen_actor, window, worker)?;
+        match state {
+            ActorState::Continue(time) => {
+                frozen_actor.time = time;
+                floor = lowest(floor, actor_floor);
+                lookahead = lowest(lookahead, actor_lookahead);
+                still_running.push(frozen_actor);
+            }
+            ActorState::Done(count) => {
+                finished.0.push((frozen_actor.ix, count));
+                finished.1.push((frozen_actor.ix, frozen_actor.stats));
+            }
+        }
+    }
+
+    *running = still_running;
+    Ok((floor, lookahead))
+}
+
+/// Runs the actors of a group window after window, until they are all done
+///
+/// If an actor panics, its worker drops the others and still goes through the next probe, for
+/// everyone to stop at once.
+fn run<T, R>(
+    actors: Vec<FrozenActor<T, R>>,
+    group: usize,
+    shared: Arc<Shared<T>>,
+) -> Result<Finished<R>, ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + num::Zero,
+{
+    let mut finished = (Vec::new(), Vec::new());
+    let mut worker = WorkerStats::default();
+    let started = Instant::now();
+
+    // whatever was left of the actors goes with the panic
+    let mut panicked = None;
+
+    let mut running = actors;
+    let mut window = None;
+    loop {
+        // everything sent in the window must be on the inputs before anyone probes
+        if window.is_some() {
+            if let Err(panic) = advance_all(&mut running, window, &mut worker, &mut finished) {
+                panicked = Some(panic);
+            }
+            shared.barrier.wait();
+        }
+
+        let (floor, lookahead) = advance_all(&mut running, None, &mut worker, &mut finished)
+            .unwrap_or_else(|panic| {
+                panicked = Some(panic);
+                (None, None)
+            });
+        shared.probes.lock()[group] = (floor, lookahead, running.len(), panicked.is_some());
+        shared.barrier.wait();
+
+        // nobody writes their probe again before everyone went through the window
+        let (floor, lookahead, n_running, aborted) = shared.probes.lock().iter().fold(
+            (None, None, 0, false),
+            |(floor, lookahead, n_running, aborted), &(f, l, n, a)| {
+                (
+                    lowest(floor, f),
+                    lowest(lookahead, l),
+                    n_running + n,
+                    aborted || a,
+                )
+            },
+        );
+        if aborted {
+            break;
+        }
+        let floor = match floor {
+            Some(floor) => floor,
+            None => {
+                assert_eq!(
+                    n_running, 0,
+                    "{} actors have nothing left to do but never finished",
+                    n_running
+                );
+                break;
+            }
+        };
+        window = Some(lookahead.map(|lookahead| {
+            assert!(
+                !lookahead.is_zero(),
+                "Windows need all the outputs to have some lookahead"
+            );
+            floor + lookahead
+        }));
+    }
+
+    if let Some(panic) = panicked {
+        return Err(panic);
+    }
+
+    worker.idle = started.elapsed().saturating_sub(worker.stats.busy);
+    let (counts, actor_stats) = finished;
+    Ok((counts, actor_stats, worker))
+}
+
+/// Runs the actors in windows of simulated time, on a worker per group of `partition`, until
+/// `end` if given
+///
+/// Each worker keeps the actors of its group for the whole run. Actors are given in the order
+/// they were numbered by the partition, and must give the hand back when they get a `Stalled`
+/// event, as they do for [`start`](fn.start.html). Their `Merger`s don't send null-messages,
+/// actors may still do so by hand, they are dropped on arrival. The time the workers spend
+/// waiting for each other counts as idle.
+///
+/// If an actor panics, the workers stop at the end of the window and the panic comes back as an
+/// [`ActorPanic`](struct.ActorPanic.html) instead.
+///
+/// # Panics
+///
+/// Panics if the partition isn't of as many actors as were given, if an output has no lookahead,
+/// or if some actors never finish because none of them has anything left to do.
+pub fn start_windowed<T, R>(
+    partition: &Partition,
+    actors: Vec<Box<dyn Advancer<T, R> + Send>>,
+    end: Option<T>,
+) -> Result<(Vec<R>, Report), ActorPanic>
+where
+    T: 'static + Ord + Copy + Debug + Send + num::Zero,
+    R: 'static + Send,
+{
+    assert_eq!(
+        partition.n_actors(),
+        actors.len(),
+        "The partition must be of as many actors as were given"
+    );
+
+    let n_actors = actors.len();
+    let n_groups = partition.n_groups();
+    let mut groups: Vec<Vec<FrozenActor<T, R>>> = (0..n_groups).map(|_| Vec::new()).collect();
+    for (ix, mut actor) in actors.into_iter().enumerate() {
+        actor.driven_by(Driver::new(Mode::Window, Some(ix)));
+        let group = partition.group(ix);
+        groups[group].push(FrozenActor {
+            time: T::zero(),
+            actor,
+            turn: 0,
+            ix,
+            stats: Stats::default(),
+            group,
+        });
+    }
+
+    let shared = Arc::new(Shared {
+        barrier: Barrier::new(n_groups),
+        probes: Mutex::new(vec![(None, None, 0, false); n_groups]),
+    });
+    let handles = groups
+        .into_iter()
+        .enumerate()
+        .map(|(i, actors)| {
+            let shared = Arc::clone(&shared);
+            crate::spawn(i, Some(partition), move || {
+                end::with(end, || run(actors, i, shared))
+            })
+        })
+        .collect();
+
+    crate::collect(n_actors, handles)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::window::*;
+    use crate::{actor_rng, spsc, start_sequential, ActorRng, Event, EventType, Merger};
+    use rand::Rng;
+
+    const LOOKAHEAD: u64 = 3;
+
+    /// PHOLD-like actor sending through its `Merger`, returns a checksum of the events it
+    /// processed, in order
+    #[derive(Debug)]
+    struct Mesh {
+        id: usize,
+        limit: u64,
+        merger: Merger<u64, u64>,
+        out_times: Vec<u64>,
+
+        rng: ActorRng,
+        checksum: u64,
+    }
+
+    impl Advancer<u64, u64> for Mesh {
+        fn advance(&mut self) -> ActorState<u64, u64> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::ModelEvent(hops) if event.time <= self.limit => {
+                        self.checksum = self
+                            .checksum
+                            .wrapping_mul(31)
+                            .wrapping_add(event.time * 7 + hops);
+
+                        // a few at once, for the links to fill up
+                        for _ in 0..self.rng.gen_range(1, 3) {
+                            let dst = self.rng.gen_range(0, self.out_times.len());
+                            self.out_times[dst] = std::cmp::max(self.out_times[dst], event.time)
+                                + self.rng.gen_range(0, 20);
+                            let event = Event {
+                                time: self.out_times[dst] + LOOKAHEAD,
+                                src: self.id,
+                                event_type: EventType::ModelEvent(hops + 1),
+                            };
+                            self.merger.send(dst, event);
+                        }
+                    }
+                    EventType::ModelEvent(_) => {
+                        for dst in 0..self.merger.n_outputs() {
+                            let close = Event {
+                                time: event.time + LOOKAHEAD,
+                                src: self.id,
+                                event_type: EventType::Close,
+                            };
+                            self.merger.send(dst, close);
+                        }
+                        break;
+                    }
+                    EventType::Close => break,
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    EventType::Null => unreachable!(),
+                }
+            }
+
+            match self.merger.finish() {
+                None => ActorState::Done(self.checksum),
+                Some(time) => ActorState::Continue(time),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    fn mesh(
+        n_actors: usize,
+        limit: u64,
+        capacity: usize,
+    ) -> Vec<Box<dyn Advancer<u64, u64> + Send>> {
+        let mut outs: Vec<Vec<_>> = (0..n_actors).map(|_| Vec::new()).collect();
+        let mut ins: Vec<Vec<_>> = (0..n_actors).map(|_| Vec::new()).collect();
+        for src_outs in outs.iter_mut() {
+            for dst_ins in ins.iter_mut() {
+                let (prod, cons) = spsc::new(capacity);
+                src_outs.push(prod);
+                dst_ins.push(cons);
+            }
+        }
+
+        // a few tokens to start
+        for (id, src_outs) in outs.iter().enumerate() {
+            src_outs[id]
+                .push(Event {
+                    time: 1 + id as u64 % 3,
+                    src: id,
+                    event_type: EventType::ModelEvent(0),
+                })
+                .unwrap();
+        }
+
+        outs.into_iter()
+            .zip(ins)
+            .enumerate()
+            .map(|(id, (out_queues, in_queues))| {
+                let merger = out_queues.into_iter().fold(
+                    Merger::new(in_queues, id, (0..n_actors).collect()),
+                    |merger, queue| merger.output(queue, LOOKAHEAD),
+                );
+                let actor = Mesh {
+                    id,
+                    limit,
+                    merger,
+                    out_times: vec![0; n_actors],
+                    rng: actor_rng(0, id),
+                    checksum: 0,
+                };
+                Box::new(actor) as Box<dyn Advancer<u64, u64> + Send>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        for &capacity in &[2, 1024] {
+            let partition = Partition::round_robin(9, 3);
+            let (mut windowed, report) =
+                start_windowed(&partition, mesh(9, 2_000, capacity), None).unwrap();
+            let mut sequential = start_sequential(mesh(9, 2_000, capacity), None);
+
+            windowed.sort_unstable();
+            sequential.sort_unstable();
+            assert_eq!(windowed, sequential, "capacity {}", capacity);
+            assert!(sequential.iter().all(|&checksum| checksum > 0));
+
+            let total = report.total();
+            assert!(total.events > 0);
+            assert_eq!(total.nulls_sent, 0);
+            assert_eq!(report.workers.len(), 3);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "lookahead")]
+    fn test_no_lookahead() {
+        let (prod, cons) = spsc::new(2);
+        prod.push(Event {
+            time: 1,
+            src: 0,
+            event_type: EventType::ModelEvent(0),
+        })
+        .unwrap();
+        let merger = Merger::new(vec![cons], 0, vec![0]).output(prod, 0);
+        let actor = Mesh {
+            id: 0,
+            limit: 10,
+            merger,
+            out_times: vec![0],
+            rng: actor_rng(0, 0),
+            checksum: 0,
+        };
+
+        let _ = start_windowed(&Partition::round_robin(1, 1), vec![Box::new(actor)], None);
+    }
+}
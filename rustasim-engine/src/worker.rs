@@ -14,18 +14,98 @@
 //! any more progress, and can be called repeatedly. This module can take these "advanceables"
 //! (trait?) and schedule them via crossbeam's work-stealing queue (insert link).
 
+use crate::failure::{ActorPanic, Delivered};
+use crate::stats::{self, Stats, WorkerStats};
+use crate::watchdog;
 use crate::FrozenActor;
 use atomic_counter::{AtomicCounter, RelaxedCounter};
-use parking_lot::Mutex;
+use crossbeam_deque::{Steal, Stealer, Worker};
+use crossbeam_utils::Backoff;
+use parking_lot::{Condvar, Mutex};
+use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
-use std::collections::VecDeque;
+use rand::Rng;
+use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::Debug;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Convenience wrapper for a reference counted, distributed heap of frozen actors...
 pub type LockedTaskHeap<T, R> = Arc<Mutex<VecDeque<FrozenActor<T, R>>>>;
 
+/// What a worker returns: the results and counters of the actors it finished, along with their
+/// index, and its own counters
+pub type Finished<R> = (Vec<(usize, R)>, Vec<(usize, Stats)>, WorkerStats);
+
+/// How long a worker whose actors all got nowhere sleeps at most when nobody else gets anywhere
+///
+/// What the network bridges push comes from outside the workers and doesn't wake them up.
+const STUCK: Duration = Duration::from_millis(1);
+
+/// How the workers pick the next actor to advance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduler {
+    /// Actors wait in a few shared FIFO queues, workers pick a queue at random
+    #[default]
+    Fifo,
+
+    /// Each worker advances its lowest-timestamp actor first, workers that run out of actors
+    /// steal from the others
+    Priority,
+}
+
+/// Which executor runs an actor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// A worker of [`start`](fn.start.html), with null-messages
+    Conservative,
+
+    /// The [sequential executor](fn.start_sequential.html)
+    Sequential,
+
+    /// The [window executor](fn.start_windowed.html)
+    Window,
+
+    /// The [optimistic executor](fn.start_optimistic.html)
+    Optimistic,
+}
+
+/// How an actor is run, handed to its `Merger`s by [`driven_by`](trait.Advancer.html#tymethod.driven_by)
+///
+/// The executors give one to each actor before advancing it for the first time. A `Merger` that
+/// wasn't given any assumes it is run by a worker of [`start`](fn.start.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Driver {
+    mode: Mode,
+
+    /// Slot of the actor in the sequential or window executor
+    slot: Option<usize>,
+}
+
+impl Default for Driver {
+    fn default() -> Self {
+        Driver::new(Mode::Conservative, None)
+    }
+}
+
+impl Driver {
+    pub(crate) fn new(mode: Mode, slot: Option<usize>) -> Self {
+        Driver { mode, slot }
+    }
+
+    /// Which executor runs the actor
+    pub(crate) fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Slot of the actor in the sequential or window executor
+    pub(crate) fn slot(&self) -> Option<usize> {
+        self.slot
+    }
+}
+
 /// Return value for actors to use to signal their state to the workers
 #[derive(Debug)]
 pub enum ActorState<T, R>
@@ -51,49 +131,480 @@ where
     /// The return value indicates whether it should get rescheduled or no. `true` reschedules,
     /// `false` assumes it is done.
     fn advance(&mut self) -> ActorState<T, R>;
+
+    /// Tells the actor how it is run, before it is first advanced
+    ///
+    /// Actors pass it on to each of their `Merger`s with
+    /// [`Merger::driven_by`](struct.Merger.html#method.driven_by), those without one have
+    /// nothing to do.
+    fn driven_by(&mut self, driver: Driver);
+
+    /// Last event the actor got, only asked for once it panicked to tell what it was doing
+    ///
+    /// Actors pass on what their `Merger` says with
+    /// [`Merger::last_delivered`](struct.Merger.html#method.last_delivered). Nothing by default.
+    fn last_delivered(&self) -> Option<Delivered<T>> {
+        None
+    }
+
+    /// Called once the actor is done, be it on its own or at the end of the run, to wrap up
+    ///
+    /// Its result is already in by then, this is for what it reports elsewhere. Does nothing by
+    /// default.
+    fn finish(&mut self) {}
+}
+
+/// Lets idle workers sleep until there might be something for them to do
+///
+/// This is an eventcount: a worker about to sleep first takes a ticket, checks one last time
+/// whether there is work, and then waits for the ticket to expire. Anything notified after the
+/// ticket was taken wakes it up, so work made available in the meantime can't be missed.
+///
+/// It also counts the advances that got somewhere, so a worker whose actors all got nowhere can
+/// sleep until someone else's did, and tells the workers when the run is aborted, after which
+/// they don't advance anything.
+#[derive(Debug, Default)]
+pub struct Parking {
+    n_sleeping: AtomicUsize,
+    epoch: Mutex<u64>,
+    wake: Condvar,
+    progress: AtomicU64,
+    aborted: AtomicBool,
 }
 
-/// Runs until no more progress can be made at all...
-pub fn run<T: Ord + Copy + Debug + num::Zero, R: Send>(
+impl Parking {
+    /// Announces that we are about to sleep, look for work once more before `wait`ing
+    pub fn prepare(&self) -> u64 {
+        self.n_sleeping.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        *self.epoch.lock()
+    }
+
+    /// Found something to do after all
+    pub fn cancel(&self) {
+        self.n_sleeping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Sleeps until something gets notified after `ticket` was taken
+    pub fn wait(&self, ticket: u64) {
+        let mut epoch = self.epoch.lock();
+        while *epoch == ticket {
+            self.wake.wait(&mut epoch);
+        }
+        drop(epoch);
+
+        self.n_sleeping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Same as `wait`, but gives up after `timeout`
+    pub fn wait_timeout(&self, ticket: u64, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut epoch = self.epoch.lock();
+        while *epoch == ticket {
+            if self.wake.wait_until(&mut epoch, deadline).timed_out() {
+                break;
+            }
+        }
+        drop(epoch);
+
+        self.n_sleeping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Notes that an advance got somewhere and wakes up the workers that gave up, returns the
+    /// new `progress`
+    pub fn progressed(&self) -> u64 {
+        let progress = self.progress.fetch_add(1, Ordering::SeqCst) + 1;
+        self.notify_all();
+        progress
+    }
+
+    /// Number of advances that got somewhere so far, look again after `prepare` before `wait`ing
+    pub fn progress(&self) -> u64 {
+        self.progress.load(Ordering::SeqCst)
+    }
+
+    /// Wakes a sleeping worker up, if any
+    pub fn notify_one(&self) {
+        if self.is_anyone_sleeping() {
+            *self.epoch.lock() += 1;
+            self.wake.notify_one();
+        }
+    }
+
+    /// Wakes all the sleeping workers up
+    pub fn notify_all(&self) {
+        if self.is_anyone_sleeping() {
+            *self.epoch.lock() += 1;
+            self.wake.notify_all();
+        }
+    }
+
+    /// Has all the workers stop, the sleeping ones are woken up
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.notify_all();
+    }
+
+    /// Whether the workers should stop, look again after `prepare` before `wait`ing
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    fn is_anyone_sleeping(&self) -> bool {
+        // orders whatever work we made available before the check, see `prepare`
+        fence(Ordering::SeqCst);
+        self.n_sleeping.load(Ordering::SeqCst) > 0
+    }
+}
+
+/// Advances the actor, counting the call and what its `Merger`s handed over
+pub(crate) fn advance<T, R>(
+    frozen_actor: &mut FrozenActor<T, R>,
+    worker: &mut WorkerStats,
+) -> ActorState<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    let started = Instant::now();
+    let state = frozen_actor.actor.advance();
+    if let ActorState::Done(_) = state {
+        frozen_actor.actor.finish();
+    }
+
+    let mut counted = stats::take();
+    counted.advances = 1;
+    counted.busy = started.elapsed();
+    frozen_actor.stats += counted;
+    worker.stats += counted;
+
+    state
+}
+
+/// Whether an advance that took the counters of an actor from `before` to `after` got somewhere
+///
+/// It did if the actor got events or pushed some, null-messages included, anything else only
+/// moved what it already had around.
+fn got_somewhere(before: &Stats, after: &Stats) -> bool {
+    after.events > before.events || after.sent > before.sent || after.nulls_sent > before.nulls_sent
+}
+
+/// Runs until all the actors are done, or one of them panics
+///
+/// Workers that can't find any actor to advance sleep until another worker puts one back in the
+/// heaps. Those that advanced as many actors as there are left in a row without getting anywhere
+/// sleep until some other advance gets somewhere. The worker finishing the last actor wakes
+/// everyone up so they can return, as does the one whose actor panicked.
+///
+/// Without a `home`, the worker picks heaps at random. With one, it takes from that heap first,
+/// and puts the actors back in the heap of their own group, see
+/// [`Partition`](../struct.Partition.html).
+pub fn run<T: 'static + Ord + Copy + Debug + num::Zero, R: Send>(
     _id: usize,
     counter: Arc<RelaxedCounter>,
     n_tasks: usize,
     task_heap: Vec<LockedTaskHeap<T, R>>,
-) -> Vec<R> {
+    parking: Arc<Parking>,
+    home: Option<usize>,
+) -> Result<Finished<R>, ActorPanic> {
     let mut counts = Vec::new();
+    let mut actor_stats = Vec::new();
+    let mut worker = WorkerStats::default();
+    let started = Instant::now();
 
     // rng
     let mut rng = thread_rng();
 
+    // our own heap first, then anyone's
+    let pick = |rng: &mut ThreadRng| {
+        let own = home.and_then(|home| task_heap[home].lock().pop_front());
+        own.or_else(|| task_heap.choose(rng).unwrap().lock().pop_front())
+    };
+
+    // advances in a row that got nowhere, and the progress when the first of them started
+    let mut nowhere = 0;
+    let mut since = parking.progress();
+
     // initial task
-    let mut task = task_heap.choose(&mut rng).unwrap().lock().pop_front();
+    let mut task = pick(&mut rng);
     loop {
+        if parking.is_aborted() {
+            return Ok((counts, actor_stats, worker));
+        }
+
         if let Some(mut frozen_actor) = task {
-            match frozen_actor.actor.advance() {
+            let before = frozen_actor.stats;
+            let state = watchdog::advance(&mut frozen_actor, &mut worker).inspect_err(|_| {
+                parking.abort();
+            })?;
+            match state {
                 ActorState::Continue(time) => {
+                    if time != frozen_actor.time || got_somewhere(&before, &frozen_actor.stats) {
+                        nowhere = 0;
+                        since = parking.progressed();
+                    } else {
+                        nowhere += 1;
+                    }
                     frozen_actor.time = time;
-                    let mut heap = task_heap.choose(&mut rng).unwrap().lock();
+                    let heap_ix = match home {
+                        Some(_) => frozen_actor.group,
+                        None => rng.gen_range(0, task_heap.len()),
+                    };
+                    let mut heap = task_heap[heap_ix].lock();
                     heap.push_back(frozen_actor);
-                    task = heap.pop_front();
+
+                    // someone else's actor goes back to them, and we go back to ours
+                    let ours = home.is_none_or(|home| home == heap_ix);
+                    task = if ours { heap.pop_front() } else { None };
+
+                    // someone else can run what's left
+                    let notify = !heap.is_empty();
+                    drop(heap);
+                    if notify {
+                        parking.notify_one();
+                    }
+                    if !ours {
+                        task = pick(&mut rng);
+                    }
+
+                    // nothing changed since we last got anywhere, wait until something does
+                    if nowhere >= n_tasks - counter.get() {
+                        let ticket = parking.prepare();
+                        if parking.progress() != since
+                            || counter.get() == n_tasks
+                            || parking.is_aborted()
+                        {
+                            parking.cancel();
+                        } else {
+                            parking.wait_timeout(ticket, STUCK);
+                        }
+                        nowhere = 0;
+                        since = parking.progress();
+                    }
                 }
                 ActorState::Done(count) => {
-                    counts.push(count);
+                    counts.push((frozen_actor.ix, count));
+                    actor_stats.push((frozen_actor.ix, frozen_actor.stats));
                     counter.inc();
-                    task = task_heap.choose(&mut rng).unwrap().lock().pop_front();
+                    if counter.get() == n_tasks {
+                        parking.notify_all();
+                    }
+                    nowhere = 0;
+                    since = parking.progressed();
+                    task = pick(&mut rng);
                 }
             }
         } else if counter.get() == n_tasks {
-            return counts;
+            worker.idle = started.elapsed().saturating_sub(worker.stats.busy);
+            return Ok((counts, actor_stats, worker));
         } else {
-            //println!("huh");
-            task = task_heap.choose(&mut rng).unwrap().lock().pop_front();
+            task = pick(&mut rng);
+            if task.is_none() {
+                // look everywhere before going to sleep
+                let ticket = parking.prepare();
+                task = task_heap.iter().find_map(|heap| heap.lock().pop_front());
+                if task.is_some() || counter.get() == n_tasks || parking.is_aborted() {
+                    parking.cancel();
+                } else {
+                    parking.wait(ticket);
+                }
+            }
+        }
+    }
+}
+
+/// What the workers of the priority scheduler share
+pub(crate) struct Pool<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// The other end of each worker's deque of actors up for grabs
+    pub(crate) stealers: Vec<Stealer<FrozenActor<T, R>>>,
+
+    /// Number of workers that ran out of actors
+    pub(crate) n_hungry: AtomicUsize,
+
+    pub(crate) n_done: RelaxedCounter,
+    pub(crate) n_tasks: usize,
+
+    pub(crate) parking: Parking,
+}
+
+impl<T, R> Pool<T, R>
+where
+    T: Ord + Copy + num::Zero,
+{
+    /// Steals from anyone but `id`, `None` if there was nothing to steal
+    fn steal_any(&self, id: usize, dest: &Worker<FrozenActor<T, R>>) -> Option<FrozenActor<T, R>> {
+        for (victim, stealer) in self.stealers.iter().enumerate() {
+            if victim == id {
+                continue;
+            }
+
+            loop {
+                match stealer.steal_batch_and_pop(dest) {
+                    Steal::Success(frozen_actor) => return Some(frozen_actor),
+                    Steal::Empty => break,
+                    Steal::Retry => {}
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Runs the lowest-timestamp actor first until all actors are done
+///
+/// The worker keeps its actors in a private heap. When some other worker is out of actors, it
+/// moves its least urgent half to `shared` where they can be stolen, and takes back whatever is
+/// left once its heap is empty. Workers that fail to steal for a while go to sleep until actors
+/// are up for grabs again.
+///
+/// Actors whose advance got nowhere are set aside until one gets somewhere, so the earliest one
+/// can't keep the hand while what it waits for is stuck behind it. Once all its actors are set
+/// aside, the worker sleeps until some other advance gets somewhere.
+pub(crate) fn run_priority<T: 'static + Ord + Copy + Debug + num::Zero, R: Send>(
+    id: usize,
+    pool: Arc<Pool<T, R>>,
+    shared: Worker<FrozenActor<T, R>>,
+) -> Result<Finished<R>, ActorPanic> {
+    let mut results = Vec::new();
+    let mut actor_stats = Vec::new();
+    let mut worker = WorkerStats::default();
+    let started = Instant::now();
+    let mut heap = BinaryHeap::new();
+    let mut hungry = false;
+
+    let mut rng = thread_rng();
+    let backoff = Backoff::new();
+    let mut turn = 0;
+
+    // actors that got nowhere since we last got anywhere, and the progress back then
+    let mut stuck = Vec::new();
+    let mut since = pool.parking.progress();
+
+    loop {
+        if pool.parking.is_aborted() {
+            break;
+        }
+
+        // nobody took them
+        if heap.is_empty() {
+            while let Some(frozen_actor) = shared.pop() {
+                heap.push(frozen_actor);
+            }
+        }
+
+        // nothing changed since we last got anywhere, wait until something does
+        if heap.is_empty() && !stuck.is_empty() {
+            let ticket = pool.parking.prepare();
+            if pool.parking.progress() != since || pool.parking.is_aborted() {
+                pool.parking.cancel();
+            } else {
+                pool.parking.wait_timeout(ticket, STUCK);
+            }
+            heap.extend(stuck.drain(..));
+            since = pool.parking.progress();
+        }
+
+        if heap.is_empty() {
+            if pool.n_done.get() == pool.n_tasks {
+                break;
+            }
+
+            if !hungry {
+                hungry = true;
+                pool.n_hungry.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let victim = rng.gen_range(0, pool.stealers.len());
+            if victim != id {
+                if let Steal::Success(frozen_actor) =
+                    pool.stealers[victim].steal_batch_and_pop(&shared)
+                {
+                    heap.push(frozen_actor);
+                    continue;
+                }
+            }
+
+            if !backoff.is_completed() {
+                backoff.snooze();
+                continue;
+            }
+
+            // look everywhere before going to sleep
+            let ticket = pool.parking.prepare();
+            if let Some(frozen_actor) = pool.steal_any(id, &shared) {
+                pool.parking.cancel();
+                heap.push(frozen_actor);
+            } else if pool.n_done.get() == pool.n_tasks || pool.parking.is_aborted() {
+                pool.parking.cancel();
+            } else {
+                pool.parking.wait(ticket);
+                backoff.reset();
+            }
+            continue;
+        }
+
+        if hungry {
+            hungry = false;
+            pool.n_hungry.fetch_sub(1, Ordering::Relaxed);
+            backoff.reset();
+        }
+
+        // someone is starving, share our least urgent actors
+        if heap.len() > 1 && shared.is_empty() && pool.n_hungry.load(Ordering::Relaxed) > 0 {
+            // latest first
+            let mut actors = std::mem::take(&mut heap).into_sorted_vec();
+            heap = actors.split_off(actors.len() / 2).into();
+            for frozen_actor in actors {
+                shared.push(frozen_actor);
+            }
+            pool.parking.notify_all();
+        }
+
+        let mut frozen_actor = heap.pop().unwrap();
+        let before = frozen_actor.stats;
+        let state = watchdog::advance(&mut frozen_actor, &mut worker).inspect_err(|_| {
+            pool.parking.abort();
+        })?;
+        match state {
+            ActorState::Continue(time) => {
+                let progressed =
+                    time != frozen_actor.time || got_somewhere(&before, &frozen_actor.stats);
+                turn += 1;
+                frozen_actor.time = time;
+                frozen_actor.turn = turn;
+                if progressed {
+                    heap.push(frozen_actor);
+                    heap.extend(stuck.drain(..));
+                    since = pool.parking.progressed();
+                } else {
+                    stuck.push(frozen_actor);
+                }
+            }
+            ActorState::Done(result) => {
+                results.push((frozen_actor.ix, result));
+                actor_stats.push((frozen_actor.ix, frozen_actor.stats));
+                pool.n_done.inc();
+                if pool.n_done.get() == pool.n_tasks {
+                    pool.parking.notify_all();
+                }
+                heap.extend(stuck.drain(..));
+                since = pool.parking.progressed();
+            }
         }
     }
+
+    worker.idle = started.elapsed().saturating_sub(worker.stats.busy);
+    Ok((results, actor_stats, worker))
 }
 
 #[cfg(test)]
 mod test {
+    use crate::fixtures::ring;
     use crate::worker::*;
+    use crate::{start_sequential, start_with, Event, EventType};
 
     #[derive(Debug)]
     struct DummyAdvance {
@@ -128,27 +639,265 @@ mod test {
                 ActorState::Done(())
             }
         }
+
+        fn driven_by(&mut self, _: Driver) {}
     }
 
     #[test]
     fn test_advance() {
         let dummy = &mut DummyAdvance::new(0, 3);
-        if let ActorState::Continue(_) = dummy.advance() {
-        } else {
-            assert!(false);
+        if let ActorState::Done(_) = dummy.advance() {
+            panic!("Dummy should not be done yet");
+        }
+
+        if let ActorState::Done(_) = dummy.advance() {
+            panic!("Dummy should not be done yet");
         }
 
         if let ActorState::Continue(_) = dummy.advance() {
-        } else {
-            assert!(false);
+            panic!("Dummy should be done");
         }
+    }
 
-        if let ActorState::Done(_) = dummy.advance() {
-        } else {
-            assert!(false);
+    #[test]
+    fn test_parking() {
+        let parking = Arc::new(Parking::default());
+
+        // notified between prepare and wait, doesn't sleep
+        let ticket = parking.prepare();
+        parking.notify_one();
+        parking.wait(ticket);
+
+        // woken up by another thread
+        let ticket = parking.prepare();
+        let waker = {
+            let parking = Arc::clone(&parking);
+            std::thread::spawn(move || parking.notify_all())
+        };
+        parking.wait(ticket);
+        waker.join().unwrap();
+
+        // nobody is sleeping anymore
+        assert_eq!(parking.n_sleeping.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_more_workers_than_actors() {
+        for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+            let actors: Vec<Box<dyn Advancer<u64, ()> + Send>> = (0..3)
+                .map(|id| Box::new(DummyAdvance::new(id, 200)) as Box<dyn Advancer<u64, ()> + Send>)
+                .collect();
+
+            let (results, report) = crate::start_with(scheduler, 8, actors, None).unwrap();
+            assert_eq!(results.len(), 3);
+            assert_eq!(report.workers.len(), 8);
+            assert!(report.actors.iter().all(|stats| stats.advances == 200));
         }
     }
 
+    #[test]
+    fn test_partitioned() {
+        for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+            let actors: Vec<Box<dyn Advancer<u64, ()> + Send>> = (0..6)
+                .map(|id| Box::new(DummyAdvance::new(id, 100)) as Box<dyn Advancer<u64, ()> + Send>)
+                .collect();
+            let partition =
+                crate::Partition::from_links(6, 2, vec![(0, 1), (2, 3), (4, 5)]).pinned(true);
+
+            let (results, report) =
+                crate::start_partitioned(scheduler, &partition, actors, None).unwrap();
+            assert_eq!(results.len(), 6);
+            assert_eq!(report.workers.len(), 2);
+            assert!(report.actors.iter().all(|stats| stats.advances == 100));
+        }
+    }
+
+    /// Advances `left` times, and then returns its name
+    #[derive(Debug)]
+    struct Named {
+        name: String,
+        left: u64,
+    }
+
+    impl Advancer<u64, String> for Named {
+        fn advance(&mut self) -> ActorState<u64, String> {
+            if self.left == 0 {
+                return ActorState::Done(self.name.clone());
+            }
+
+            self.left -= 1;
+            ActorState::Continue(self.left)
+        }
+
+        fn driven_by(&mut self, _: Driver) {}
+    }
+
+    #[test]
+    fn test_results_by_index() {
+        // the last ones finish first
+        let actors = || -> Vec<Box<dyn Advancer<u64, String> + Send>> {
+            (0..10)
+                .map(|ix| {
+                    let actor = Named {
+                        name: format!("actor {}", ix),
+                        left: 100 * (10 - ix),
+                    };
+                    Box::new(actor) as Box<_>
+                })
+                .collect()
+        };
+        let expected: Vec<_> = (0..10).map(|ix| format!("actor {}", ix)).collect();
+
+        let partition = crate::Partition::round_robin(10, 3);
+        for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+            let (results, _) = crate::start_with(scheduler, 3, actors(), None).unwrap();
+            assert_eq!(results, expected);
+
+            let (results, _) =
+                crate::start_partitioned(scheduler, &partition, actors(), None).unwrap();
+            assert_eq!(results, expected);
+        }
+    }
+
+    /// Ticks on its own until it panics at `panic_at`
+    #[derive(Debug)]
+    struct Ticker {
+        merger: crate::Merger<u64, ()>,
+        panic_at: u64,
+    }
+
+    impl Advancer<u64, ()> for Ticker {
+        fn advance(&mut self) -> ActorState<u64, ()> {
+            while let Some(event) = self.merger.next() {
+                match event.event_type {
+                    EventType::ModelEvent(()) if event.time == self.panic_at => panic!("boom"),
+                    EventType::ModelEvent(()) => self.merger.send(
+                        0,
+                        Event {
+                            time: event.time + 1,
+                            src: 7,
+                            event_type: EventType::ModelEvent(()),
+                        },
+                    ),
+                    EventType::Stalled => return ActorState::Continue(event.time),
+                    _ => unreachable!(),
+                }
+            }
+
+            ActorState::Done(())
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+
+        fn last_delivered(&self) -> Option<Delivered<u64>> {
+            self.merger.last_delivered()
+        }
+    }
+
+    #[test]
+    fn test_panic() {
+        let actors = || {
+            let (tx, rx) = crate::spsc::new(16);
+            tx.push(Event {
+                time: 1,
+                src: 7,
+                event_type: EventType::ModelEvent(()),
+            })
+            .unwrap();
+            let ticker = Ticker {
+                merger: crate::Merger::new(vec![rx], 7, vec![7]).output(tx, 1),
+                panic_at: 50,
+            };
+
+            // the others would never finish
+            let mut actors: Vec<Box<dyn Advancer<u64, ()> + Send>> = (0..4)
+                .map(|id| Box::new(DummyAdvance::new(id, u64::MAX)) as Box<_>)
+                .collect();
+            actors.insert(2, Box::new(ticker));
+            actors
+        };
+
+        let partition = crate::Partition::round_robin(5, 2);
+        for panicked in [
+            crate::start_with(Scheduler::Fifo, 2, actors(), None),
+            crate::start_with(Scheduler::Priority, 2, actors(), None),
+            crate::start_windowed(&partition, actors(), None),
+        ] {
+            let panicked = panicked.unwrap_err();
+            assert_eq!(panicked.ix, 2);
+            assert_eq!(panicked.id, Some(7));
+            assert_eq!(panicked.time, "50");
+            assert_eq!(
+                panicked.last_event.as_deref(),
+                Some("ModelEvent at 50 from #7")
+            );
+            assert_eq!(panicked.message, "boom");
+        }
+    }
+
+    /// Waits for its input to close
+    #[derive(Debug)]
+    struct Waiting {
+        merger: crate::Merger<u64, ()>,
+    }
+
+    impl Advancer<u64, ()> for Waiting {
+        fn advance(&mut self) -> ActorState<u64, ()> {
+            match self
+                .merger
+                .next()
+                .map(|event| (event.time, event.event_type))
+            {
+                Some((time, EventType::Stalled)) => ActorState::Continue(time),
+                Some((_, EventType::Close)) | None => ActorState::Done(()),
+                _ => unreachable!(),
+            }
+        }
+
+        fn driven_by(&mut self, driver: Driver) {
+            self.merger.driven_by(driver);
+        }
+    }
+
+    #[test]
+    fn test_parks_when_stuck() {
+        for &scheduler in &[Scheduler::Fifo, Scheduler::Priority] {
+            // closed from outside the workers, like a network bridge would
+            let (tx, rx) = crate::spsc::new(16);
+            let closer = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                tx.push(Event {
+                    time: 1,
+                    src: 8,
+                    event_type: EventType::Close,
+                })
+                .unwrap();
+            });
+            let waiting = Waiting {
+                merger: crate::Merger::new(vec![rx], 7, vec![8]),
+            };
+
+            let actors: Vec<Box<dyn Advancer<u64, ()> + Send>> = vec![Box::new(waiting)];
+            let (_, report) = start_with(scheduler, 2, actors, None).unwrap();
+            closer.join().unwrap();
+
+            // spinning would have advanced it all along
+            assert!(report.actors[0].advances < 1_000);
+        }
+    }
+
+    #[test]
+    fn test_matches_priority() {
+        let (mut priority, _) = start_with(Scheduler::Priority, 3, ring(7, 1_000), None).unwrap();
+        let mut sequential = start_sequential(ring(7, 1_000), None);
+
+        priority.sort_unstable();
+        sequential.sort_unstable();
+        assert_eq!(sequential, priority);
+    }
+
     /*
         #[test]
         fn test_single_thread() {